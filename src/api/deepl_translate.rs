@@ -3,9 +3,17 @@
 use std::{collections::HashMap, env};
 
 use html_escape::decode_html_entities;
-use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
-use ureq::http::StatusCode;
+
+use crate::{
+    api::{ProviderUsage, http::HttpClient, reassemble},
+    config::{Config, DeepLPlan},
+    error::{ProviderError, TranslationError},
+    utils::logging::{debug, info, warn},
+};
+
+const FREE_API_URL: &str = "https://api-free.deepl.com/v2/translate";
+const PRO_API_URL: &str = "https://api.deepl.com/v2/translate";
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct TranslatedResponse {
@@ -16,6 +24,8 @@ struct TranslatedResponse {
 struct TranslationResponse {
     pub detected_source_language: String,
     pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub billed_characters: Option<usize>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -57,150 +67,206 @@ struct TranslationRequestBody {
     pub ignore_tags: Option<Vec<String>>,
 }
 
+/// Request body with the options set in the config
+fn request_body(
+    text: Vec<String>,
+    source_lang: &str,
+    target_lang: &str,
+    context: Option<&str>,
+    config: &Config,
+) -> TranslationRequestBody {
+    let tag_options = &config.deepl_tag_options;
+
+    TranslationRequestBody {
+        text,
+        target_lang: target_lang.to_string(),
+        source_lang: source_lang.to_string(),
+        context: context.map(String::from),
+        glossary_id: config.deepl_glossary(source_lang, target_lang).cloned(),
+        show_billed_characters: config.track_billing.then_some(true),
+        model_type: config.deepl_model_type.clone(),
+        custom_instructions: (!config.deepl_instructions.is_empty())
+            .then(|| config.deepl_instructions.clone()),
+        tag_handling: tag_options.tag_handling.clone(),
+        tag_handling_version: tag_options.tag_handling_version.clone(),
+        outline_detection: tag_options.outline_detection,
+        splitting_tags: tag_options.splitting_tags.clone(),
+        non_splitting_tags: tag_options.non_splitting_tags.clone(),
+        ignore_tags: tag_options.ignore_tags.clone(),
+        ..Default::default()
+    }
+}
+
 ///Translate using v2 api
 ///
+/// The context applies to every value, it is ignored by deeplx
 pub fn translate_v2(
-    source_data: &Vec<&str>,
+    chunks: &[&[&str]],
     source_lang: &str,
     target_lang: &str,
-) -> Result<Vec<String>, String> {
-    let mut translated: Vec<String> = Vec::with_capacity(source_data.len());
-
-    let (api_key, api_url) = get_key_url();
-
-    let mut duplicates = 0;
+    context: Option<&str>,
+    config: &Config,
+    usage: &mut ProviderUsage,
+    client: &dyn HttpClient,
+) -> Result<Vec<String>, TranslationError> {
+    let (api_key, api_url) = get_key_url(config.deepl_plan)?;
 
     if let Some(key) = api_key
         && !key.is_empty()
     {
-        let mut mem_cache: HashMap<&str, Vec<usize>> = HashMap::new();
+        translate_api(
+            &key,
+            &api_url,
+            chunks,
+            source_lang,
+            target_lang,
+            context,
+            config,
+            usage,
+            client,
+        )
+    } else {
+        warn!(
+            "DeepL API key not found. Set it using DEEPL_FREE_API_KEY or DEEPL_PRO_API_KEY variable"
+        );
+        info!("Using deeplx local...");
+        if context.is_some() {
+            info!(provider = "deeplx"; "Context is not supported by deeplx, ignored");
+        }
 
-        let chunks: Vec<&[&str]> = source_data.chunks(120).collect();
+        translate_deeplx(
+            &config.deeplx_endpoint,
+            chunks,
+            source_lang,
+            target_lang,
+            client,
+        )
+    }
+}
 
-        for chunk in chunks {
-            let mut qry_text: Vec<String> = Vec::new();
+///Translate using the cloud api
+#[allow(clippy::too_many_arguments)]
+fn translate_api(
+    key: &str,
+    api_url: &str,
+    chunks: &[&[&str]],
+    source_lang: &str,
+    target_lang: &str,
+    context: Option<&str>,
+    config: &Config,
+    usage: &mut ProviderUsage,
+    client: &dyn HttpClient,
+) -> Result<Vec<String>, TranslationError> {
+    let mut translated: Vec<String> =
+        Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
 
-            for (idx, q) in chunk.iter().enumerate() {
-                //if item in cache then record the position in the chunk array
-                // send empty character for translation
-                //You will be charged for only one character reducing usage
-                if let Some(mem_val) = mem_cache.get_mut(q) {
-                    mem_val.push(idx);
-                    qry_text.push("".to_string());
+    let mut duplicates = 0;
 
-                    duplicates += 1;
-                } else {
-                    mem_cache.insert(*q, vec![idx]);
-                    qry_text.push(q.to_string());
-                }
+    let mut mem_cache: HashMap<&str, Vec<usize>> = HashMap::new();
+
+    for chunk in chunks {
+        let mut qry_text: Vec<String> = Vec::new();
+
+        for (idx, q) in chunk.iter().enumerate() {
+            //if item in cache then record the position in the chunk array
+            // send empty character for translation
+            //You will be charged for only one character reducing usage
+            if let Some(mem_val) = mem_cache.get_mut(q) {
+                mem_val.push(idx);
+                qry_text.push("".to_string());
+
+                duplicates += 1;
+            } else {
+                mem_cache.insert(*q, vec![idx]);
+                qry_text.push(q.to_string());
             }
+        }
 
-            let json_body = TranslationRequestBody {
-                text: qry_text,
-                target_lang: target_lang.to_string(),
-                source_lang: source_lang.to_string(),
-                ..Default::default()
-            };
-
-            let response = ureq::post(&api_url)
-                .config()
-                .http_status_as_error(false)
-                .build()
-                .header("Authorization", &key)
-                .content_type("application/json")
-                .send_json(json_body);
-
-            match response {
-                Ok(mut translated_res) => {
-                    match translated_res.status() {
-                        StatusCode::OK => {
-                            let data_res =
-                                translated_res.body_mut().read_json::<TranslatedResponse>();
-                            match data_res {
-                                Ok(data) => {
-                                    let g_translated_data = &data.translations;
-
-                                    for (idx, translation_res) in
-                                        data.translations.iter().enumerate()
-                                    {
-                                        let decoded_str =
-                                            decode_html_entities(&translation_res.text);
-                                        let decoded = decoded_str.trim();
-
-                                        //replace the empty value with one in pos
-                                        if decoded.is_empty() {
-                                            for mem_val in mem_cache.values() {
-                                                let pos = mem_val.iter().position(|x| x == &idx);
-
-                                                if let Some(pos) = pos {
-                                                    //We only want to use not 0 pos as it is the finder of the  translated value
-                                                    if pos > 0 {
-                                                        let init_pos = mem_val[0];
-                                                        let translated_value =
-                                                            g_translated_data.get(init_pos);
-                                                        if let Some(translation) = translated_value
-                                                        {
-                                                            let init_pos_decoded =
-                                                                decode_html_entities(
-                                                                    &translation.text,
-                                                                );
-                                                            translated
-                                                                .push(init_pos_decoded.to_string());
-                                                            break;
-                                                        } else {
-                                                            translated.push(decoded.to_string());
-                                                            break;
-                                                        }
-                                                    } else {
-                                                        translated.push(decoded.to_string());
-                                                        break;
-                                                    }
-                                                }
-                                            }
-                                        } else {
-                                            translated.push(decoded.to_string());
-                                        }
-                                    }
-                                }
-                                Err(err) => {
-                                    return Err(err.to_string());
+        let json_body = request_body(qry_text, source_lang, target_lang, context, config);
+
+        let json_body = serde_json::to_value(json_body).map_err(|e| e.to_string())?;
+        let response = client.post_json(api_url, &[("Authorization", key)], &json_body);
+
+        match response {
+            Ok(translated_res) => {
+                if translated_res.is_ok() {
+                    let data_res = serde_json::from_str::<TranslatedResponse>(&translated_res.body);
+                    match data_res {
+                        Ok(data) => {
+                            for translation_res in data.translations.iter() {
+                                if let Some(billed) = translation_res.billed_characters {
+                                    *usage.billed_characters.get_or_insert(0) += billed;
                                 }
                             }
+
+                            let responses: Vec<String> =
+                                data.translations.into_iter().map(|t| t.text).collect();
+
+                            translated.extend(reassemble(&responses, &mem_cache)?);
                         }
-                        _ => {
-                            return Err(translated_res
-                                .body_mut()
-                                .read_to_string()
-                                .unwrap_or_default());
+                        Err(err) => {
+                            return Err(err.to_string().into());
                         }
                     }
-                }
-                Err(e) => {
-                    return Err(e.to_string());
+                } else {
+                    return Err(
+                        ProviderError::deepl(translated_res.status, &translated_res.body).into(),
+                    );
                 }
             }
-
-            mem_cache.clear();
+            Err(e) => {
+                return Err(e.to_string().into());
+            }
         }
 
-        debug!("Duplicates found: {duplicates}");
+        mem_cache.clear();
+    }
 
-        Ok(translated)
-    } else {
-        warn!(
-            "DeepL API key not found. Set it using DEEPL_FREE_API_KEY or DEEPL_PRO_API_KEY variable"
-        );
-        info!("Using deeplx local...");
+    debug!(provider = "deepl", duplicates; "Duplicates found: {duplicates}");
 
-        let mut mem_cache: HashMap<&str, String> = HashMap::new();
+    Ok(translated)
+}
 
-        for romanize in source_data.iter() {
+///Translate using a local deeplx
+///
+/// Chunks are sent in a single batch request when the deeplx build supports the cloud api body,
+/// otherwise one request is sent per unique value. A failed request is an error, it does not
+/// switch to one value at a time
+fn translate_deeplx(
+    endpoint: &str,
+    chunks: &[&[&str]],
+    source_lang: &str,
+    target_lang: &str,
+    client: &dyn HttpClient,
+) -> Result<Vec<String>, TranslationError> {
+    let mut translated: Vec<String> =
+        Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
+
+    let mut duplicates = 0;
+    let mut batch_supported = true;
+
+    let mut mem_cache: HashMap<&str, String> = HashMap::new();
+
+    for chunk in chunks {
+        if batch_supported {
+            if let Some(values) =
+                deeplx_translate_batch(endpoint, chunk, source_lang, target_lang, client)?
+            {
+                translated.extend(values);
+                continue;
+            }
+
+            info!("DeepLX does not support batch requests, translating one value at a time...");
+            batch_supported = false;
+        }
+
+        for romanize in chunk.iter() {
             if let Some(mem_val) = mem_cache.get(romanize) {
                 translated.push(mem_val.to_owned());
                 duplicates += 1;
             } else {
                 //if not in mem cache
-                match deeplx_translate(source_lang, target_lang, *romanize) {
+                match deeplx_translate(endpoint, source_lang, target_lang, romanize, client) {
                     Ok(result) => {
                         translated.push(result.clone());
                         mem_cache.insert(*romanize, result);
@@ -209,94 +275,394 @@ pub fn translate_v2(
                 }
             }
         }
+    }
+
+    debug!(provider = "deepl", duplicates; "Duplicates: {duplicates}");
+    Ok(translated)
+}
 
-        debug!("Duplicates: {duplicates}");
-        Ok(translated)
+///Translate a chunk with the cloud api body
+///
+/// `None` when the response shows the batch is not supported: the classic deeplx error body, or
+/// a success without the cloud api shape. Transport and http errors are returned
+fn deeplx_translate_batch(
+    endpoint: &str,
+    chunk: &[&str],
+    source_lang: &str,
+    target_lang: &str,
+    client: &dyn HttpClient,
+) -> Result<Option<Vec<String>>, TranslationError> {
+    let mut mem_cache: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut qry_text: Vec<String> = Vec::with_capacity(chunk.len());
+
+    for (idx, q) in chunk.iter().enumerate() {
+        if let Some(mem_val) = mem_cache.get_mut(q) {
+            mem_val.push(idx);
+            qry_text.push("".to_string());
+        } else {
+            mem_cache.insert(*q, vec![idx]);
+            qry_text.push(q.to_string());
+        }
     }
+
+    let json = TranslationRequestBody {
+        text: qry_text,
+        target_lang: target_lang.to_string(),
+        source_lang: source_lang.to_string(),
+        ..Default::default()
+    };
+    let json = serde_json::to_value(json).map_err(|e| e.to_string())?;
+
+    let response = client.post_json(endpoint, &[], &json)?;
+    if !response.is_ok() {
+        //the classic endpoint rejects the list of texts with its own error body
+        let unsupported = response.status == 400
+            && serde_json::from_str::<DeepLXErrorResponse>(&response.body).is_ok();
+        return if unsupported {
+            Ok(None)
+        } else {
+            Err(ProviderError::deepl(response.status, &response.body).into())
+        };
+    }
+
+    let Ok(data) = serde_json::from_str::<TranslatedResponse>(&response.body) else {
+        return Ok(None);
+    };
+
+    let responses: Vec<String> = data.translations.into_iter().map(|t| t.text).collect();
+
+    Ok(Some(reassemble(&responses, &mem_cache)?))
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct DeepLXTranslationResponse {
-    pub detected_source_language: String,
+struct DeepLXRequestBody {
     pub text: String,
+    pub source_lang: String,
+    pub target_lang: String,
 }
 
-// TODO: This is not tested... Built by referring to the api online.
-fn deeplx_translate(source_lang: &str, target_lang: &str, q: &str) -> Result<String, String> {
-    let json = TranslationRequestBody {
-        text: [q.to_string()].to_vec(),
-        target_lang: target_lang.to_string(),
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DeepLXTranslationResponse {
+    pub code: u16,
+    pub data: String,
+}
+
+/// Error body of the classic deeplx endpoint, the cloud api one has no code
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DeepLXErrorResponse {
+    pub code: u16,
+}
+
+fn deeplx_translate(
+    endpoint: &str,
+    source_lang: &str,
+    target_lang: &str,
+    q: &str,
+    client: &dyn HttpClient,
+) -> Result<String, TranslationError> {
+    let json = DeepLXRequestBody {
+        text: q.to_string(),
         source_lang: source_lang.to_string(),
-        ..Default::default()
+        target_lang: target_lang.to_string(),
     };
 
-    let web_url = "http://127.0.0.1:1188/translate";
-    let res = ureq::post(web_url).send_json(json);
+    let json = serde_json::to_value(json).map_err(|e| e.to_string())?;
+    let res = client.post_json(endpoint, &[], &json);
 
     match res {
-        Ok(mut response) => {
-            if response.status() == StatusCode::OK {
-                let json_res = response.body_mut().read_json::<DeepLXTranslationResponse>();
+        Ok(response) => {
+            if response.is_ok() {
+                let json_res = serde_json::from_str::<DeepLXTranslationResponse>(&response.body);
 
                 match json_res {
-                    Ok(translation) => {
-                        let decoded = decode_html_entities(&translation.text);
+                    Ok(translation) if translation.code == 200 => {
+                        let decoded = decode_html_entities(&translation.data);
 
                         Ok(decoded.to_string())
                     }
-                    Err(e) => Err(e.to_string()),
+                    Ok(translation) => {
+                        Err(format!("DeepLX error code {}", translation.code).into())
+                    }
+                    Err(e) => Err(e.to_string().into()),
                 }
             } else {
-                Err("Invalid request".to_string())
+                Err(ProviderError::deepl(response.status, &response.body).into())
             }
         }
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(e.into()),
     }
 }
 
-fn get_key_url() -> (Option<String>, String) {
+fn get_key_url(plan: DeepLPlan) -> Result<(Option<String>, String), String> {
     let free_api_key = env::var("DEEPL_FREE_API_KEY").ok();
     let pro_api_key = env::var("DEEPL_PRO_API_KEY").ok();
 
-    let free_api_url = "https://api-free.deepl.com/v2/translate".to_string();
-    let pro_api_url = "https://api.deepl.com/v2/translate".to_string();
-    let web_url = "".to_string();
+    key_url(plan, free_api_key, pro_api_key)
+}
 
-    match (free_api_key, pro_api_key) {
-        (None, None) => (None, web_url),
-        (None, Some(pro_key)) => {
-            if pro_key.is_empty() {
-                (None, web_url)
-            } else {
-                (Some(format!("DeepL-Auth-Key {pro_key}")), pro_api_url)
-            }
-        }
-        (Some(free_key), None) => {
-            if free_key.is_empty() {
-                (None, web_url)
-            } else {
-                (Some(format!("DeepL-Auth-Key {free_key}")), free_api_url)
-            }
-        }
-        (Some(free_key), Some(pro_key)) => match (!free_key.is_empty(), !pro_key.is_empty()) {
-            (true, true) => (Some(format!("DeepL-Auth-Key {free_key}")), free_api_url),
-            (true, false) => (Some(format!("DeepL-Auth-Key {free_key}")), free_api_url),
-            (false, true) => (Some(format!("DeepL-Auth-Key {pro_key}")), pro_api_url),
-            (false, false) => (None, web_url),
-        },
+/// Auth header and url of the plan, no key and an empty url for deeplx
+fn key_url(
+    plan: DeepLPlan,
+    free_api_key: Option<String>,
+    pro_api_key: Option<String>,
+) -> Result<(Option<String>, String), String> {
+    let free_api_key = free_api_key.filter(|key| !key.is_empty());
+    let pro_api_key = pro_api_key.filter(|key| !key.is_empty());
+
+    let free = |key| {
+        (
+            Some(format!("DeepL-Auth-Key {key}")),
+            FREE_API_URL.to_string(),
+        )
+    };
+    let pro = |key| {
+        (
+            Some(format!("DeepL-Auth-Key {key}")),
+            PRO_API_URL.to_string(),
+        )
+    };
+
+    match plan {
+        //the free key wins when both are set
+        DeepLPlan::Auto => Ok(match (free_api_key, pro_api_key) {
+            (Some(free_key), _) => free(free_key),
+            (None, Some(pro_key)) => pro(pro_key),
+            (None, None) => (None, String::new()),
+        }),
+        DeepLPlan::Free => free_api_key
+            .map(free)
+            .ok_or_else(|| "DEEPL_FREE_API_KEY is required by the Free DeepL plan".to_string()),
+        DeepLPlan::Pro => pro_api_key
+            .map(pro)
+            .ok_or_else(|| "DEEPL_PRO_API_KEY is required by the Pro DeepL plan".to_string()),
     }
 }
 
 #[test]
 fn test_translate_v2() {
-    let source_values = vec!["hello", "mello", "cat", "god", "hello", "feline", "cat"];
-    let translated_values: Vec<String> = vec![
+    use crate::api::http::FixtureClient;
+
+    let source_values = ["hello", "mello", "cat", "god", "hello", "feline", "cat"];
+    let translated_values: Vec<String> = [
         "Bonjour", "bonjour", "chat", "Dieu", "Bonjour", "félin", "chat",
     ]
     .iter()
     .map(|v| v.to_string())
     .collect();
-    let translated = translate_v2(&source_values, "en", "fr");
+    let client = FixtureClient::new("deepl_v2");
+    let translated = translate_api(
+        "DeepL-Auth-Key test-key",
+        "https://api-free.deepl.com/v2/translate",
+        &[&source_values],
+        "EN",
+        "FR",
+        None,
+        &Config::new(),
+        &mut ProviderUsage::default(),
+        &client,
+    );
 
     assert_eq!(translated, Ok(translated_values));
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_v2_context() {
+    use crate::api::http::FixtureClient;
+
+    let client = FixtureClient::new("deepl_v2_context");
+    let translated = translate_api(
+        "DeepL-Auth-Key test-key",
+        "https://api-free.deepl.com/v2/translate",
+        &[&["Open"]],
+        "EN",
+        "FR",
+        Some("Menu item opening a file"),
+        &Config::new(),
+        &mut ProviderUsage::default(),
+        &client,
+    );
+
+    assert_eq!(translated, Ok(vec!["Ouvrir".to_string()]));
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_v2_error() {
+    use crate::api::http::FixtureClient;
+
+    let client = FixtureClient::new("deepl_v2_error");
+    let translated = translate_api(
+        "DeepL-Auth-Key test-key",
+        "https://api-free.deepl.com/v2/translate",
+        &[&["hello"]],
+        "EN",
+        "FR",
+        None,
+        &Config::new(),
+        &mut ProviderUsage::default(),
+        &client,
+    );
+
+    assert_eq!(
+        translated,
+        Err(TranslationError::Provider(ProviderError {
+            code: 456,
+            message: "Quota Exceeded".to_string(),
+            status: None,
+        }))
+    );
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_deeplx() {
+    use crate::api::http::FixtureClient;
+
+    let source_values = ["hello", "cat", "hello"];
+    let client = FixtureClient::new("deeplx");
+    let translated = translate_deeplx(
+        "http://deeplx.local:8080/translate",
+        &[&source_values],
+        "EN",
+        "FR",
+        &client,
+    );
+
+    assert_eq!(
+        translated,
+        Ok(vec![
+            "Bonjour".to_string(),
+            "chat".to_string(),
+            "Bonjour".to_string()
+        ])
+    );
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_deeplx_batch() {
+    use crate::api::http::FixtureClient;
+
+    let source_values = ["hello", "cat", "hello"];
+    let client = FixtureClient::new("deeplx_batch");
+    let translated = translate_deeplx(
+        "http://deeplx.local:8080/v2/translate",
+        &[&source_values, &source_values],
+        "EN",
+        "FR",
+        &client,
+    );
+
+    assert_eq!(
+        translated,
+        Ok(["Bonjour", "chat", "Bonjour", "Bonjour", "chat", "Bonjour"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect())
+    );
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_deeplx_batch_error() {
+    use crate::api::http::FixtureClient;
+
+    let client = FixtureClient::new("deeplx_batch_error");
+    let translated = translate_deeplx(
+        "http://deeplx.local:8080/v2/translate",
+        &[&["hello", "cat"]],
+        "EN",
+        "FR",
+        &client,
+    );
+
+    //a rate limit is left to the retry policy, the values are not sent one at a time
+    assert!(matches!(
+        translated,
+        Err(TranslationError::Provider(ProviderError { code: 429, .. }))
+    ));
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_request_body_tag_options() {
+    use crate::config::TagOptions;
+
+    let config = Config::new()
+        .deepl_tag_options(TagOptions {
+            tag_handling: Some("xml".to_string()),
+            ignore_tags: Some(vec!["code".to_string()]),
+            ..Default::default()
+        })
+        .build();
+
+    let body = request_body(
+        vec!["Run <code>cargo</code>".to_string()],
+        "EN",
+        "FR",
+        Some("Command line tutorial"),
+        &config,
+    );
+
+    assert_eq!(
+        serde_json::to_value(body).unwrap(),
+        serde_json::json!({
+            "text": ["Run <code>cargo</code>"],
+            "target_lang": "FR",
+            "source_lang": "EN",
+            "context": "Command line tutorial",
+            "tag_handling": "xml",
+            "ignore_tags": ["code"],
+        })
+    );
+}
+
+#[test]
+fn test_request_body_model_type() {
+    let mut builder = Config::new();
+    builder.deepl_instructions(vec!["Keep a friendly tone"]);
+
+    let instructions_only = request_body(vec![], "EN", "FR", None, &builder.build());
+    let with_model = request_body(
+        vec![],
+        "EN",
+        "FR",
+        None,
+        &builder.deepl_model_type("quality_optimized").build(),
+    );
+
+    assert_eq!(instructions_only.model_type, None);
+    assert_eq!(
+        instructions_only.custom_instructions,
+        Some(vec!["Keep a friendly tone".to_string()])
+    );
+    assert_eq!(with_model.model_type.as_deref(), Some("quality_optimized"));
+}
+
+#[test]
+fn test_key_url_plan() {
+    let free = Some("free-key".to_string());
+    let pro = Some("pro-key".to_string());
+
+    assert_eq!(
+        key_url(DeepLPlan::Auto, free.clone(), pro.clone()),
+        Ok((
+            Some("DeepL-Auth-Key free-key".to_string()),
+            FREE_API_URL.to_string()
+        ))
+    );
+    assert_eq!(
+        key_url(DeepLPlan::Pro, free.clone(), pro.clone()),
+        Ok((
+            Some("DeepL-Auth-Key pro-key".to_string()),
+            PRO_API_URL.to_string()
+        ))
+    );
+    assert_eq!(
+        key_url(DeepLPlan::Auto, Some(String::new()), None),
+        Ok((None, String::new()))
+    );
+    assert!(key_url(DeepLPlan::Pro, free, Some(String::new())).is_err());
+    assert!(key_url(DeepLPlan::Free, None, pro).is_err());
 }
@@ -0,0 +1,110 @@
+//Offline dictionary
+//Exact lookups of the source values, unknown values are returned unchanged.
+//Not meant for real translations, lets the whole pipeline run without network.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+#[cfg(test)]
+use crate::utils::TempDir;
+use crate::{api::ProviderUsage, utils::logging::debug};
+
+/// target locale -> source value -> translation
+type Dictionary = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Common UI words, english source only
+const EMBEDDED_DICTIONARY: &str = include_str!("dictionary.json");
+
+///Translate using the embedded dictionary or the `dictionary_file`
+pub fn translate(
+    chunks: &[&[&str]],
+    source_lang: &str,
+    target_lang: &str,
+    dictionary_file: Option<&Path>,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, String> {
+    let dictionary: Dictionary = match dictionary_file {
+        Some(path) => {
+            let content = fs::read_to_string(path)
+                .map_err(|e| format!("Could not read dictionary {}: {e}", path.display()))?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid dictionary {}: {e}", path.display()))?
+        }
+        None if source_lang == "en" => {
+            serde_json::from_str(EMBEDDED_DICTIONARY).map_err(|e| e.to_string())?
+        }
+        None => Dictionary::new(),
+    };
+
+    //fr-CA falls back to fr
+    let primary_lang = target_lang.split(['-', '_']).next().unwrap_or(target_lang);
+    let entries = dictionary
+        .get(target_lang)
+        .or_else(|| dictionary.get(primary_lang));
+
+    let mut untranslated = 0;
+
+    let translated = chunks
+        .iter()
+        .flat_map(|chunk| chunk.iter())
+        .map(
+            |value| match entries.and_then(|entries| lookup(entries, value)) {
+                Some(translation) => translation,
+                None => {
+                    untranslated += 1;
+                    value.to_string()
+                }
+            },
+        )
+        .collect();
+
+    debug!(provider = "dictionary", untranslated; "Values not in the dictionary: {untranslated}");
+    usage.untranslated += untranslated;
+
+    Ok(translated)
+}
+
+/// Exact match, or the lowercase value with the translation lowercased as well
+fn lookup(entries: &BTreeMap<String, String>, value: &str) -> Option<String> {
+    if let Some(translation) = entries.get(value) {
+        return Some(translation.to_string());
+    }
+
+    entries
+        .iter()
+        .find(|(source, _)| source.to_lowercase() == value && source.as_str() != value)
+        .map(|(_, translation)| translation.to_lowercase())
+}
+
+#[test]
+fn test_translate_embedded() {
+    let source_values = ["Save", "Not a known word", "save"];
+    let mut usage = ProviderUsage::default();
+
+    let translated = translate(&[&source_values], "en", "fr-CA", None, &mut usage);
+
+    assert_eq!(
+        translated,
+        Ok(vec![
+            "Enregistrer".to_string(),
+            "Not a known word".to_string(),
+            "enregistrer".to_string()
+        ])
+    );
+    assert_eq!(usage.untranslated, 1);
+}
+
+#[test]
+fn test_translate_dictionary_file() {
+    let dir = TempDir::new("dictionary_test");
+    let path = &dir.join("dictionary_test.json");
+    fs::write(path, r#"{"fr": {"Hello": "Salut"}}"#).unwrap();
+
+    let mut usage = ProviderUsage::default();
+    let translated = translate(&[&["Hello", "Save"]], "en", "fr", Some(path), &mut usage);
+
+    assert_eq!(
+        translated,
+        Ok(vec!["Salut".to_string(), "Save".to_string()])
+    );
+    assert_eq!(usage.untranslated, 1);
+}
@@ -1,12 +1,19 @@
 //api_version_v2
 
-use std::{collections::HashMap, env};
+use std::{collections::HashMap, env, thread, time::Duration};
 
 use html_escape::decode_html_entities;
-use log::{debug, info, warn};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use ureq::http::StatusCode;
+
+use crate::{
+    api::{
+        http::{HttpClient, HttpResponse},
+        reassemble,
+    },
+    error::{ProviderError, TranslationError},
+    utils::logging::{debug, info, warn},
+};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -27,204 +34,281 @@ struct Translation {
 }
 
 ///Translate using v2 api
-/// max string that can be taken by the q param is 128
 ///
 pub fn translate_v2(
-    source_data: &Vec<&str>,
+    chunks: &[&[&str]],
     source_lang: &str,
     target_lang: &str,
-) -> Result<Vec<String>, String> {
-    let mut translated: Vec<String> = Vec::with_capacity(source_data.len());
-    let api_url = "https://translation.googleapis.com/language/translate/v2";
-
+    client: &dyn HttpClient,
+) -> Result<Vec<String>, TranslationError> {
     let api_key = env::var("GOOGLE_API_KEY").ok();
-    let mut duplicates = 0;
 
     if let Some(key) = api_key
         && !key.is_empty()
     {
-        let mut mem_cache: HashMap<&str, Vec<usize>> = HashMap::new();
+        translate_api(&key, chunks, source_lang, target_lang, client)
+    } else {
+        warn!("Google API key not found. Set it using GOOGLE_API_KEY variable");
+        info!("Using google translate web...");
 
-        let chunks: Vec<&[&str]> = source_data.chunks(120).collect();
+        translate_web(chunks, source_lang, target_lang, client).map_err(Into::into)
+    }
+}
 
-        for chunk in chunks {
-            let mut qry_pairs: Vec<(&str, &str)> = Vec::new();
+///Translate using the cloud translation api
+fn translate_api(
+    key: &str,
+    chunks: &[&[&str]],
+    source_lang: &str,
+    target_lang: &str,
+    client: &dyn HttpClient,
+) -> Result<Vec<String>, TranslationError> {
+    let mut translated: Vec<String> =
+        Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
+    let api_url = "https://translation.googleapis.com/language/translate/v2";
 
-            for (idx, q) in chunk.iter().enumerate() {
-                //if item in cache then record the position in the chunk array
-                // send empty character for translation
-                //You will be charged for only one character reducing usage
-                if let Some(mem_val) = mem_cache.get_mut(q) {
-                    mem_val.push(idx);
-                    qry_pairs.push(("q", ""));
+    let mut duplicates = 0;
 
-                    duplicates += 1;
-                } else {
-                    mem_cache.insert(*q, vec![idx]);
-                    qry_pairs.push(("q", *q));
-                }
+    let mut mem_cache: HashMap<&str, Vec<usize>> = HashMap::new();
+
+    for chunk in chunks {
+        let mut qry_pairs: Vec<(&str, &str)> = Vec::new();
+
+        for (idx, q) in chunk.iter().enumerate() {
+            //if item in cache then record the position in the chunk array
+            // send empty character for translation
+            //You will be charged for only one character reducing usage
+            if let Some(mem_val) = mem_cache.get_mut(q) {
+                mem_val.push(idx);
+                qry_pairs.push(("q", ""));
+
+                duplicates += 1;
+            } else {
+                mem_cache.insert(*q, vec![idx]);
+                qry_pairs.push(("q", *q));
             }
+        }
 
-            let response = ureq::get(api_url)
-                .config()
-                .http_status_as_error(false)
-                .build()
-                .query("key", &key)
-                .query("source", source_lang)
-                .query("target", target_lang)
-                .query_pairs(qry_pairs)
-                .call();
-
-            match response {
-                Ok(mut translated_res) => {
-                    match translated_res.status() {
-                        StatusCode::OK => {
-                            let data_res =
-                                translated_res.body_mut().read_json::<TranslatedResponse>();
-
-                            match data_res {
-                                Ok(data) => {
-                                    let g_translated_data = &data.data.translations;
-
-                                    for (idx, translated_text) in
-                                        data.data.translations.iter().enumerate()
-                                    {
-                                        let decoded_str =
-                                            decode_html_entities(&translated_text.translated_text);
-
-                                        let decoded = decoded_str.trim();
-
-                                        //replace the empty value with one in pos
-                                        if decoded.is_empty() {
-                                            for mem_val in mem_cache.values() {
-                                                let pos = mem_val.iter().position(|x| x == &idx);
-
-                                                if let Some(pos) = pos {
-                                                    //We only want to use not 0 pos as it is the finder of the  translated value
-                                                    if pos > 0 {
-                                                        let init_pos = mem_val[0];
-                                                        let translated_value =
-                                                            g_translated_data.get(init_pos);
-                                                        if let Some(translation) = translated_value
-                                                        {
-                                                            let init_pos_decoded =
-                                                                decode_html_entities(
-                                                                    &translation.translated_text,
-                                                                );
-                                                            translated
-                                                                .push(init_pos_decoded.to_string());
-                                                            break;
-                                                        } else {
-                                                            translated.push(decoded.to_string());
-                                                            break;
-                                                        }
-                                                    } else {
-                                                        translated.push(decoded.to_string());
-                                                        break;
-                                                    }
-                                                }
-                                            }
-                                        } else {
-                                            translated.push(decoded.to_string());
-                                        }
-                                    }
-                                }
-                                Err(e) => return Err(e.to_string()),
-                            }
-                        }
-                        _ => {
-                            return Err(translated_res
-                                .body_mut()
-                                .read_to_string()
-                                .unwrap_or_default());
+        let mut query = vec![
+            ("key", key),
+            ("source", source_lang),
+            ("target", target_lang),
+        ];
+        query.extend(qry_pairs);
+
+        let response = client.get(api_url, &[], &query);
+
+        match response {
+            Ok(translated_res) => {
+                if translated_res.is_ok() {
+                    let data_res = serde_json::from_str::<TranslatedResponse>(&translated_res.body);
+
+                    match data_res {
+                        Ok(data) => {
+                            let responses: Vec<String> = data
+                                .data
+                                .translations
+                                .into_iter()
+                                .map(|t| t.translated_text)
+                                .collect();
+
+                            translated.extend(reassemble(&responses, &mem_cache)?);
                         }
+                        Err(e) => return Err(e.to_string().into()),
                     }
+                } else {
+                    return Err(
+                        ProviderError::google(translated_res.status, &translated_res.body).into(),
+                    );
                 }
-                Err(e) => return Err(e.to_string()),
             }
-
-            mem_cache.clear();
+            Err(e) => return Err(e.to_string().into()),
         }
 
-        debug!("Duplicates found: {duplicates}");
+        mem_cache.clear();
+    }
 
-        Ok(translated)
-    } else {
-        warn!("Google API key not found. Set it using GOOGLE_API_KEY variable");
-        info!("Using google translate web...");
+    debug!(provider = "google", duplicates; "Duplicates found: {duplicates}");
 
-        let mut mem_cache: HashMap<&str, String> = HashMap::new();
+    Ok(translated)
+}
 
-        for romanize in source_data.iter() {
-            if let Some(mem_val) = mem_cache.get(romanize) {
-                translated.push(mem_val.to_owned());
-                duplicates += 1;
-            } else {
-                //if not in mem cache
-                match google_web_translate(source_lang, target_lang, *romanize) {
-                    Ok(result) => {
-                        translated.push(result.clone());
-                        mem_cache.insert(*romanize, result);
-                    }
-                    Err(err) => return Err(err.to_string()),
+///Translate using google translate web, one request per unique value
+fn translate_web(
+    chunks: &[&[&str]],
+    source_lang: &str,
+    target_lang: &str,
+    client: &dyn HttpClient,
+) -> Result<Vec<String>, String> {
+    let mut translated: Vec<String> =
+        Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
+
+    let mut duplicates = 0;
+
+    let mut mem_cache: HashMap<&str, String> = HashMap::new();
+
+    for romanize in chunks.iter().flat_map(|chunk| chunk.iter()) {
+        if let Some(mem_val) = mem_cache.get(romanize) {
+            translated.push(mem_val.to_owned());
+            duplicates += 1;
+        } else {
+            //if not in mem cache
+            match google_web_translate(source_lang, target_lang, romanize, client) {
+                Ok(result) => {
+                    translated.push(result.clone());
+                    mem_cache.insert(*romanize, result);
                 }
+                Err(err) => return Err(err),
             }
         }
-
-        debug!("Duplicates: {duplicates}");
-        Ok(translated)
     }
+
+    debug!(provider = "google", duplicates; "Duplicates: {duplicates}");
+    Ok(translated)
 }
 
+/// Attempts before giving up when google serves a CAPTCHA or consent page
+const WEB_ATTEMPTS: usize = 3;
+
+/// Delay before the first retry, doubled on each retry
+const WEB_RETRY_DELAY: Duration = if cfg!(test) {
+    Duration::ZERO
+} else {
+    Duration::from_secs(2)
+};
+
+/// Rotated on each retry
+const USER_AGENTS: [&str; 3] = [
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0 Safari/537.36",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_4) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4 Safari/605.1.15",
+    "Mozilla/5.0 (X11; Linux x86_64; rv:125.0) Gecko/20100101 Firefox/125.0",
+];
+
 fn google_web_translate(
     source_lang: &str,
     target_lang: &str,
     q: &str,
-) -> Result<String, &'static str> {
+    client: &dyn HttpClient,
+) -> Result<String, String> {
     let web_url = "https://translate.google.com/m";
-    let res = ureq::get(web_url)
-        .query("sl", source_lang)
-        .query("tl", target_lang)
-        .query("q", q)
-        .call();
-
-    match res {
-        Ok(mut response) => {
-            if response.status() == StatusCode::OK {
-                let t_text =
-                    get_translated_text(&response.body_mut().read_to_string().unwrap_or_default())?;
+    let mut delay = WEB_RETRY_DELAY;
+
+    for attempt in 0..WEB_ATTEMPTS {
+        let user_agent = USER_AGENTS[attempt % USER_AGENTS.len()];
+        let res = client.get(
+            web_url,
+            &[("User-Agent", user_agent)],
+            &[("sl", source_lang), ("tl", target_lang), ("q", q)],
+        );
+
+        match res {
+            Ok(response) if is_blocked(&response) => {
+                warn!(provider = "google", attempt = attempt + 1; "Google translate web served a CAPTCHA or consent page, retrying in {delay:?}");
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            Ok(response) => {
+                if response.is_ok() {
+                    let t_text = get_translated_text(&response.body)?;
 
-                let decoded = decode_html_entities(&t_text);
+                    let decoded = decode_html_entities(&t_text);
 
-                Ok(decoded.to_string())
-            } else {
-                Err("Invalid request")
+                    return Ok(decoded.to_string());
+                } else {
+                    return Err("Invalid request".to_string());
+                }
             }
+            Err(_) => return Err("Could not query google translate api".to_string()),
         }
-        Err(_) => Err("Could not query google translate api"),
     }
+
+    Err(format!(
+        "Google translate web is blocking requests (CAPTCHA or consent page after {WEB_ATTEMPTS} attempts). Set GOOGLE_API_KEY to use the cloud translation api"
+    ))
+}
+
+/// CAPTCHA (`/sorry/`) or cookie consent page instead of a translation
+fn is_blocked(response: &HttpResponse) -> bool {
+    response.status == 429
+        || response.body.contains("consent.google.com")
+        || response.body.contains("/sorry/")
+        || response.body.contains("g-recaptcha")
 }
 
-fn get_translated_text(html: &str) -> Result<String, &'static str> {
+fn get_translated_text(html: &str) -> Result<String, String> {
     // extracting translation text
     let pattern = Regex::new(r#"(?s)class="(?:t0|result-container)">(.*?)<"#).unwrap();
     if let Some(captures) = pattern.captures(html) {
         Ok(html_escape::decode_html_entities(&captures[1]).to_string())
     } else {
-        Err("Invalid request")
+        Err("Invalid request".to_string())
     }
 }
 
 #[test]
 fn test_translate_v2() {
-    let source_values = vec!["hello", "mello", "cat", "god", "hello", "feline", "cat"];
-    let translated_values: Vec<String> = vec![
+    use crate::api::http::FixtureClient;
+
+    let source_values = ["hello", "mello", "cat", "god", "hello", "feline", "cat"];
+    let translated_values: Vec<String> = [
         "Bonjour", "bonjour", "chat", "Dieu", "Bonjour", "félin", "chat",
     ]
     .iter()
     .map(|v| v.to_string())
     .collect();
-    let translated = translate_v2(&source_values, "en", "fr");
+    let client = FixtureClient::new("google_v2");
+    let translated = translate_api("test-key", &[&source_values], "en", "fr", &client);
 
     assert_eq!(translated, Ok(translated_values));
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_v2_error() {
+    use crate::api::http::FixtureClient;
+
+    let client = FixtureClient::new("google_v2_error");
+    let translated = translate_api("test-key", &[&["hello"]], "en", "xx", &client);
+
+    assert_eq!(
+        translated,
+        Err(TranslationError::Provider(ProviderError {
+            code: 400,
+            message: "Invalid Value".to_string(),
+            status: Some("INVALID_ARGUMENT".to_string()),
+        }))
+    );
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_web() {
+    use crate::api::http::FixtureClient;
+
+    let source_values = ["hello", "cat", "hello"];
+    let client = FixtureClient::new("google_web");
+    let translated = translate_web(&[&source_values], "en", "fr", &client);
+
+    assert_eq!(
+        translated,
+        Ok(vec![
+            "Bonjour".to_string(),
+            "chat".to_string(),
+            "Bonjour".to_string()
+        ])
+    );
+    assert_eq!(client.remaining(), 0);
+}
+
+#[test]
+fn test_translate_web_blocked() {
+    use crate::api::http::FixtureClient;
+
+    let client = FixtureClient::new("google_web_blocked");
+    let translated = translate_web(&[&["hello"]], "en", "fr", &client);
+    assert_eq!(translated, Ok(vec!["Bonjour".to_string()]));
+
+    let translated = translate_web(&[&["cat"]], "en", "fr", &client);
+    assert!(translated.unwrap_err().contains("GOOGLE_API_KEY"));
+    assert_eq!(client.remaining(), 0);
 }
@@ -0,0 +1,296 @@
+//Http transport used by the providers
+//The providers only build requests and parse responses, the client does the io.
+
+use std::cell::Cell;
+
+use serde_json::Value;
+
+/// Status and body of an http response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn is_ok(&self) -> bool {
+        self.status == 200
+    }
+}
+
+pub trait HttpClient {
+    /// GET request with the headers and query params
+    fn get(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<HttpResponse, String>;
+
+    /// POST request with a json body
+    fn post_json(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &Value,
+    ) -> Result<HttpResponse, String>;
+}
+
+/// Default client doing the requests with ureq
+///
+/// Uses the agent of `Config::http_agent` when set, a default agent otherwise
+#[derive(Debug, Clone, Default)]
+pub struct UreqClient {
+    pub agent: Option<ureq::Agent>,
+}
+
+impl HttpClient for UreqClient {
+    fn get(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<HttpResponse, String> {
+        let request = match &self.agent {
+            Some(agent) => agent.get(url),
+            None => ureq::get(url),
+        };
+        let mut request = request
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .query_pairs(query.iter().copied());
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let mut response = request.call().map_err(|e| e.to_string())?;
+
+        Ok(HttpResponse {
+            status: response.status().as_u16(),
+            body: response
+                .body_mut()
+                .read_to_string()
+                .map_err(|e| e.to_string())?,
+        })
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &Value,
+    ) -> Result<HttpResponse, String> {
+        let request = match &self.agent {
+            Some(agent) => agent.post(url),
+            None => ureq::post(url),
+        };
+        let mut request = request.config().http_status_as_error(false).build();
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let mut response = request.send_json(body).map_err(|e| e.to_string())?;
+
+        Ok(HttpResponse {
+            status: response.status().as_u16(),
+            body: response
+                .body_mut()
+                .read_to_string()
+                .map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+/// Counts the requests sent through a client
+///
+/// The retries are told apart where the chunks are retried, see `ProviderUsage::retries`
+pub struct CountingClient<'a> {
+    inner: &'a dyn HttpClient,
+    requests: Cell<usize>,
+}
+
+impl<'a> CountingClient<'a> {
+    pub fn new(inner: &'a dyn HttpClient) -> Self {
+        Self {
+            inner,
+            requests: Cell::new(0),
+        }
+    }
+
+    /// Requests sent so far
+    pub fn requests(&self) -> usize {
+        self.requests.get()
+    }
+
+    fn count(&self) {
+        self.requests.set(self.requests.get() + 1);
+    }
+}
+
+impl HttpClient for CountingClient<'_> {
+    fn get(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<HttpResponse, String> {
+        self.count();
+        self.inner.get(url, headers, query)
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &Value,
+    ) -> Result<HttpResponse, String> {
+        self.count();
+        self.inner.post_json(url, headers, body)
+    }
+}
+
+/// Replays http interactions from a fixture file in `tests/fixtures`.
+///
+/// Run the tests with `RECORD_FIXTURES=1` to do the requests with [`UreqClient`]
+/// and (re)write the fixture file instead.
+#[cfg(test)]
+pub struct FixtureClient {
+    path: std::path::PathBuf,
+    record: bool,
+    interactions: std::cell::RefCell<std::collections::VecDeque<Interaction>>,
+    recorded: std::cell::RefCell<Vec<Interaction>>,
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Interaction {
+    method: String,
+    url: String,
+    request: Value,
+    status: u16,
+    body: String,
+}
+
+#[cfg(test)]
+impl FixtureClient {
+    pub fn new(name: &str) -> Self {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("{name}.json"));
+        let record = std::env::var("RECORD_FIXTURES").is_ok();
+
+        let interactions = if record {
+            Default::default()
+        } else {
+            let content = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Missing fixture {}: {e}", path.display()));
+            serde_json::from_str(&content)
+                .unwrap_or_else(|e| panic!("Invalid fixture {}: {e}", path.display()))
+        };
+
+        Self {
+            path,
+            record,
+            interactions: std::cell::RefCell::new(interactions),
+            recorded: Default::default(),
+        }
+    }
+
+    /// Number of interactions that were not replayed
+    pub fn remaining(&self) -> usize {
+        self.interactions.borrow().len()
+    }
+
+    fn exchange(
+        &self,
+        method: &str,
+        url: &str,
+        request: Value,
+        send: impl FnOnce() -> Result<HttpResponse, String>,
+    ) -> Result<HttpResponse, String> {
+        if self.record {
+            let response = send()?;
+            self.recorded.borrow_mut().push(Interaction {
+                method: method.to_string(),
+                url: url.to_string(),
+                request,
+                status: response.status,
+                body: response.body.clone(),
+            });
+            let content = serde_json::to_string_pretty(&*self.recorded.borrow()).unwrap();
+            std::fs::write(&self.path, content).unwrap();
+            return Ok(response);
+        }
+
+        let interaction = self
+            .interactions
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or_else(|| panic!("No interaction left for {method} {url}"));
+
+        assert_eq!(
+            (interaction.method.as_str(), interaction.url.as_str()),
+            (method, url)
+        );
+        assert_eq!(interaction.request, request, "request for {method} {url}");
+
+        Ok(HttpResponse {
+            status: interaction.status,
+            body: interaction.body,
+        })
+    }
+}
+
+#[cfg(test)]
+impl HttpClient for FixtureClient {
+    fn get(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<HttpResponse, String> {
+        //the api key is never written to the fixtures
+        let request = query
+            .iter()
+            .filter(|(name, _)| *name != "key")
+            .map(|(name, value)| serde_json::json!([name, value]))
+            .collect();
+
+        self.exchange("GET", url, request, || {
+            UreqClient::default().get(url, headers, query)
+        })
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &Value,
+    ) -> Result<HttpResponse, String> {
+        let mut request = body.clone();
+        if let Some(obj) = request.as_object_mut() {
+            obj.remove("api_key");
+        }
+
+        self.exchange("POST", url, request, || {
+            UreqClient::default().post_json(url, headers, body)
+        })
+    }
+}
+
+#[test]
+fn test_counting_client() {
+    let fixture = FixtureClient::new("google_web_blocked");
+    let client = CountingClient::new(&fixture);
+    let query = |q| [("sl", "en"), ("tl", "fr"), ("q", q)];
+
+    for q in ["hello", "hello", "cat", "cat", "cat"] {
+        client
+            .get("https://translate.google.com/m", &[], &query(q))
+            .unwrap();
+    }
+
+    //the same request sent twice is not a retry by itself
+    assert_eq!(client.requests(), 5);
+}
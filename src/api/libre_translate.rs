@@ -4,14 +4,17 @@
 //You can make up to 80 API calls per minute. These are bursts of up to 80 / minute.
 //If you are translating non-stop, the actual limit is closer to 20 / minute (1200 / hour). Each call has a 2,000 character limit.
 
-use std::{collections::HashMap, env};
+use std::{collections::HashMap, env, sync::LazyLock};
 
-use html_escape::decode_html_entities;
-use log::debug;
 use serde::{Deserialize, Serialize};
-use ureq::http::StatusCode;
 
-use crate::utils::translation_limiter::SyncRateLimiter;
+use crate::{
+    api::{http::HttpClient, reassemble},
+    utils::{logging::debug, translation_limiter::SyncRateLimiter},
+};
+
+//shared by every request of the process, chunks may be sent concurrently
+static LIMITER: LazyLock<SyncRateLimiter> = LazyLock::new(SyncRateLimiter::new);
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -31,15 +34,12 @@ pub struct TranslationRequestBody {
 ///Translate using v1 api
 ///
 pub fn translate_v1(
-    source_data: &Vec<&str>,
+    chunks: &[&[&str]],
     source_lang: &str,
     target_lang: &str,
+    client: &dyn HttpClient,
 ) -> Result<Vec<String>, String> {
-    let limiter = SyncRateLimiter::new();
-
-    let mut translated: Vec<String> = Vec::with_capacity(source_data.len());
-
-    let (api_key, api_url) = if let Some(key) = env::var("LIBRE_TRANSLATE_API_KEY").ok() {
+    let (api_key, api_url) = if let Ok(key) = env::var("LIBRE_TRANSLATE_API_KEY") {
         if key.is_empty() {
             (None, "http://127.0.0.1:5001/translate")
         } else {
@@ -49,6 +49,21 @@ pub fn translate_v1(
         (None, "http://127.0.0.1:5001/translate")
     };
 
+    translate_api(api_key, api_url, chunks, source_lang, target_lang, client)
+}
+
+///Translate using the hosted api when a key is set or a local instance
+fn translate_api(
+    api_key: Option<String>,
+    api_url: &str,
+    chunks: &[&[&str]],
+    source_lang: &str,
+    target_lang: &str,
+    client: &dyn HttpClient,
+) -> Result<Vec<String>, String> {
+    let mut translated: Vec<String> =
+        Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
+
     let mut duplicates = 0;
 
     // if let Some(key) = api_key
@@ -56,8 +71,6 @@ pub fn translate_v1(
     // {
     let mut mem_cache: HashMap<&str, Vec<usize>> = HashMap::new();
 
-    let chunks: Vec<&[&str]> = source_data.chunks(120).collect();
-
     for chunk in chunks {
         let mut qry_text: Vec<String> = Vec::new();
 
@@ -83,69 +96,22 @@ pub fn translate_v1(
             api_key: api_key.clone(),
         };
 
-        let response = limiter.run(|| {
-            ureq::post(api_url)
-                .config()
-                .http_status_as_error(false)
-                .build()
-                .send_json(json_body)
-        });
+        let json_body = serde_json::to_value(json_body).map_err(|e| e.to_string())?;
+        let response = LIMITER.run(|| client.post_json(api_url, &[], &json_body));
         match response {
-            Ok(mut translated_res) => {
-                match translated_res.status() {
-                    StatusCode::OK => {
-                        let data_res = translated_res.body_mut().read_json::<TranslationResponse>();
-
-                        match data_res {
-                            Ok(data) => {
-                                let g_translated_data = &data.translated_text;
-
-                                for (idx, text) in data.translated_text.iter().enumerate() {
-                                    let decoded_str = decode_html_entities(&text);
-                                    let decoded = decoded_str.trim();
-
-                                    //replace the empty value with one in pos
-                                    if decoded.is_empty() {
-                                        for mem_val in mem_cache.values() {
-                                            let pos = mem_val.iter().position(|x| x == &idx);
-
-                                            if let Some(pos) = pos {
-                                                //We only want to use not 0 pos as it is the finder of the  translated value
-                                                if pos > 0 {
-                                                    let init_pos = mem_val[0];
-                                                    let translated_value =
-                                                        g_translated_data.get(init_pos);
-                                                    if let Some(translation) = translated_value {
-                                                        let init_pos_decoded =
-                                                            decode_html_entities(&translation);
-                                                        translated
-                                                            .push(init_pos_decoded.to_string());
-                                                        break;
-                                                    } else {
-                                                        translated.push(decoded.to_string());
-                                                        break;
-                                                    }
-                                                } else {
-                                                    translated.push(decoded.to_string());
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                    } else {
-                                        translated.push(decoded.to_string());
-                                    }
-                                }
-                            }
-                            Err(err) => return Err(err.to_string()),
+            Ok(translated_res) => {
+                if translated_res.is_ok() {
+                    let data_res =
+                        serde_json::from_str::<TranslationResponse>(&translated_res.body);
+
+                    match data_res {
+                        Ok(data) => {
+                            translated.extend(reassemble(&data.translated_text, &mem_cache)?);
                         }
+                        Err(err) => return Err(err.to_string()),
                     }
-
-                    _ => {
-                        return Err(translated_res
-                            .body_mut()
-                            .read_to_string()
-                            .unwrap_or_default());
-                    }
+                } else {
+                    return Err(translated_res.body);
                 }
             }
             Err(e) => return Err(e.to_string()),
@@ -154,21 +120,32 @@ pub fn translate_v1(
         mem_cache.clear();
     }
 
-    debug!("Duplicates found: {duplicates}");
+    debug!(provider = "libretranslate", duplicates; "Duplicates found: {duplicates}");
 
     Ok(translated)
 }
 
 #[test]
 fn test_translate_v1() {
-    let source_values = vec!["hello", "mello", "cat", "god", "hello", "feline", "cat"];
-    let translated_values: Vec<String> = vec![
+    use crate::api::http::FixtureClient;
+
+    let source_values = ["hello", "mello", "cat", "god", "hello", "feline", "cat"];
+    let translated_values: Vec<String> = [
         "bonjour", "mello", "chat", "dieu", "bonjour", "féline", "chat",
     ]
     .iter()
     .map(|v| v.to_string())
     .collect();
-    let translated = translate_v1(&source_values, "en", "fr");
+    let client = FixtureClient::new("libre_v1");
+    let translated = translate_api(
+        None,
+        "http://127.0.0.1:5001/translate",
+        &[&source_values],
+        "en",
+        "fr",
+        &client,
+    );
 
     assert_eq!(translated, Ok(translated_values));
+    assert_eq!(client.remaining(), 0);
 }
@@ -1,38 +1,1275 @@
-use crate::{config::TranslationProvider, utils::languages::normalize_lang};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    slice,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use html_escape::decode_html_entities;
+use regex::Regex;
+
+#[cfg(test)]
+use crate::utils::TempDir;
+use crate::{
+    api::http::{CountingClient, UreqClient},
+    config::{Config, TranslationProvider},
+    error::TranslationError,
+    utils::{
+        glossary::{self, GlossaryMatcher},
+        icu::{self, IcuToken},
+        journal::{self, Journal},
+        languages::{normalize_source_lang, normalize_target_lang},
+        logging::{self, LogScope, debug, info, warn},
+        placeholders::{self, Protected},
+        retry,
+    },
+};
 
 mod deepl_translate;
+mod dictionary;
 mod google_translate;
+mod http;
 mod libre_translate;
+mod numbered;
+mod pseudo;
+
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+
+/// Usage reported by the provider while translating
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProviderUsage {
+    /// Characters billed, only set by providers that report it
+    pub billed_characters: Option<usize>,
+    /// Values returned unchanged as the provider had no translation for them
+    pub untranslated: usize,
+    /// Http requests sent, retries excluded
+    pub requests: usize,
+    /// Http requests sent again after a failed attempt
+    pub retries: usize,
+    /// Characters sent again after a failure, by the retries of the chunks or to find the
+    /// values of a batch that failed
+    pub retried_characters: usize,
+}
+
+impl ProviderUsage {
+    /// Add the usage of another request
+    pub fn add(&mut self, other: ProviderUsage) {
+        self.billed_characters = match (self.billed_characters, other.billed_characters) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.untranslated += other.untranslated;
+        self.requests += other.requests;
+        self.retries += other.retries;
+        self.retried_characters += other.retried_characters;
+    }
+}
+
+/// Hard request limits of a provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderLimits {
+    /// Max number of segments that can be sent in a single request
+    pub max_segments: usize,
+    /// Max number of characters that can be sent in a single request, bytes of the json
+    /// request with `json_bytes`
+    pub max_chars: usize,
+    /// The request size is capped in bytes of the json body instead of characters
+    pub json_bytes: bool,
+}
+
+impl ProviderLimits {
+    /// Limits according to the provider docs
+    pub fn of(provider: &TranslationProvider) -> Self {
+        match provider {
+            //max string that can be taken by the q param is 128
+            //recommended max request size is 5k characters
+            TranslationProvider::GOOGLE => Self {
+                max_segments: 128,
+                max_chars: 5_000,
+                json_bytes: false,
+            },
+            //max 50 texts per request, total request size is capped at 128 KiB
+            //the rest of the body is left 8 KiB, eg: the context and the instructions
+            TranslationProvider::DEEPL => Self {
+                max_segments: 50,
+                max_chars: 120_000,
+                json_bytes: true,
+            },
+            //each call has a 2,000 character limit
+            TranslationProvider::LIBRETRANSLATE => Self {
+                max_segments: 128,
+                max_chars: 2_000,
+                json_bytes: false,
+            },
+            //local lookups
+            TranslationProvider::DICTIONARY | TranslationProvider::PSEUDO => Self {
+                max_segments: usize::MAX,
+                max_chars: usize::MAX,
+                json_bytes: false,
+            },
+        }
+    }
+
+    /// Size of a value against `max_chars`, its characters or the bytes it adds to the json
+    /// request with `json_bytes`
+    pub fn size(&self, value: &str) -> usize {
+        if self.json_bytes {
+            //quoted, escaped and followed by a comma in the array of texts
+            serde_json::to_string(value).map_or(value.len(), |json| json.len()) + 1
+        } else {
+            value.chars().count()
+        }
+    }
+
+    /// Max characters of the pieces of a value over the limit, see [`split_long_value`]
+    pub fn max_piece_chars(&self) -> usize {
+        if self.json_bytes {
+            //an escaped character takes up to 6 bytes, eg: `\u0001`
+            self.max_chars / 6
+        } else {
+            self.max_chars
+        }
+    }
+}
+
+///
+/// Split the source data into chunks that respect the provider limits.
+///
+/// A value longer than `max_chars` is sent on its own, see [`ProviderLimits::size`].
+pub fn chunk_source<'a>(source_data: &'a [&'a str], limits: &ProviderLimits) -> Vec<&'a [&'a str]> {
+    let mut chunks = Vec::new();
+
+    let mut start = 0;
+    let mut chars = 0;
+
+    for (idx, value) in source_data.iter().enumerate() {
+        let len = limits.size(value);
+        let segments = idx - start;
+
+        if segments > 0 && (segments >= limits.max_segments || chars + len > limits.max_chars) {
+            chunks.push(&source_data[start..idx]);
+            start = idx;
+            chars = 0;
+        }
+
+        chars += len;
+    }
+
+    if start < source_data.len() {
+        chunks.push(&source_data[start..]);
+    }
+
+    chunks
+}
+
+///
+/// Split a value longer than `max_chars` into pieces sent separately, with the whitespace
+/// following each piece.
+///
+/// Splits on sentence boundaries, sentences that are still too long are split between words.
+/// Placeholders are protected as `__PH0__` tokens which never contain whitespace, so they are
+/// never cut. Joining the pieces with their whitespace gives back the value.
+pub fn split_long_value(value: &str, max_chars: usize) -> Vec<(&str, &str)> {
+    if value.chars().count() <= max_chars {
+        return vec![(value, "")];
+    }
+
+    let sentence_end = |before: &str, sep: &str| {
+        sep.contains('\n') || before.ends_with(['.', '!', '?', '…', '。', '！', '？'])
+    };
+
+    let mut units = Vec::new();
+    for (text, sep) in split_whitespace_units(value, 0..value.len(), sentence_end) {
+        if value[text.clone()].chars().count() > max_chars {
+            let mut words = split_whitespace_units(value, text.start..sep.end, |_, _| true);
+            units.append(&mut words);
+        } else {
+            units.push((text, sep));
+        }
+    }
+
+    //pack consecutive units as long as they fit
+    let mut pieces: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    for (text, sep) in units {
+        if let Some((piece, piece_sep)) = pieces.last_mut()
+            && value[piece.start..text.end].chars().count() <= max_chars
+        {
+            piece.end = text.end;
+            *piece_sep = sep;
+        } else {
+            pieces.push((text, sep));
+        }
+    }
+
+    pieces
+        .into_iter()
+        .map(|(text, sep)| (&value[text], &value[sep]))
+        .collect()
+}
+
+/// Byte ranges of the text and the whitespace following it, split on the whitespace runs
+/// accepted by `is_boundary(text before, whitespace)`
+fn split_whitespace_units(
+    value: &str,
+    range: Range<usize>,
+    is_boundary: impl Fn(&str, &str) -> bool,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut units = Vec::new();
+    let mut start = range.start;
+
+    for run in WHITESPACE.find_iter(&value[range.clone()]) {
+        let (run_start, run_end) = (range.start + run.start(), range.start + run.end());
+        if run_end == range.end {
+            break;
+        }
+        if run_start > start && is_boundary(&value[start..run_start], run.as_str()) {
+            units.push((start..run_start, run_start..run_end));
+            start = run_end;
+        }
+    }
+
+    //trailing whitespace stays with the last unit
+    let trimmed_end = start + value[start..range.end].trim_end().len();
+    units.push((start..trimmed_end, trimmed_end..range.end));
+
+    units
+}
+
+///
+/// Environment variables holding the api key of the provider, any of them is enough
+pub fn api_key_vars(provider: &TranslationProvider) -> &'static [&'static str] {
+    match provider {
+        TranslationProvider::GOOGLE => &["GOOGLE_API_KEY"],
+        TranslationProvider::DEEPL => &["DEEPL_FREE_API_KEY", "DEEPL_PRO_API_KEY"],
+        TranslationProvider::LIBRETRANSLATE => &["LIBRE_TRANSLATE_API_KEY"],
+        TranslationProvider::DICTIONARY | TranslationProvider::PSEUDO => &[],
+    }
+}
+
+///
+/// Error when `Config::require_api_key` is set and the provider would use its keyless fallback
+pub fn check_api_key(config: &Config) -> Result<(), String> {
+    let vars = api_key_vars(&config.provider);
+    let has_key = vars.is_empty()
+        || vars
+            .iter()
+            .any(|var| std::env::var(var).is_ok_and(|key| !key.is_empty()));
+
+    if !config.require_api_key || has_key {
+        Ok(())
+    } else {
+        Err(format!(
+            "An api key is required for {:?}, set {}",
+            config.provider,
+            vars.join(" or ")
+        ))
+    }
+}
+
+///
+/// Characters billed for translating the source data.
+///
+/// Duplicates in a chunk are sent as empty strings and are not counted.
+pub fn billable_chars(provider: &TranslationProvider, source_data: &[&str]) -> usize {
+    if matches!(
+        provider,
+        TranslationProvider::DICTIONARY | TranslationProvider::PSEUDO
+    ) {
+        return 0;
+    }
+
+    chunk_source(source_data, &ProviderLimits::of(provider))
+        .iter()
+        .map(|chunk| {
+            let mut seen = HashSet::new();
+            chunk
+                .iter()
+                .filter(|value| seen.insert(**value))
+                .map(|value| value.chars().count())
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+///
+/// Values that appear more than once, they are only translated once
+pub fn duplicate_count(values: &[&str]) -> usize {
+    values.len() - values.iter().collect::<HashSet<_>>().len()
+}
+
+///
+/// Rebuild the translations of a chunk that was sent with its duplicates as empty strings.
+///
+/// `mem_cache` maps each unique value of the chunk to its positions, the first position
+/// being the one that was sent for translation. A response with more or fewer translations
+/// than the chunk has values is an error, the translations could not be matched to the keys.
+pub fn reassemble(
+    responses: &[String],
+    mem_cache: &HashMap<&str, Vec<usize>>,
+) -> Result<Vec<String>, String> {
+    let len: usize = mem_cache.values().map(|positions| positions.len()).sum();
+    if responses.len() != len {
+        return Err(format!(
+            "The provider returned {} translations for {len} values",
+            responses.len()
+        ));
+    }
+
+    let mut translated: Vec<String> = responses
+        .iter()
+        .map(|text| decode_html_entities(text).trim().to_string())
+        .collect();
+
+    //fill every duplicate position with the translation of the first occurrence
+    for positions in mem_cache.values() {
+        if let Some((first, duplicates)) = positions.split_first() {
+            let translation = translated.get(*first).cloned().unwrap_or_default();
+
+            for pos in duplicates {
+                if let Some(slot) = translated.get_mut(*pos) {
+                    *slot = translation.clone();
+                }
+            }
+        }
+    }
+
+    Ok(translated)
+}
 
 ///
 /// Translates according to the provider selected
 pub fn translate_data(
-    provider: &TranslationProvider,
-    source_data: &Vec<&str>,
-    source_lang: &str,
+    config: &Config,
+    source_data: &[&str],
     target_lang: &str,
-) -> Result<Vec<String>, String> {
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    translate_data_with_context(config, source_data, &[], target_lang, usage)
+}
+
+///
+/// Translates with a context per value, `contexts` is either empty or as long as `source_data`
+pub fn translate_data_with_context(
+    config: &Config,
+    source_data: &[&str],
+    contexts: &[Option<&str>],
+    target_lang: &str,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    if config.icu_messageformat {
+        translate_icu(config, source_data, contexts, target_lang, usage)
+    } else {
+        translate_values(config, source_data, contexts, target_lang, usage)
+    }
+}
+
+/// Providers using the context of the values
+fn supports_context(provider: &TranslationProvider) -> bool {
+    *provider == TranslationProvider::DEEPL
+}
+
+///
+/// Positions of the values sharing the same context, in order of first appearance
+fn group_by_context<'a>(contexts: &[Option<&'a str>]) -> Vec<(Option<&'a str>, Vec<usize>)> {
+    let mut groups: Vec<(Option<&str>, Vec<usize>)> = Vec::new();
+
+    for (idx, context) in contexts.iter().enumerate() {
+        match groups.iter_mut().find(|(c, _)| c == context) {
+            Some((_, positions)) => positions.push(idx),
+            None => groups.push((*context, vec![idx])),
+        }
+    }
+
+    groups
+}
+
+///
+/// Translates only the literal parts of ICU messages and rebuilds them
+fn translate_icu(
+    config: &Config,
+    source_data: &[&str],
+    contexts: &[Option<&str>],
+    target_lang: &str,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    let messages: Vec<Vec<IcuToken>> = source_data
+        .iter()
+        .map(|value| {
+            icu::tokenize(value).unwrap_or_else(|e| {
+                debug!("Not an ICU message, translating as is: {e}");
+                vec![IcuToken::Text(value.to_string())]
+            })
+        })
+        .collect();
+
+    let texts: Vec<&str> = messages
+        .iter()
+        .flat_map(|tokens| icu::texts(tokens))
+        .collect();
+
+    //every literal part shares the context of its message
+    let text_contexts: Vec<Option<&str>> = if contexts.is_empty() {
+        vec![]
+    } else {
+        messages
+            .iter()
+            .zip(contexts)
+            .flat_map(|(tokens, context)| icu::texts(tokens).map(|_| *context))
+            .collect()
+    };
+
+    let translated = translate_values(config, &texts, &text_contexts, target_lang, usage)?;
+    if translated.len() != texts.len() {
+        return Err("Some translations failed".into());
+    }
+
+    let mut translated = translated.into_iter();
+
+    Ok(messages
+        .iter()
+        .map(|tokens| icu::render(tokens, &mut translated))
+        .collect())
+}
+
+///
+/// Sends the values to the provider selected
+fn translate_values(
+    config: &Config,
+    source_data: &[&str],
+    contexts: &[Option<&str>],
+    target_lang: &str,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    let provider = &config.provider;
+    let source_lang = &config.source_locale;
+
     let normalized_source_lang =
-        normalize_lang(provider, source_lang).map_err(|e| e.to_string())?;
+        normalize_source_lang(config, source_lang).map_err(|e| e.to_string())?;
 
     let normalized_target_lang =
-        normalize_lang(provider, target_lang).map_err(|e| e.to_string())?;
+        normalize_target_lang(config, target_lang).map_err(|e| e.to_string())?;
 
-    match provider {
-        TranslationProvider::GOOGLE => google_translate::translate_v2(
-            source_data,
+    check_api_key(config)?;
+
+    //keep the placeholders and glossary terms away from the provider
+    let terms = glossary::load_terms(config, target_lang)?;
+    let glossary = terms.as_ref().map(GlossaryMatcher::new);
+    let protected: Vec<Protected> = source_data
+        .iter()
+        .map(|value| {
+            let mut protected = match (config.protect_placeholders, config.preserve_html_whitespace)
+            {
+                (true, true) => placeholders::protect_html(value),
+                (true, false) => placeholders::protect(value),
+                (false, true) => placeholders::protect_html_whitespace(value),
+                (false, false) => Protected {
+                    text: value.to_string(),
+                    placeholders: vec![],
+                },
+            };
+            if let Some(glossary) = &glossary {
+                protected.text = glossary.protect(&protected.text);
+            }
+            protected
+        })
+        .collect();
+    let protected_data: Vec<&str> = protected.iter().map(|p| p.text.as_str()).collect();
+
+    let with_context = contexts.iter().any(Option::is_some);
+    if with_context && !supports_context(provider) {
+        info!(provider:? = provider; "Context is not supported by the provider, ignored");
+    }
+
+    let translated = if with_context && supports_context(provider) {
+        //the context applies to a whole request, one pass per distinct context
+        let mut translated = vec![String::new(); protected_data.len()];
+
+        for (context, positions) in group_by_context(contexts) {
+            let values: Vec<&str> = positions.iter().map(|idx| protected_data[*idx]).collect();
+            let group = send_values(
+                config,
+                &values,
+                &normalized_source_lang,
+                &normalized_target_lang,
+                context,
+                usage,
+            )?;
+
+            //the caller discards incomplete translations
+            if group.len() != positions.len() {
+                return Ok(group);
+            }
+            for (idx, translation) in positions.into_iter().zip(group) {
+                translated[idx] = translation;
+            }
+        }
+
+        translated
+    } else {
+        send_values(
+            config,
+            &protected_data,
             &normalized_source_lang,
             &normalized_target_lang,
+            None,
+            usage,
+        )?
+    };
+
+    let translated = match &glossary {
+        Some(glossary) => translated.iter().map(|t| glossary.apply(t)).collect(),
+        None => translated,
+    };
+
+    restore_placeholders(config, source_data, &protected, translated)
+}
+
+///
+/// Splits the values over the provider segment limit, sends them and joins them back
+fn send_values(
+    config: &Config,
+    values: &[&str],
+    source_lang: &str,
+    target_lang: &str,
+    context: Option<&str>,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    let limits = ProviderLimits::of(&config.provider);
+    if values
+        .iter()
+        .all(|value| limits.size(value) <= limits.max_chars)
+    {
+        return send_chunks(config, values, source_lang, target_lang, context, usage);
+    }
+
+    let pieces: Vec<Vec<(&str, &str)>> = values
+        .iter()
+        .map(|value| split_long_value(value, limits.max_piece_chars()))
+        .collect();
+    let segments: Vec<&str> = pieces.iter().flatten().map(|(text, _)| *text).collect();
+    debug!(
+        values = values.len(),
+        segments = segments.len();
+        "Split values over the {} chars limit",
+        limits.max_piece_chars()
+    );
+
+    let translated = send_chunks(config, &segments, source_lang, target_lang, context, usage)?;
+    //the caller discards incomplete translations
+    if translated.len() != segments.len() {
+        return Ok(translated);
+    }
+
+    let mut translated = translated.into_iter();
+    Ok(pieces
+        .iter()
+        .map(|value_pieces| {
+            value_pieces
+                .iter()
+                .map(|(_, sep)| translated.next().unwrap_or_default() + sep)
+                .collect::<String>()
+        })
+        .collect())
+}
+
+///
+/// Chunks the values and dispatches them to the provider selected
+fn send_chunks(
+    config: &Config,
+    values: &[&str],
+    source_lang: &str,
+    target_lang: &str,
+    context: Option<&str>,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    let chunks = chunk_source(values, &ProviderLimits::of(&config.provider));
+    let workers = config.max_concurrent_requests.min(chunks.len());
+
+    if workers <= 1 && !config.resumable {
+        return dispatch(config, &chunks, source_lang, target_lang, context, usage);
+    }
+
+    let journal = config.resumable.then(|| Journal::of_run(config));
+    let send_chunk = |idx: usize, usage: &mut ProviderUsage| match &journal {
+        Some(journal) => send_journaled(
+            journal,
+            config,
+            chunks[idx],
+            source_lang,
+            target_lang,
+            context,
+            usage,
         ),
-        TranslationProvider::DEEPL => deepl_translate::translate_v2(
-            source_data,
-            &normalized_source_lang,
-            &normalized_target_lang,
+        None => dispatch(
+            config,
+            &chunks[idx..=idx],
+            source_lang,
+            target_lang,
+            context,
+            usage,
         ),
-        TranslationProvider::LIBRETRANSLATE => libre_translate::translate_v1(
-            source_data,
-            &normalized_source_lang,
-            &normalized_target_lang,
+    };
+
+    if workers <= 1 {
+        let mut translated = Vec::with_capacity(values.len());
+        for idx in 0..chunks.len() {
+            translated.extend(send_chunk(idx, usage)?);
+        }
+        return Ok(translated);
+    }
+
+    debug!(chunks = chunks.len(), workers; "Sending the chunks concurrently");
+    let results = in_parallel(chunks.len(), workers, |idx| {
+        let mut chunk_usage = ProviderUsage::default();
+        let translated = send_chunk(idx, &mut chunk_usage);
+        (translated, chunk_usage)
+    });
+
+    let mut translated = Vec::with_capacity(values.len());
+    for (chunk_translated, chunk_usage) in results {
+        usage.add(chunk_usage);
+        translated.extend(chunk_translated?);
+    }
+
+    Ok(translated)
+}
+
+///
+/// Sends a chunk unless a stopped run already did, see `Config::resumable`
+fn send_journaled(
+    journal: &Journal,
+    config: &Config,
+    chunk: &[&str],
+    source_lang: &str,
+    target_lang: &str,
+    context: Option<&str>,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    let id = journal::chunk_id(config, source_lang, context, chunk);
+    if let Some(translated) = journal.get(target_lang, &id) {
+        return Ok(translated.clone());
+    }
+
+    let translated = dispatch(config, &[chunk], source_lang, target_lang, context, usage)?;
+    //incomplete translations are sent again
+    if translated.len() == chunk.len() {
+        journal.record(target_lang, &id, &translated);
+    }
+
+    Ok(translated)
+}
+
+///
+/// Run `f` for every index with at most `workers` running at once, results are in index order
+fn in_parallel<T: Send>(len: usize, workers: usize, f: impl Fn(usize) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(len));
+
+    let max_log_level = logging::current();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                let _log = LogScope::set(max_log_level);
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    if idx >= len {
+                        break;
+                    }
+                    let result = f(idx);
+                    results
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push((idx, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    results.sort_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+///
+/// Sends the chunks to the provider selected
+fn dispatch(
+    config: &Config,
+    chunks: &[&[&str]],
+    source_lang: &str,
+    target_lang: &str,
+    context: Option<&str>,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    let client = UreqClient {
+        agent: config.http_agent.as_ref().map(|agent| (*agent.0).clone()),
+    };
+    let client = CountingClient::new(&client);
+
+    let send = |chunks: &[&[&str]], usage: &mut ProviderUsage| match &config.provider {
+        TranslationProvider::GOOGLE => {
+            google_translate::translate_v2(chunks, source_lang, target_lang, &client)
+        }
+        TranslationProvider::DEEPL => deepl_translate::translate_v2(
+            chunks,
+            source_lang,
+            target_lang,
+            context,
+            config,
+            usage,
+            &client,
         ),
+        TranslationProvider::LIBRETRANSLATE => {
+            libre_translate::translate_v1(chunks, source_lang, target_lang, &client)
+                .map_err(Into::into)
+        }
+        TranslationProvider::DICTIONARY => dictionary::translate(
+            chunks,
+            source_lang,
+            target_lang,
+            config.dictionary_file.as_deref(),
+            usage,
+        )
+        .map_err(Into::into),
+        TranslationProvider::PSEUDO => {
+            pseudo::translate(chunks, config.pseudo_expansion).map_err(Into::into)
+        }
+    };
+    retry_chunks(
+        config,
+        chunks,
+        usage,
+        || client.requests(),
+        |chunks, usage| {
+            if config.numbered_batches {
+                numbered::send(chunks, usage, send)
+            } else {
+                send(chunks, usage)
+            }
+        },
+    )
+}
+
+///
+/// Sends the chunks one after the other, a failed chunk is retried alone
+///
+/// The chunks already translated are not sent again. `requests` is the number of http requests
+/// sent so far, the ones of the attempts after the first are counted in `ProviderUsage::retries`
+/// and their characters in `ProviderUsage::retried_characters`
+fn retry_chunks(
+    config: &Config,
+    chunks: &[&[&str]],
+    usage: &mut ProviderUsage,
+    requests: impl Fn() -> usize,
+    mut send: impl FnMut(&[&[&str]], &mut ProviderUsage) -> Result<Vec<String>, TranslationError>,
+) -> Result<Vec<String>, TranslationError> {
+    let mut translated = Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
+    for chunk in chunks {
+        let mut attempts = 0;
+        translated.extend(retry::with_retry(&config.retry_policy, || {
+            if attempts > 0 {
+                usage.retried_characters += billable_chars(&config.provider, chunk);
+            }
+            attempts += 1;
+            let sent = requests();
+            let res = send(slice::from_ref(chunk), usage);
+            let sent = requests() - sent;
+            if attempts > 1 {
+                usage.retries += sent;
+            } else {
+                usage.requests += sent;
+            }
+            res
+        })?);
     }
+    Ok(translated)
+}
+
+///
+/// Put the placeholders back in the translations
+///
+/// A translation that lost or duplicated a placeholder is an error with `strict_placeholders`,
+/// a warning otherwise
+fn restore_placeholders(
+    config: &Config,
+    source_data: &[&str],
+    protected: &[Protected],
+    translated: Vec<String>,
+) -> Result<Vec<String>, TranslationError> {
+    //the caller discards incomplete translations
+    if translated.len() != protected.len() {
+        return Ok(translated);
+    }
+
+    translated
+        .iter()
+        .zip(protected)
+        .zip(source_data)
+        .map(|((translation, protected), source)| {
+            let (restored, check) = placeholders::restore(translation, &protected.placeholders);
+
+            match check {
+                Err(e) if config.strict_placeholders => Err(format!("`{source}`: {e}").into()),
+                Err(e) => {
+                    warn!("`{source}`: {e}");
+                    Ok(restored)
+                }
+                Ok(()) => Ok(restored),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_translate_data_glossary() {
+    let config = Config::new()
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .add_glossary_term("fr", "Dashboard", "Tableau de bord")
+        .add_glossary_term("de", "Dashboard", "Übersicht")
+        .build();
+
+    let translated = translate_data(
+        &config,
+        &["Dashboard", "Open the Dashboard"],
+        "fr",
+        &mut ProviderUsage::default(),
+    );
+
+    assert_eq!(
+        translated,
+        Ok(vec![
+            "Tableau de bord".to_string(),
+            "Open the Tableau de bord".to_string()
+        ])
+    );
+}
+
+#[test]
+fn test_translate_data_pseudo() {
+    let config = Config::new()
+        .translation_provider(TranslationProvider::PSEUDO)
+        .pseudo_expansion(1.5)
+        .protect_placeholders(true)
+        .build();
+
+    let translated = translate_data(
+        &config,
+        &["Hello %{name}"],
+        "en-XA",
+        &mut ProviderUsage::default(),
+    );
+
+    //only the 6 literal chars are counted
+    assert_eq!(translated, Ok(vec!["[! Ḥéļļö %{name} !!]".to_string()]));
+}
+
+#[test]
+fn test_translate_data_protect_placeholders() {
+    let dictionary_dir = TempDir::new("protect_placeholders_test");
+    let dictionary = &dictionary_dir.join("protect_placeholders_test.json");
+    std::fs::write(
+        dictionary,
+        r#"{"fr": {"Hi %{name}": "Salut %{name}", "Hi __PH0__": "Coucou __PH0__"}}"#,
+    )
+    .unwrap();
+    let mut builder = Config::new();
+    builder
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .dictionary_file(dictionary);
+
+    let sent_as_is = translate_data(
+        &builder.build(),
+        &["Hi %{name}"],
+        "fr",
+        &mut ProviderUsage::default(),
+    );
+    let protected = translate_data(
+        &builder.protect_placeholders(true).build(),
+        &["Hi %{name}"],
+        "fr",
+        &mut ProviderUsage::default(),
+    );
+
+    assert_eq!(sent_as_is, Ok(vec!["Salut %{name}".to_string()]));
+    assert_eq!(protected, Ok(vec!["Coucou %{name}".to_string()]));
+}
+
+#[test]
+fn test_translate_data_numbered_batches() {
+    let dictionary_dir = TempDir::new("numbered_batches_test");
+    let dictionary = &dictionary_dir.join("numbered_batches_test.json");
+    std::fs::write(
+        dictionary,
+        r#"{"fr": {"1. Hello\n2. Cat": "2. Chat\n1. Bonjour", "1. Hello\n2. Dog": "1. Bonjour"}}"#,
+    )
+    .unwrap();
+    let config = Config::new()
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .dictionary_file(dictionary)
+        .numbered_batches(true)
+        .build();
+
+    let reordered = translate_data(
+        &config,
+        &["Hello", "Cat"],
+        "fr",
+        &mut ProviderUsage::default(),
+    );
+    //the numbering can't be recovered, the batch fails
+    let mismatch = translate_data(
+        &config,
+        &["Hello", "Dog"],
+        "fr",
+        &mut ProviderUsage::default(),
+    );
+
+    assert_eq!(reordered, Ok(strings(&["Bonjour", "Chat"])));
+    assert!(mismatch.is_err_and(|e| e.to_string().contains("Item 2 missing")));
+}
+
+#[test]
+fn test_retry_chunks() {
+    use crate::{config::RetryPolicy, error::ProviderError};
+    use std::time::Duration;
+
+    let config = Config::new()
+        .translation_provider(TranslationProvider::GOOGLE)
+        .retry_policy(RetryPolicy::custom(|attempt, _| {
+            (attempt <= 2).then_some(Duration::ZERO)
+        }))
+        .build();
+    let chunks: [&[&str]; 2] = [&["Hello", "World"], &["Bye"]];
+
+    let requests = std::cell::Cell::new(0);
+    let mut sent = Vec::new();
+    let mut usage = ProviderUsage::default();
+    let translated = retry_chunks(
+        &config,
+        &chunks,
+        &mut usage,
+        || requests.get(),
+        |chunks, _| {
+            requests.set(requests.get() + 1);
+            sent.push(chunks[0][0].to_string());
+            //the last chunk fails twice
+            if chunks[0] == ["Bye"] && sent.len() < 4 {
+                return Err(TranslationError::Provider(ProviderError::raw(
+                    503,
+                    "Unavailable",
+                )));
+            }
+            Ok(chunks[0].iter().map(|value| value.to_uppercase()).collect())
+        },
+    );
+
+    assert_eq!(translated.unwrap(), ["HELLO", "WORLD", "BYE"]);
+    //the first chunk is not sent again
+    assert_eq!(sent, ["Hello", "Bye", "Bye", "Bye"]);
+    assert_eq!(usage.retried_characters, 6);
+    assert_eq!((usage.requests, usage.retries), (2, 2));
+}
+
+#[test]
+fn test_send_chunks_resumable() {
+    let locales = &TempDir::new("locales_resumable");
+    let config = Config::new()
+        .locales_directory(locales)
+        .translation_provider(TranslationProvider::PSEUDO)
+        .resumable(true)
+        .build();
+
+    //sent by a run that was stopped
+    let chunk = journal::chunk_id(&config, "en", None, &["Hello", "World"]);
+    Journal::load(&config).record("fr", &chunk, &["Bonjour".to_string(), "Monde".to_string()]);
+
+    let resumed = translate_data(
+        &config,
+        &["Hello", "World"],
+        "fr",
+        &mut ProviderUsage::default(),
+    );
+    translate_data(&config, &["Cat"], "fr", &mut ProviderUsage::default()).unwrap();
+    let recorded = Journal::load(&config)
+        .get("fr", &journal::chunk_id(&config, "en", None, &["Cat"]))
+        .cloned();
+
+    assert_eq!(
+        resumed,
+        Ok(vec!["Bonjour".to_string(), "Monde".to_string()])
+    );
+    assert_eq!(recorded.map(|translations| translations.len()), Some(1));
+}
+
+#[test]
+fn test_translate_data_html_whitespace() {
+    let config = Config::new()
+        .translation_provider(TranslationProvider::PSEUDO)
+        .preserve_html_whitespace(true)
+        .build();
+
+    let translated = translate_data(
+        &config,
+        &["<b>Hi</b> <i>Yo</i>"],
+        "en-XA",
+        &mut ProviderUsage::default(),
+    )
+    .unwrap();
+
+    //the pseudolocale accents the tag names too
+    assert!(translated[0].contains("> <"), "{translated:?}");
+    assert!(!translated[0].contains("__PH"), "{translated:?}");
+}
+
+#[test]
+fn test_check_api_key() {
+    let config = Config::new()
+        .translation_provider(TranslationProvider::LIBRETRANSLATE)
+        .require_api_key(true)
+        .build();
+
+    //never set in the test environment
+    if std::env::var("LIBRE_TRANSLATE_API_KEY").is_err() {
+        assert!(
+            check_api_key(&config)
+                .unwrap_err()
+                .contains("LIBRE_TRANSLATE_API_KEY")
+        );
+    }
+
+    let mut dictionary = config.clone();
+    dictionary.translation_provider(TranslationProvider::DICTIONARY);
+    assert_eq!(check_api_key(&dictionary), Ok(()));
+}
+
+#[test]
+fn test_group_by_context() {
+    let contexts = [None, Some("button"), None, Some("title"), Some("button")];
+
+    assert_eq!(
+        group_by_context(&contexts),
+        vec![
+            (None, vec![0, 2]),
+            (Some("button"), vec![1, 4]),
+            (Some("title"), vec![3]),
+        ]
+    );
+}
+
+#[test]
+fn test_chunk_source_segments() {
+    let source_values = ["a"; 300];
+    let limits = ProviderLimits::of(&TranslationProvider::GOOGLE);
+
+    let chunks = chunk_source(&source_values, &limits);
+
+    let lens: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+    assert_eq!(lens, [128, 128, 44]);
+}
+
+#[test]
+fn test_chunk_source_chars() {
+    let long = "x".repeat(900);
+    let too_long = "y".repeat(2_500);
+    let source_values = [
+        long.as_str(),
+        long.as_str(),
+        long.as_str(),
+        too_long.as_str(),
+        "a",
+    ];
+    let limits = ProviderLimits::of(&TranslationProvider::LIBRETRANSLATE);
+
+    let chunks = chunk_source(&source_values, &limits);
+
+    let lens: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+    assert_eq!(lens, [2, 1, 1, 1]);
+    assert!(chunk_source(&[], &limits).is_empty());
+}
+
+#[test]
+fn test_chunk_source_bytes() {
+    //2 bytes per character in the json of the request
+    let accented = "é".repeat(30_000);
+    let source_values = [accented.as_str(); 4];
+    let limits = ProviderLimits::of(&TranslationProvider::DEEPL);
+
+    let chunks = chunk_source(&source_values, &limits);
+
+    let lens: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+    assert_eq!(lens, [1, 1, 1, 1]);
+    assert_eq!(limits.size("é\"n"), 8);
+    assert_eq!(limits.size("ab"), 5);
+    assert!(
+        chunks
+            .iter()
+            .all(|chunk| chunk.iter().map(|v| limits.size(v)).sum::<usize>() <= limits.max_chars)
+    );
+    let ascii = "a".repeat(29_000);
+    assert_eq!(chunk_source(&[ascii.as_str(); 4], &limits).len(), 1);
+}
+
+#[test]
+fn test_split_long_value() {
+    let sentence = "The terms apply to __PH0__ and every user of the service.";
+    let notice = format!(
+        "{}\n\n{}",
+        [sentence; 30].join("  "),
+        [sentence; 20].join(" ")
+    );
+    let max_chars = ProviderLimits::of(&TranslationProvider::LIBRETRANSLATE).max_chars;
+    assert!(notice.chars().count() > max_chars);
+
+    let pieces = split_long_value(&notice, max_chars);
+
+    assert!(pieces.len() > 1);
+    assert!(
+        pieces
+            .iter()
+            .all(|(text, _)| text.chars().count() <= max_chars)
+    );
+    assert!(pieces.iter().all(|(text, _)| text.ends_with("service.")));
+    assert_eq!(
+        pieces
+            .iter()
+            .map(|(text, sep)| format!("{text}{sep}"))
+            .collect::<String>(),
+        notice
+    );
+
+    //a sentence over the limit is split between words
+    let words = ["__PH1__ word"; 300].join(" ");
+    let pieces = split_long_value(&words, max_chars);
+    assert!(
+        pieces
+            .iter()
+            .all(|(text, _)| text.chars().count() <= max_chars)
+    );
+    assert_eq!(
+        pieces
+            .iter()
+            .map(|(text, sep)| format!("{text}{sep}"))
+            .collect::<String>(),
+        words
+    );
+
+    assert_eq!(split_long_value("Short.", max_chars), [("Short.", "")]);
+}
+
+#[test]
+fn test_in_parallel_ordered() {
+    let results = in_parallel(12, 4, |idx| {
+        //later chunks finish first
+        thread::sleep(std::time::Duration::from_millis((12 - idx as u64) * 2));
+        idx * 10
+    });
+
+    assert_eq!(results, (0..12).map(|idx| idx * 10).collect::<Vec<_>>());
+    assert!(in_parallel(0, 4, |idx| idx).is_empty());
+}
+
+#[cfg(test)]
+fn mem_cache_of<'a>(chunk: &[&'a str]) -> HashMap<&'a str, Vec<usize>> {
+    let mut mem_cache: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, q) in chunk.iter().enumerate() {
+        mem_cache.entry(*q).or_default().push(idx);
+    }
+    mem_cache
+}
+
+#[cfg(test)]
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+#[test]
+fn test_reassemble() {
+    let chunk = ["hello", "cat", "hello", "god", "cat", "hello"];
+    let responses = strings(&["Bonjour", "chat", "", "Dieu", "", ""]);
+
+    assert_eq!(
+        reassemble(&responses, &mem_cache_of(&chunk)).unwrap(),
+        strings(&["Bonjour", "chat", "Bonjour", "Dieu", "chat", "Bonjour"])
+    );
+}
+
+#[test]
+fn test_reassemble_first_duplicate_later() {
+    let chunk = ["god", "hello", "cat", "hello"];
+    let responses = strings(&["Dieu", "Bonjour", "chat", ""]);
+
+    assert_eq!(
+        reassemble(&responses, &mem_cache_of(&chunk)).unwrap(),
+        strings(&["Dieu", "Bonjour", "chat", "Bonjour"])
+    );
+}
+
+#[test]
+fn test_reassemble_all_duplicates() {
+    let chunk = ["cat", "cat", "cat"];
+    let responses = strings(&["chat &amp; co", "", ""]);
+
+    assert_eq!(
+        reassemble(&responses, &mem_cache_of(&chunk)).unwrap(),
+        strings(&["chat & co", "chat & co", "chat & co"])
+    );
+}
+
+#[test]
+fn test_reassemble_interleaved() {
+    let chunk = ["a", "b", "a", "c", "b"];
+    let responses = strings(&["A", "B", "", "C", ""]);
+
+    assert_eq!(
+        reassemble(&responses, &mem_cache_of(&chunk)).unwrap(),
+        strings(&["A", "B", "A", "C", "B"])
+    );
+}
+
+#[test]
+fn test_reassemble_length_mismatch() {
+    let chunk = ["hello", "cat", "hello", "god"];
+    let short = strings(&["Bonjour", "chat", ""]);
+    let long = strings(&["Bonjour", "chat", "", "Dieu", "chien"]);
+
+    assert_eq!(
+        reassemble(&short, &mem_cache_of(&chunk)),
+        Err("The provider returned 3 translations for 4 values".to_string())
+    );
+    assert!(reassemble(&long, &mem_cache_of(&chunk)).is_err());
+}
+
+#[test]
+fn test_restore_placeholders_strict() {
+    let source_data = ["{0} of {1}"];
+    let protected = [placeholders::protect(source_data[0])];
+    let translated = vec!["__PH0__ sur".to_string()];
+
+    assert_eq!(
+        restore_placeholders(&Config::new(), &source_data, &protected, translated.clone()),
+        Ok(vec!["{0} sur".to_string()])
+    );
+    assert!(
+        restore_placeholders(
+            &Config::new().strict_placeholders(true).build(),
+            &source_data,
+            &protected,
+            translated
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn test_billable_chars() {
+    let source_values = ["hello", "cat", "hello", "félin", "cat"];
+
+    assert_eq!(
+        billable_chars(&TranslationProvider::GOOGLE, &source_values),
+        13
+    );
+    assert_eq!(billable_chars(&TranslationProvider::GOOGLE, &[]), 0);
 }
@@ -0,0 +1,111 @@
+//Numbered batches
+//The values of a batch are sent as a numbered list (`1. ...\n2. ...`) and the output is mapped
+//back by number instead of by position, so the alignment survives providers that reorder the
+//items (LLM based providers). A value spanning several lines keeps its continuation lines.
+//Opt-in with `Config::numbered_batches`.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::{api::ProviderUsage, error::TranslationError};
+
+static ITEM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(\d+)[.)]\s?(.*)$").unwrap());
+
+/// Number the values of the batch, starting at 1
+pub fn number(values: &[&str]) -> String {
+    values
+        .iter()
+        .enumerate()
+        .map(|(idx, value)| format!("{}. {value}", idx + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Map the numbered output back to the positions of the batch
+///
+/// Text before the first item is ignored. Errors if a number is missing, repeated or out of
+/// range so the whole batch is discarded instead of misaligned.
+pub fn parse(output: &str, len: usize) -> Result<Vec<String>, String> {
+    let mut items: Vec<Option<String>> = vec![None; len];
+    let mut current: Option<usize> = None;
+
+    for line in output.lines() {
+        if let Some(caps) = ITEM.captures(line) {
+            let idx = caps[1]
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (1..=len).contains(n))
+                .ok_or_else(|| format!("Item {} out of range 1..={len}", &caps[1]))?
+                - 1;
+
+            if items[idx].is_some() {
+                return Err(format!("Item {} returned twice", idx + 1));
+            }
+            items[idx] = Some(caps[2].trim_end().to_string());
+            current = Some(idx);
+        } else if let Some(item) = current.and_then(|idx| items[idx].as_mut()) {
+            item.push('\n');
+            item.push_str(line.trim_end());
+        }
+    }
+
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(idx, item)| item.ok_or_else(|| format!("Item {} missing", idx + 1)))
+        .collect()
+}
+
+/// Send each chunk as one numbered list and map the output back to the values of the chunk
+pub fn send(
+    chunks: &[&[&str]],
+    usage: &mut ProviderUsage,
+    send: impl Fn(&[&[&str]], &mut ProviderUsage) -> Result<Vec<String>, TranslationError>,
+) -> Result<Vec<String>, TranslationError> {
+    let mut translated = Vec::with_capacity(chunks.iter().map(|chunk| chunk.len()).sum());
+    for chunk in chunks {
+        let numbered = number(chunk);
+        let output = send(&[&[numbered.as_str()]], usage)?;
+        let [output] = output.as_slice() else {
+            return Err("No translation returned for the numbered batch".into());
+        };
+        translated.extend(parse(output, chunk.len()).map_err(|e| format!("Numbered batch: {e}"))?);
+    }
+    Ok(translated)
+}
+
+#[test]
+fn test_parse_reordered() {
+    let batch = ["Hello", "Two\nlines", "Cat"];
+    assert_eq!(number(&batch), "1. Hello\n2. Two\nlines\n3. Cat");
+
+    let output = "Here are the translations:\n3) Chat\n1. Bonjour\n2. Deux\nlignes\n";
+    assert_eq!(
+        parse(output, batch.len()),
+        Ok(vec![
+            "Bonjour".to_string(),
+            "Deux\nlignes".to_string(),
+            "Chat".to_string()
+        ])
+    );
+}
+
+#[test]
+fn test_parse_unrecoverable() {
+    assert!(
+        parse("1. Bonjour\n3. Chat", 3)
+            .unwrap_err()
+            .contains("2 missing")
+    );
+    assert!(
+        parse("1. Bonjour\n1. Salut", 2)
+            .unwrap_err()
+            .contains("twice")
+    );
+    assert!(
+        parse("1. Bonjour\n4. Chat", 3)
+            .unwrap_err()
+            .contains("out of range")
+    );
+}
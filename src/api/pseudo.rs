@@ -0,0 +1,73 @@
+//Pseudolocalization
+//Accents the letters, pads the length and wraps the values in brackets to expose hardcoded
+//strings and truncation bugs before real translations exist. No network calls.
+//eg: `Hello World` -> `[!! Ḥéļļö Ŵöŕļð !!]`
+//
+//Protected placeholders and glossary terms (`__PH0__`, `__GL0__`) are left untouched.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"__(?:PH|GL)\d+__").unwrap());
+
+const PLAIN: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const ACCENTED: &str = "áƀçðéƒĝĥíĵķļɱñöþǫŕšţúṽŵẋýžÁƁÇÐÉƑĜḤÍĴĶĻṀÑÖÞǪŔŠŢÚṼŴẊÝŽ";
+
+///Pseudolocalize the values, `expansion` is the length in percent of the source
+pub fn translate(chunks: &[&[&str]], expansion: u32) -> Result<Vec<String>, String> {
+    Ok(chunks
+        .iter()
+        .flat_map(|chunk| chunk.iter())
+        .map(|value| pseudolocalize(value, expansion))
+        .collect())
+}
+
+/// Accent the literal text, keep the tokens and pad the literal length to `expansion` percent
+fn pseudolocalize(value: &str, expansion: u32) -> String {
+    if value.is_empty() {
+        return String::new();
+    }
+
+    let mut accented = String::with_capacity(value.len() * 2);
+    let mut literal_chars = 0;
+    let mut last = 0;
+
+    for token in TOKEN.find_iter(value) {
+        literal_chars += accent(&value[last..token.start()], &mut accented);
+        accented.push_str(token.as_str());
+        last = token.end();
+    }
+    literal_chars += accent(&value[last..], &mut accented);
+
+    let padding = (literal_chars * expansion.saturating_sub(100) as usize).div_ceil(100);
+    let left = padding / 2;
+
+    format!(
+        "[{} {accented} {}]",
+        "!".repeat(left),
+        "!".repeat(padding - left)
+    )
+}
+
+/// Push the accented text, returns the number of chars
+fn accent(text: &str, out: &mut String) -> usize {
+    let mut count = 0;
+    for c in text.chars() {
+        match PLAIN.find(c) {
+            Some(idx) => out.push(ACCENTED.chars().nth(idx).unwrap_or(c)),
+            None => out.push(c),
+        }
+        count += 1;
+    }
+    count
+}
+
+#[test]
+fn test_pseudolocalize() {
+    assert_eq!(
+        translate(&[&["Hello World", ""]], 130),
+        Ok(vec!["[!! Ḥéļļö Ŵöŕļð !!]".to_string(), String::new()])
+    );
+    assert_eq!(pseudolocalize("Hi __PH0__", 130), "[ Ḥí __PH0__ !]");
+}
@@ -4,12 +4,30 @@
 //! Helps build the configuration for the translation api
 //!
 
+use log::LevelFilter;
 use normpath::PathExt;
+use serde::Serialize;
 use std::{
-    io,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt, io,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 use thiserror::Error;
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+
+#[cfg(test)]
+use crate::utils::TempDir;
+use crate::{
+    error::TranslationError,
+    report::TranslationReportEntry,
+    utils::{
+        existing_locales,
+        languages::{canonical_locale, normalize_lang, zh_script},
+    },
+};
 
 /// Errors for the Config Builder
 #[derive(Error, Debug)]
@@ -20,7 +38,7 @@ pub enum DirectoryError {
 }
 
 /// Providers available for translation
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize)]
 pub enum TranslationProvider {
     ///Google Cloud Translation
     #[default]
@@ -29,8 +47,418 @@ pub enum TranslationProvider {
     DEEPL,
     ///LibreTranslate Translations
     LIBRETRANSLATE,
+    ///Offline dictionary lookups, unknown values are kept as is, see `Config::dictionary_file`
+    DICTIONARY,
+    ///Pseudolocalization for testing, eg: `[!! Ḥéļļö Ŵöŕļð !!]` into `en-XA`
+    PSEUDO,
+}
+
+/// Format of the generated locale files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    ///`.json`
+    Json,
+    ///`.yml` or `.yaml`
+    Yaml,
+    ///`.toml`
+    Toml,
+    ///`.json5`, unquoted keys and trailing commas
+    Json5,
+    ///Apple `.strings`
+    Strings,
+    ///Java `.properties`
+    Properties,
+}
+
+impl OutputFormat {
+    /// Every supported format
+    pub fn all() -> &'static [OutputFormat] {
+        &[
+            OutputFormat::Json,
+            OutputFormat::Yaml,
+            OutputFormat::Toml,
+            OutputFormat::Json5,
+            OutputFormat::Strings,
+            OutputFormat::Properties,
+        ]
+    }
+
+    /// Format of a file extension, without the dot
+    pub fn from_extension(ext: &str) -> Option<OutputFormat> {
+        match ext.to_ascii_lowercase().as_str() {
+            "json" => Some(OutputFormat::Json),
+            "yml" | "yaml" => Some(OutputFormat::Yaml),
+            "toml" => Some(OutputFormat::Toml),
+            "json5" => Some(OutputFormat::Json5),
+            "strings" => Some(OutputFormat::Strings),
+            "properties" => Some(OutputFormat::Properties),
+            _ => None,
+        }
+    }
+
+    /// Main extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Yaml => "yml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Json5 => "json5",
+            OutputFormat::Strings => "strings",
+            OutputFormat::Properties => "properties",
+        }
+    }
+}
+
+/// Source locale translated into its own target locales, see [`Config::add_group`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LocaleGroup {
+    ///Source language of the group
+    pub source_locale: String,
+    ///Languages to translate the source of the group to
+    pub target_locales: Vec<String>,
+}
+
+/// Normalization of the source values used as cache keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheKeyNormalization {
+    ///Exact source value
+    #[default]
+    None,
+    ///Surrounding whitespace removed
+    Trim,
+    ///Surrounding whitespace removed and unicode NFC normalized
+    TrimNfc,
+}
+
+impl CacheKeyNormalization {
+    /// Cache key of a source value
+    pub fn key<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        match self {
+            CacheKeyNormalization::None => Cow::Borrowed(value),
+            CacheKeyNormalization::Trim => Cow::Borrowed(value.trim()),
+            CacheKeyNormalization::TrimNfc => {
+                let trimmed = value.trim();
+                if is_nfc(trimmed) {
+                    Cow::Borrowed(trimmed)
+                } else {
+                    Cow::Owned(trimmed.nfc().collect())
+                }
+            }
+        }
+    }
+
+    /// Cache key of a source value sent with a context, the same value may be translated
+    /// differently in another context
+    ///
+    /// The context comes first, separated by `\u{4}` like the `msgctxt` of gettext
+    pub fn context_key<'a>(&self, value: &'a str, context: Option<&str>) -> Cow<'a, str> {
+        match context {
+            Some(context) => Cow::Owned(format!("{context}\u{4}{}", self.key(value))),
+            None => self.key(value),
+        }
+    }
+}
+
+/// Encoding of generated Apple `.strings` files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringsEncoding {
+    ///UTF-8 without a BOM
+    #[default]
+    Utf8,
+    ///UTF-16 little endian with a BOM, expected by legacy iOS toolchains
+    Utf16Le,
+}
+
+/// Encoding of generated Java `.properties` files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PropertiesEncoding {
+    ///ISO-8859-1 with `\uXXXX` escapes, read by every JVM
+    #[default]
+    Latin1,
+    ///UTF-8, read by `ResourceBundle` since Java 9
+    Utf8,
+}
+
+/// How the autogen cache of translated values is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheMode {
+    ///Translate everything and do not record the translations
+    Off,
+    ///Reuse cached translations and record the new ones
+    #[default]
+    ReadWrite,
+    ///Translate everything but record the translations
+    WriteOnly,
+    ///Reuse cached translations but do not record the new ones
+    ReadOnly,
+}
+
+impl CacheMode {
+    ///Cached translations are reused
+    pub fn reads(&self) -> bool {
+        matches!(self, Self::ReadWrite | Self::ReadOnly)
+    }
+
+    ///New translations are recorded in the cache
+    pub fn writes(&self) -> bool {
+        matches!(self, Self::ReadWrite | Self::WriteOnly)
+    }
+}
+
+/// DeepL api used when translating with DeepL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeepLPlan {
+    ///The free api when `DEEPL_FREE_API_KEY` is set, then the pro api, then deeplx
+    #[default]
+    Auto,
+    ///The free api, `DEEPL_FREE_API_KEY` is required
+    Free,
+    ///The pro api, `DEEPL_PRO_API_KEY` is required
+    Pro,
+}
+
+/// English sent to DeepL for an `en` target, DeepL wants a regional variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeepLEnglish {
+    ///`EN-US`
+    #[default]
+    EnUs,
+    ///`EN-GB`
+    EnGb,
+}
+
+impl DeepLEnglish {
+    /// DeepL language code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::EnUs => "EN-US",
+            Self::EnGb => "EN-GB",
+        }
+    }
+}
+
+/// Portuguese sent to DeepL for a `pt` target, DeepL wants a regional variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeepLPortuguese {
+    ///`PT-BR`
+    #[default]
+    PtBr,
+    ///`PT-PT`
+    PtPt,
+}
+
+impl DeepLPortuguese {
+    /// DeepL language code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::PtBr => "PT-BR",
+            Self::PtPt => "PT-PT",
+        }
+    }
+}
+
+/// What happens to a locked key when its source value changes, see `Config::lock_key`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockPolicy {
+    ///Keep the existing translation
+    #[default]
+    KeepAlways,
+    ///Translate the key again, the translation is locked again afterwards
+    ReleaseOnSourceChange,
+}
+
+/// When the generated file of a target locale is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overwrite {
+    ///Always write the file
+    #[default]
+    Always,
+    ///Only write the file when its content changed, keeps the modification time otherwise
+    IfChanged,
+    ///Only write the file when it does not exist, existing locales are not translated
+    Never,
+}
+
+/// Case of the locale in the generated file names, see `Config::filename_case`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilenameCase {
+    ///Locale as given in the config
+    #[default]
+    AsIs,
+    ///eg: `zh-CN` -> `zh-cn`
+    Lower,
+    ///Lowercase language, title case script and uppercase region, eg: `zh_cn` -> `zh-CN`
+    Bcp47,
+}
+
+impl FilenameCase {
+    ///Locale spelled in this case
+    pub fn apply(self, locale: &str) -> String {
+        match self {
+            FilenameCase::AsIs => locale.to_string(),
+            FilenameCase::Lower => locale.to_lowercase(),
+            FilenameCase::Bcp47 => canonical_locale(locale),
+        }
+    }
+}
+
+/// What to do when the provider does not support a target language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnsupportedLanguage {
+    ///Fail the whole run
+    #[default]
+    Abort,
+    ///Log a warning, record the locale in the report and continue with the rest
+    Skip,
+}
+
+/// What to do when a generated file does not match the schema, see `Config::validate_schema`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaViolation {
+    ///Do not write the file and fail the run once every locale is done
+    #[default]
+    Fail,
+    ///Log the violations and write the file anyway
+    Warn,
+}
+
+/// How a failed request to the provider is retried, see `Config::retry_policy`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum RetryPolicy {
+    ///Fail on the first error
+    #[default]
+    Never,
+    ///Wait a random delay up to `base` doubled on each retry, capped at `max`
+    ///
+    /// Errors of the request (4xx) other than 408 and 429 are not retried, eg: a wrong api key
+    ExponentialJitter {
+        ///Longest delay before the first retry
+        base: Duration,
+        ///Longest delay before any retry
+        max: Duration,
+        ///Retries before giving up
+        attempts: u32,
+    },
+    ///Called with the retry number, from 1, and the error. Returns the delay before the retry,
+    ///`None` to give up
+    Custom(RetryHook),
+}
+
+impl RetryPolicy {
+    ///Policy deciding each retry with a closure
+    ///
+    /// eg: give up on authentication errors, retry anything else ten times
+    /// ```
+    /// use std::time::Duration;
+    /// use rust_i18n_autotranslate::{config::RetryPolicy, error::TranslationError};
+    ///
+    /// let policy = RetryPolicy::custom(|attempt, error| match error {
+    ///     TranslationError::Provider(e) if e.code == 401 || e.code == 403 => None,
+    ///     _ => (attempt <= 10).then(|| Duration::from_millis(200)),
+    /// });
+    /// ```
+    pub fn custom<F>(policy: F) -> Self
+    where
+        F: Fn(u32, &TranslationError) -> Option<Duration> + Send + Sync + 'static,
+    {
+        RetryPolicy::Custom(Hook(Arc::new(policy)))
+    }
+}
+
+/// Chinese script, see `Config::zh_convert`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZhScript {
+    ///eg: `zh-CN`, `zh-SG`, `zh-Hans`
+    Simplified,
+    ///eg: `zh-TW`, `zh-HK`, `zh-Hant`
+    Traditional,
+}
+
+/// Tag handling options for DeepL, only the options that are set are sent
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TagOptions {
+    ///`xml` or `html`
+    pub tag_handling: Option<String>,
+    ///`v1` or `v2`
+    pub tag_handling_version: Option<String>,
+    ///Set to false to only split sentences on `splitting_tags`
+    pub outline_detection: Option<bool>,
+    ///Tags that always split sentences
+    pub splitting_tags: Option<Vec<String>>,
+    ///Tags that never split sentences
+    pub non_splitting_tags: Option<Vec<String>>,
+    ///Tags whose content is left untouched, eg: `code`
+    pub ignore_tags: Option<Vec<String>>,
+}
+
+/// Extensions of the locale files read by rust-i18n
+pub const DEFAULT_LOCALE_EXTENSIONS: [&str; 4] = ["json", "yml", "yaml", "toml"];
+
+/// Endpoint of a local DeepLX install
+pub const DEFAULT_DEEPLX_ENDPOINT: &str = "http://127.0.0.1:1188/translate";
+
+/// Name of the generated files, eg: `fr.json`
+pub const DEFAULT_OUTPUT_NAME_TEMPLATE: &str = "{locale}.{ext}";
+
+/// Length of the `PSEUDO` values in percent of the source
+pub const DEFAULT_PSEUDO_EXPANSION: u32 = 130;
+
+/// Callback set on the config
+///
+/// Hooks are compared by identity, two configs are only equal if they share the same hook.
+/// The callbacks are `Send + Sync`, a config with hooks is cloned and shared between threads
+/// like any other, eg: with `Config::max_concurrent_requests`.
+pub struct Hook<F: ?Sized>(pub Arc<F>);
+
+impl<F: ?Sized> Clone for Hook<F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook(..)")
+    }
 }
 
+impl<F: ?Sized> PartialEq for Hook<F> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<F: ?Sized> Eq for Hook<F> {}
+
+/// Agent set with [`Config::http_agent`]
+///
+/// Compared by identity like [`Hook`]
+#[derive(Clone)]
+pub struct HttpAgent(pub Arc<ureq::Agent>);
+
+impl fmt::Debug for HttpAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HttpAgent(..)")
+    }
+}
+
+impl PartialEq for HttpAgent {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for HttpAgent {}
+
+/// Called with the target locale before it is translated
+pub type BeforeLocaleHook = Hook<dyn Fn(&str) + Send + Sync>;
+
+/// Called with the target locale and its report entry once it is done
+pub type AfterLocaleHook = Hook<dyn Fn(&str, &TranslationReportEntry) + Send + Sync>;
+
+/// Called with the retry number and the error of a request, see [`RetryPolicy::custom`]
+pub type RetryHook = Hook<dyn Fn(u32, &TranslationError) -> Option<Duration> + Send + Sync>;
+
 /// Providers available for translation
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Config {
@@ -40,10 +468,136 @@ pub struct Config {
     pub source_locale: String,
     ///Languages to translate
     pub target_locales: Vec<String>,
-    ///Default: true
-    pub use_cache: bool,
+    ///Default: ReadWrite
+    pub cache_mode: CacheMode,
     ///Translation provider
     pub provider: TranslationProvider,
+    ///Providers tried in order for each target, see `Config::auto_route`
+    pub route_providers: Vec<TranslationProvider>,
+    ///Max billable characters sent in a run
+    pub max_chars: Option<usize>,
+    ///Extension of the generated files, defaults to the source file extension
+    pub output_format: Option<String>,
+    ///Default: true
+    pub generated_header: bool,
+    ///Add a `_dir` key (`ltr` or `rtl`) to the generated files
+    pub emit_direction: bool,
+    ///Encoding of generated `.strings` files
+    pub strings_encoding: StringsEncoding,
+    ///Encoding of generated `.properties` files
+    pub properties_encoding: PropertiesEncoding,
+    ///Default: Abort
+    pub on_unsupported: UnsupportedLanguage,
+    ///Ask the provider for the billed characters (DeepL)
+    pub track_billing: bool,
+    ///Report the keys changed in each file, default: false
+    pub report_changes: bool,
+    ///JSON Schema the generated files are validated against
+    pub schema_file: Option<PathBuf>,
+    ///Default: Fail
+    pub on_schema_violation: SchemaViolation,
+    ///Instructions for the DeepL next-gen model
+    pub deepl_instructions: Vec<String>,
+    ///DeepL model, not sent when not set
+    pub deepl_model_type: Option<String>,
+    ///Tag handling options for DeepL
+    pub deepl_tag_options: TagOptions,
+    ///Called before each target locale
+    pub before_locale: Option<BeforeLocaleHook>,
+    ///Called after each target locale, translated or skipped
+    pub after_locale: Option<AfterLocaleHook>,
+    ///Retries of the failed provider requests, default: no retry
+    pub retry_policy: RetryPolicy,
+    ///Extensions of the files considered as locales, see [`DEFAULT_LOCALE_EXTENSIONS`]
+    pub locale_extensions: Vec<String>,
+    ///DeepLX endpoint used when no DeepL api key is set
+    pub deeplx_endpoint: String,
+    ///Only translate the literal parts of ICU MessageFormat values
+    pub icu_messageformat: bool,
+    ///Keep `%{name}` and `{0}` placeholders away from the provider, default: false
+    pub protect_placeholders: bool,
+    ///Fail instead of warning when a translation loses or duplicates a placeholder
+    pub strict_placeholders: bool,
+    ///Keep the whitespace between tags of html values, default: false
+    pub preserve_html_whitespace: bool,
+    ///Send each batch as one numbered list mapped back by number, default: false
+    pub numbered_batches: bool,
+    ///Dictionary used by the `DICTIONARY` provider instead of the embedded one
+    pub dictionary_file: Option<PathBuf>,
+    ///Name of the generated files relative to the locales directory
+    pub output_name_template: String,
+    ///Case of the locale in the generated file names, default: AsIs
+    pub filename_case: FilenameCase,
+    ///Read the source from namespace files and write one combined file per locale
+    pub combine_output: bool,
+    ///Command run on each written file, eg: `["prettier", "--write", "{file}"]`
+    pub post_format_command: Vec<String>,
+    ///Unix mode set on each written file, eg: `0o640`
+    pub target_file_permissions: Option<u32>,
+    ///Make each written file read-only, default: false
+    pub read_only_output: bool,
+    ///Default: true, a disabled run is a no-op
+    pub enabled: bool,
+    ///Translate even when the source checksum did not change, default: false
+    pub force: bool,
+    ///Delete the locale files and cached translations of locales no longer targeted, default: false
+    pub prune_untracked: bool,
+    ///Remove the cached translation of a source value edited in place, default: false
+    pub evict_stale_cache: bool,
+    ///Chinese targets produced by converting the target in the other script
+    pub zh_convert: Option<ZhScript>,
+    ///Command converting the Chinese script, reads a json array of strings on stdin
+    pub zh_convert_command: Vec<String>,
+    ///Sidecar file of key -> description sent as context to the providers supporting it
+    pub context_file: Option<PathBuf>,
+    ///TMX file of reviewed translations used before the provider
+    pub translation_memory: Option<PathBuf>,
+    ///Add the locales that already have a file in the locales directory to the targets
+    pub targets_from_existing: bool,
+    ///Target locale -> source term -> target term, enforced for every provider
+    pub glossary: BTreeMap<String, BTreeMap<String, String>>,
+    ///Target locale -> file of source term -> target term, see `glossary_file`
+    pub glossary_files: HashMap<String, PathBuf>,
+    ///Default: None, the exact source value is the cache key
+    pub cache_key_normalization: CacheKeyNormalization,
+    ///Other sources in the locales directory with their own targets
+    pub groups: Vec<LocaleGroup>,
+    ///Only translate the keys under this dotted path
+    pub only_path: Option<String>,
+    ///DeepL glossary id per (source, target) language pair
+    pub deepl_glossaries: HashMap<(String, String), String>,
+    ///Fail instead of falling back to the keyless web or local provider
+    pub require_api_key: bool,
+    ///Length of the `PSEUDO` values in percent of the source, see `Config::pseudo_expansion`
+    pub pseudo_expansion: u32,
+    ///Price per million characters in millionths of the currency, for `TranslationAPI::estimate`
+    pub price_per_million: Option<u64>,
+    ///Default: Always
+    pub overwrite: Overwrite,
+    ///Send the values that failed in a previous run again
+    pub retry_failed: bool,
+    ///Keep the autogen cache in memory, nothing is read or written to disk
+    pub in_memory_cache: bool,
+    ///Default: Auto
+    pub deepl_plan: DeepLPlan,
+    ///Default: EnUs
+    pub deepl_default_english: DeepLEnglish,
+    ///Default: PtBr
+    pub deepl_default_portuguese: DeepLPortuguese,
+    ///Keys whose existing translations are never replaced
+    pub locked_keys: Vec<String>,
+    ///Default: KeepAlways
+    pub lock_policy: LockPolicy,
+    ///Chunks of a locale sent at once, default: 1
+    pub max_concurrent_requests: usize,
+    ///Keys of a locale translated before the cache is saved, all at once when not set
+    pub batch_size: Option<usize>,
+    ///Agent doing the requests to the providers, a default one when not set
+    pub http_agent: Option<HttpAgent>,
+    ///Record the chunks translated so a stopped run resumes, default: false
+    pub resumable: bool,
+    ///Most verbose level the crate logs at, everything the logger accepts when not set
+    pub max_log_level: Option<LevelFilter>,
 }
 
 impl Default for Config {
@@ -52,8 +606,71 @@ impl Default for Config {
             locales_dir: "".into(),
             source_locale: "en".to_string(),
             target_locales: Default::default(),
-            use_cache: true,
+            cache_mode: CacheMode::ReadWrite,
             provider: Default::default(),
+            route_providers: vec![],
+            max_chars: None,
+            output_format: None,
+            generated_header: true,
+            emit_direction: false,
+            strings_encoding: StringsEncoding::Utf8,
+            properties_encoding: PropertiesEncoding::Latin1,
+            on_unsupported: UnsupportedLanguage::Abort,
+            track_billing: false,
+            report_changes: false,
+            schema_file: None,
+            on_schema_violation: SchemaViolation::Fail,
+            deepl_instructions: vec![],
+            deepl_model_type: None,
+            deepl_tag_options: TagOptions::default(),
+            before_locale: None,
+            after_locale: None,
+            retry_policy: RetryPolicy::Never,
+            locale_extensions: DEFAULT_LOCALE_EXTENSIONS.map(String::from).to_vec(),
+            deeplx_endpoint: DEFAULT_DEEPLX_ENDPOINT.to_string(),
+            icu_messageformat: false,
+            protect_placeholders: false,
+            strict_placeholders: false,
+            preserve_html_whitespace: false,
+            numbered_batches: false,
+            dictionary_file: None,
+            output_name_template: DEFAULT_OUTPUT_NAME_TEMPLATE.to_string(),
+            filename_case: FilenameCase::AsIs,
+            combine_output: false,
+            post_format_command: vec![],
+            target_file_permissions: None,
+            read_only_output: false,
+            enabled: true,
+            force: false,
+            prune_untracked: false,
+            evict_stale_cache: false,
+            zh_convert: None,
+            zh_convert_command: vec![],
+            context_file: None,
+            translation_memory: None,
+            targets_from_existing: false,
+            glossary: BTreeMap::new(),
+            glossary_files: HashMap::new(),
+            cache_key_normalization: CacheKeyNormalization::None,
+            groups: vec![],
+            only_path: None,
+            deepl_glossaries: HashMap::new(),
+            require_api_key: false,
+            pseudo_expansion: DEFAULT_PSEUDO_EXPANSION,
+            price_per_million: None,
+            overwrite: Overwrite::Always,
+            retry_failed: false,
+            in_memory_cache: false,
+            deepl_plan: DeepLPlan::Auto,
+            deepl_default_english: DeepLEnglish::EnUs,
+            deepl_default_portuguese: DeepLPortuguese::PtBr,
+            locked_keys: Default::default(),
+            lock_policy: LockPolicy::KeepAlways,
+            max_concurrent_requests: 1,
+            batch_size: None,
+            http_agent: None,
+            resumable: false,
+            max_log_level: None,
         }
     }
 }
@@ -65,8 +682,71 @@ impl Config {
             locales_dir: "".into(),
             source_locale: "en".to_string(),
             target_locales: vec![],
-            use_cache: true,
+            cache_mode: CacheMode::ReadWrite,
             provider: TranslationProvider::GOOGLE,
+            route_providers: vec![],
+            max_chars: None,
+            output_format: None,
+            generated_header: true,
+            emit_direction: false,
+            strings_encoding: StringsEncoding::Utf8,
+            properties_encoding: PropertiesEncoding::Latin1,
+            on_unsupported: UnsupportedLanguage::Abort,
+            track_billing: false,
+            report_changes: false,
+            schema_file: None,
+            on_schema_violation: SchemaViolation::Fail,
+            deepl_instructions: vec![],
+            deepl_model_type: None,
+            deepl_tag_options: TagOptions::default(),
+            before_locale: None,
+            after_locale: None,
+            retry_policy: RetryPolicy::Never,
+            locale_extensions: DEFAULT_LOCALE_EXTENSIONS.map(String::from).to_vec(),
+            deeplx_endpoint: DEFAULT_DEEPLX_ENDPOINT.to_string(),
+            icu_messageformat: false,
+            protect_placeholders: false,
+            strict_placeholders: false,
+            preserve_html_whitespace: false,
+            numbered_batches: false,
+            dictionary_file: None,
+            output_name_template: DEFAULT_OUTPUT_NAME_TEMPLATE.to_string(),
+            filename_case: FilenameCase::AsIs,
+            combine_output: false,
+            post_format_command: vec![],
+            target_file_permissions: None,
+            read_only_output: false,
+            enabled: true,
+            force: false,
+            prune_untracked: false,
+            evict_stale_cache: false,
+            zh_convert: None,
+            zh_convert_command: vec![],
+            context_file: None,
+            translation_memory: None,
+            targets_from_existing: false,
+            glossary: BTreeMap::new(),
+            glossary_files: HashMap::new(),
+            cache_key_normalization: CacheKeyNormalization::None,
+            groups: vec![],
+            only_path: None,
+            deepl_glossaries: HashMap::new(),
+            require_api_key: false,
+            pseudo_expansion: DEFAULT_PSEUDO_EXPANSION,
+            price_per_million: None,
+            overwrite: Overwrite::Always,
+            retry_failed: false,
+            in_memory_cache: false,
+            deepl_plan: DeepLPlan::Auto,
+            deepl_default_english: DeepLEnglish::EnUs,
+            deepl_default_portuguese: DeepLPortuguese::PtBr,
+            locked_keys: Default::default(),
+            lock_policy: LockPolicy::KeepAlways,
+            max_concurrent_requests: 1,
+            batch_size: None,
+            http_agent: None,
+            resumable: false,
+            max_log_level: None,
         }
     }
 
@@ -81,6 +761,17 @@ impl Config {
         self
     }
 
+    /// Path to directory where the locales are located, relative to `CARGO_MANIFEST_DIR`
+    ///
+    /// Resolves the same from `build.rs` and from a binary run elsewhere.
+    /// Relative to the working directory when `CARGO_MANIFEST_DIR` is not set
+    pub fn locales_directory_relative_to_manifest<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        match std::env::var_os("CARGO_MANIFEST_DIR") {
+            Some(manifest_dir) => self.locales_directory(PathBuf::from(manifest_dir).join(p)),
+            None => self.locales_directory(p),
+        }
+    }
+
     /// Language to translate from
     pub fn source_lang<S: Into<String>>(&mut self, lang: S) -> &mut Self {
         self.source_locale = lang.into();
@@ -93,6 +784,11 @@ impl Config {
         self
     }
 
+    ///Languages to translate to, borrowed
+    pub fn targets(&self) -> Vec<&str> {
+        self.target_locales.iter().map(String::as_str).collect()
+    }
+
     ///Languages to translate to -- add many
     pub fn add_target_langs<S: Into<String>>(&mut self, langs: Vec<S>) -> &mut Self {
         self.target_locales
@@ -100,9 +796,196 @@ impl Config {
         self
     }
 
-    ///Use cache or not
+    ///Normalize the source values used as cache keys, the translations are stored as is
+    ///
+    /// Values differing only by surrounding whitespace (or unicode composition with `TrimNfc`)
+    /// share a cache entry
+    pub fn cache_key_normalization(&mut self, normalization: CacheKeyNormalization) -> &mut Self {
+        self.cache_key_normalization = normalization;
+        self
+    }
+
+    ///Translate into every locale that already has a file in the locales directory, default: false
+    ///
+    /// Resolved on `build`, new languages still have to be added with [`Config::add_target_lang`]
+    pub fn targets_from_existing(&mut self, from_existing: bool) -> &mut Self {
+        self.targets_from_existing = from_existing;
+        self
+    }
+
+    ///DeepL glossaries keyed by (source, target) language, eg: `("en", "de")`
+    ///
+    /// The glossary of each target locale is picked automatically, none is sent when the
+    /// pair has no glossary
+    pub fn deepl_glossaries(&mut self, glossaries: HashMap<(String, String), String>) -> &mut Self {
+        self.deepl_glossaries = glossaries;
+        self
+    }
+
+    ///Never replace the existing translation of the key, eg: reviewed by a human
+    ///
+    /// The key is still translated while a target file has no value for it
+    pub fn lock_key<S: Into<String>>(&mut self, key: S) -> &mut Self {
+        self.locked_keys.push(key.into());
+        self
+    }
+
+    ///What happens to a locked key when its source value changes, default: KeepAlways
+    ///
+    /// The source value is recorded in the cache when the key is first kept
+    pub fn lock_policy(&mut self, policy: LockPolicy) -> &mut Self {
+        self.lock_policy = policy;
+        self
+    }
+
+    ///Send up to `max` chunks of a locale concurrently, default: 1
+    ///
+    /// The translations are reassembled in the source order. Worth it for locales with
+    /// thousands of keys, the rate limits of the provider still apply
+    pub fn max_concurrent_requests(&mut self, max: usize) -> &mut Self {
+        self.max_concurrent_requests = max.max(1);
+        self
+    }
+
+    ///Translate the keys of a locale in batches of `keys`, for very large catalogs
+    ///
+    /// Only one batch of translations is in flight at a time. When the cache is written (the
+    /// default) each batch is added to the cache, which is saved as a checkpoint, a stopped run
+    /// reads the batches done from the cache and resumes after them. The target file is only
+    /// written once the locale is done. Every checkpoint saves the whole cache, so keep the
+    /// batches large. A batch that fails is translated value by value like a whole locale
+    /// would be
+    pub fn batch_size(&mut self, keys: usize) -> &mut Self {
+        self.batch_size = Some(keys.max(1));
+        self
+    }
+
+    ///Agent used by every provider for its requests
+    ///
+    /// Configure proxies, timeouts, TLS or the user agent on the agent. Without it each
+    /// request uses a default agent
+    pub fn http_agent(&mut self, agent: ureq::Agent) -> &mut Self {
+        self.http_agent = Some(HttpAgent(Arc::new(agent)));
+        self
+    }
+
+    ///Record every chunk translated in a journal in the locales directory, default: false
+    ///
+    /// A run that was stopped (crash, network down, budget exceeded) resumes from the journal
+    /// instead of sending the chunks again, even in the middle of a locale.
+    /// The journal is read once per run and removed once the run completes, by `translate`,
+    /// `translate_since` or `translate_from_map`
+    pub fn resumable(&mut self, resumable: bool) -> &mut Self {
+        self.resumable = resumable;
+        self
+    }
+
+    ///Most verbose level the crate logs at, to quiet it without touching the rest of the app
+    ///
+    /// This is a cap, records above it are dropped by the crate. It can't make the crate more
+    /// verbose than the logger, that needs the logger to accept the crate target,
+    /// eg: `RUST_LOG=warn,rust_i18n_autotranslate=debug`
+    pub fn max_log_level(&mut self, level: LevelFilter) -> &mut Self {
+        self.max_log_level = Some(level);
+        self
+    }
+
+    ///DeepL api to use, default: Auto
+    ///
+    /// `Auto` prefers the free api when both keys are set, `Free` and `Pro` force the
+    /// api and return an error when its key is missing
+    pub fn deepl_plan(&mut self, plan: DeepLPlan) -> &mut Self {
+        self.deepl_plan = plan;
+        self
+    }
+
+    ///English variant DeepL translates `en` targets into, default: EnUs
+    ///
+    /// `en-GB` and `en-US` targets keep their variant, the source stays `EN`
+    pub fn deepl_default_english(&mut self, english: DeepLEnglish) -> &mut Self {
+        self.deepl_default_english = english;
+        self
+    }
+
+    ///Portuguese variant DeepL translates `pt` targets into, default: PtBr
+    ///
+    /// `pt-BR` and `pt-PT` targets keep their variant, the source stays `PT`
+    pub fn deepl_default_portuguese(&mut self, portuguese: DeepLPortuguese) -> &mut Self {
+        self.deepl_default_portuguese = portuguese;
+        self
+    }
+
+    ///Return an error when the api key of the provider is missing or empty, default: false
+    ///
+    /// By default google falls back to google translate web, DeepL to deeplx and LibreTranslate
+    /// to a local install
+    pub fn require_api_key(&mut self, required: bool) -> &mut Self {
+        self.require_api_key = required;
+        self
+    }
+
+    ///Length of the `PSEUDO` values as a factor of the source, default: 1.3
+    ///
+    /// Use ~1.4 to test German or Finnish length strings. The padding is added around the
+    /// literal text, placeholders are never touched. Below 1.0 no padding is added
+    pub fn pseudo_expansion(&mut self, factor: f64) -> &mut Self {
+        self.pseudo_expansion = (factor.max(0.0) * 100.0).round() as u32;
+        self
+    }
+
+    ///Price of a million characters of the provider, eg: 20.0 for DeepL Pro in USD
+    ///
+    /// `TranslationAPI::estimate` reports the approximate cost of a run in the same currency,
+    /// the cost is left out when no price is set
+    pub fn price_per_million(&mut self, price: f64) -> &mut Self {
+        self.price_per_million = Some((price.max(0.0) * 1_000_000.0).round() as u64);
+        self
+    }
+
+    ///Translate another source locale of the locales directory into its own targets
+    ///
+    /// Each group has its own checksum, the files of a group are never deleted by another one.
+    /// The groups share `max_chars`, a group that fails does not stop the others.
+    /// eg: `en` -> `fr`, `de` with `ja` -> `ko`, `zh`
+    pub fn add_group<S: Into<String>>(&mut self, source: S, targets: Vec<S>) -> &mut Self {
+        self.groups.push(LocaleGroup {
+            source_locale: source.into(),
+            target_locales: targets.into_iter().map(|s| s.into()).collect(),
+        });
+        self
+    }
+
+    ///Only translate the subtree at the dotted path, eg: `menu.file`
+    ///
+    /// The other keys of the existing target files are kept as is. The checksum of the source
+    /// is not updated, the next full run still translates the keys edited outside the path
+    pub fn only_path<S: Into<String>>(&mut self, path: S) -> &mut Self {
+        self.only_path = Some(path.into());
+        self
+    }
+
+    ///Use cache or not, shorthand for `cache_mode(ReadWrite)` and `cache_mode(Off)`
     pub fn use_cache(&mut self, cache: bool) -> &mut Self {
-        self.use_cache = cache;
+        self.cache_mode = if cache {
+            CacheMode::ReadWrite
+        } else {
+            CacheMode::Off
+        };
+        self
+    }
+
+    ///Keep the autogen cache in memory for the run, default: false
+    ///
+    /// `.autogen.cache` is never read, created or written, eg: read-only file systems or
+    /// sandboxed CI. Without the stored checksum every run translates the source again
+    pub fn in_memory_cache(&mut self, in_memory: bool) -> &mut Self {
+        self.in_memory_cache = in_memory;
+        self
+    }
+
+    ///How the autogen cache is used
+    pub fn cache_mode(&mut self, mode: CacheMode) -> &mut Self {
+        self.cache_mode = mode;
         self
     }
 
@@ -112,14 +995,782 @@ impl Config {
         self
     }
 
+    ///Translate each target with the first of `providers` supporting it, default: none
+    ///
+    /// eg: `auto_route(vec![TranslationProvider::DEEPL, TranslationProvider::GOOGLE])` translates
+    /// `de` with DeepL and `haw` with Google. A target none of them supports uses the
+    /// `translation_provider`. The report records the provider of each locale, see
+    /// `TranslationReportEntry::provider`
+    pub fn auto_route(&mut self, providers: Vec<TranslationProvider>) -> &mut Self {
+        self.route_providers = providers;
+        self
+    }
+
+    ///Stop the run before the billable characters sent go over the budget
+    ///
+    /// Locales translated before the budget is hit are kept
+    pub fn max_chars(&mut self, budget: usize) -> &mut Self {
+        self.max_chars = Some(budget);
+        self
+    }
+
+    ///Extension of the generated files, see [`OutputFormat::all`]
+    ///
+    /// Defaults to the format of the source file
+    pub fn output_format<S: Into<String>>(&mut self, format: S) -> &mut Self {
+        self.output_format = Some(format.into());
+        self
+    }
+
+    ///Mark generated files with a header comment, for formats that support comments (json5, strings)
+    pub fn generated_header(&mut self, header: bool) -> &mut Self {
+        self.generated_header = header;
+        self
+    }
+
+    ///Add the text direction of the target language to the generated files as `_dir: rtl` or `_dir: ltr`
+    pub fn emit_direction(&mut self, emit: bool) -> &mut Self {
+        self.emit_direction = emit;
+        self
+    }
+
+    ///Send the values that could not be translated in a previous run again, default: false
+    ///
+    /// When a batch fails its halves are sent again until the failing values are alone, they are
+    /// recorded in the cache with the reason and written as the source value. They are skipped
+    /// on the next runs unless this is set. An auth or quota error is not split
+    pub fn retry_failed(&mut self, retry: bool) -> &mut Self {
+        self.retry_failed = retry;
+        self
+    }
+
+    ///When the generated files are written, default: Always
+    ///
+    /// The report records the files that were kept, see `TranslationReportEntry::file`
+    pub fn overwrite(&mut self, overwrite: Overwrite) -> &mut Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    ///Encoding of generated Apple `.strings` files, other formats are always UTF-8
+    pub fn strings_encoding(&mut self, encoding: StringsEncoding) -> &mut Self {
+        self.strings_encoding = encoding;
+        self
+    }
+
+    ///Encoding of generated Java `.properties` files, default: ISO-8859-1
+    pub fn properties_encoding(&mut self, encoding: PropertiesEncoding) -> &mut Self {
+        self.properties_encoding = encoding;
+        self
+    }
+
+    ///What to do when the provider does not support one of the target languages
+    pub fn on_unsupported(&mut self, policy: UnsupportedLanguage) -> &mut Self {
+        self.on_unsupported = policy;
+        self
+    }
+
+    ///Report the characters billed by the provider in the `TranslationReport`
+    ///
+    /// Only DeepL reports billed characters, other providers ignore it
+    pub fn track_billing(&mut self, track: bool) -> &mut Self {
+        self.track_billing = track;
+        self
+    }
+
+    ///Diff each file against its previous version and report the keys added, changed and
+    /// removed, see `TranslationReportEntry::changes`. Default: false
+    ///
+    /// Key-level and independent of the file format, unlike a git diff
+    pub fn report_changes(&mut self, report: bool) -> &mut Self {
+        self.report_changes = report;
+        self
+    }
+
+    ///Validate each generated file against a JSON Schema before it is written
+    ///
+    /// The nested keys are validated whatever the output format, so one schema covers
+    /// json, yaml and toml files. The failing keys of each locale are listed in
+    /// `TranslationReportEntry::schema_errors`, see `on_schema_violation`
+    pub fn validate_schema<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.schema_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    ///What to do when a generated file does not match the schema, default: Fail
+    pub fn on_schema_violation(&mut self, policy: SchemaViolation) -> &mut Self {
+        self.on_schema_violation = policy;
+        self
+    }
+
+    ///Custom instructions for DeepL, eg: "Keep a friendly tone", "Do not translate product names"
+    ///
+    /// Custom instructions only work with DeepL's next-gen model, set
+    /// `deepl_model_type("quality_optimized")` to make sure they are applied.
+    /// DeepL accepts up to 10 instructions of max 300 characters each and only for some target languages.
+    pub fn deepl_instructions<S: Into<String>>(&mut self, instructions: Vec<S>) -> &mut Self {
+        self.deepl_instructions = instructions.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    ///Model used by DeepL, eg: `quality_optimized`, `prefer_quality_optimized` or `latency_optimized`
+    ///
+    /// Not sent when not set, DeepL picks the model of the account
+    pub fn deepl_model_type<S: Into<String>>(&mut self, model_type: S) -> &mut Self {
+        self.deepl_model_type = Some(model_type.into());
+        self
+    }
+
+    ///Tag handling options for DeepL
+    ///
+    /// The `*_tags` options and `outline_detection` only take effect with `tag_handling: xml`
+    pub fn deepl_tag_options(&mut self, options: TagOptions) -> &mut Self {
+        self.deepl_tag_options = options;
+        self
+    }
+
+    ///Called with the target locale before it is translated
+    pub fn before_locale<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.before_locale = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    ///Called with the target locale and its report entry once it is done
+    ///
+    /// Called by every run that translates locales, `TranslationAPI::translate_from_map` included.
+    /// Also called for skipped locales, for the locales that failed and for the locale the budget
+    /// stopped the run at, the reason is set in the entry
+    pub fn after_locale<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&str, &TranslationReportEntry) + Send + Sync + 'static,
+    {
+        self.after_locale = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    ///Retry the failed requests to the provider, default: `RetryPolicy::Never`
+    ///
+    /// eg: `RetryPolicy::ExponentialJitter { base: Duration::from_millis(500), max:
+    /// Duration::from_secs(10), attempts: 5 }`, or [`RetryPolicy::custom`] for full control.
+    /// Only the failed chunk is sent again, the retries are counted in
+    /// `TranslationReportEntry::retries` and their characters against `max_chars`
+    pub fn retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    ///Extensions of the files considered as locales when looking for the source file
+    /// and verifying the target files
+    ///
+    /// The source file still needs to be in a format read by rust-i18n (json, yml, yaml, toml)
+    pub fn locale_extensions<S: Into<String>>(&mut self, extensions: Vec<S>) -> &mut Self {
+        self.locale_extensions = extensions.into_iter().map(|e| e.into()).collect();
+        self
+    }
+
+    ///DeepLX endpoint used as the DeepL fallback, default: `http://127.0.0.1:1188/translate`
+    pub fn deeplx_endpoint<S: Into<String>>(&mut self, endpoint: S) -> &mut Self {
+        self.deeplx_endpoint = endpoint.into();
+        self
+    }
+
+    ///Treat values as ICU MessageFormat messages
+    ///
+    /// The `plural`, `select` and `selectordinal` structure is kept and only the literal
+    /// sub-messages are translated, eg: `{count, plural, one {# item} other {# items}}`
+    pub fn icu_messageformat(&mut self, enabled: bool) -> &mut Self {
+        self.icu_messageformat = enabled;
+        self
+    }
+
+    ///Send `%{name}` and positional `{0}` placeholders as opaque tokens, default: false
+    ///
+    /// The provider can not translate or reformat a token, it is swapped back for the
+    /// placeholder in the translation. See `strict_placeholders` for a token that is lost.
+    pub fn protect_placeholders(&mut self, protect: bool) -> &mut Self {
+        self.protect_placeholders = protect;
+        self
+    }
+
+    ///Fail the translation when a placeholder is lost or duplicated by the provider
+    ///
+    /// Only for the placeholders of `protect_placeholders`, by default a warning is logged
+    /// if one does not come back exactly once.
+    pub fn strict_placeholders(&mut self, strict: bool) -> &mut Self {
+        self.strict_placeholders = strict;
+        self
+    }
+
+    ///Protect the whitespace-only text between two tags like a placeholder
+    ///
+    /// For html values, eg: with the `html` tag handling of DeepL. Providers normalize the
+    /// whitespace between tags, the space of `<b>Hello</b> <i>World</i>` would be lost.
+    pub fn preserve_html_whitespace(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_html_whitespace = preserve;
+        self
+    }
+
+    ///Send each batch as one numbered list (`1. ...\n2. ...`) and map the output back by number
+    ///
+    /// For providers that may reorder, merge or split the items of a batch, eg: LLM based ones.
+    /// A batch whose numbering can't be recovered fails instead of being misaligned.
+    pub fn numbered_batches(&mut self, numbered: bool) -> &mut Self {
+        self.numbered_batches = numbered;
+        self
+    }
+
+    ///JSON dictionary for the `DICTIONARY` provider, replaces the embedded one
+    ///
+    /// Format: `{ "fr": { "Hello": "Bonjour" } }`, target locale then source value
+    pub fn dictionary_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.dictionary_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    ///Always translate `source_term` to `target_term` for the target locale
+    ///
+    /// Terms are matched as whole words and enforced client side, so it works with every provider.
+    /// `fr` terms also apply to `fr-CA` when it has none of its own
+    pub fn add_glossary_term<L, S, T>(
+        &mut self,
+        locale: L,
+        source_term: S,
+        target_term: T,
+    ) -> &mut Self
+    where
+        L: Into<String>,
+        S: Into<String>,
+        T: Into<String>,
+    {
+        self.glossary
+            .entry(locale.into())
+            .or_default()
+            .insert(source_term.into(), target_term.into());
+        self
+    }
+
+    ///Glossary file of a target locale, json, yaml or toml of source term -> target term
+    ///
+    /// Applied like the `add_glossary_term` terms, which win over the file for the same term.
+    /// The `fr` file also applies to `fr-CA` when it has none of its own, a locale without a
+    /// glossary gets no replacement. eg: `{ "OK": "D'accord", "Dashboard": "Tableau de bord" }`
+    pub fn glossary_file<L: Into<String>, P: AsRef<Path>>(
+        &mut self,
+        locale: L,
+        path: P,
+    ) -> &mut Self {
+        self.glossary_files
+            .insert(locale.into(), path.as_ref().to_path_buf());
+        self
+    }
+
+    ///Name of the generated files relative to the locales directory, default: `{locale}.{ext}`
+    ///
+    /// Tokens: `{locale}` target locale, `{ext}` output extension, `{source}` source locale.
+    /// Subdirectories are created as needed, eg: `values-{locale}/strings.xml`
+    pub fn output_name_template<S: Into<String>>(&mut self, template: S) -> &mut Self {
+        self.output_name_template = template.into();
+        self
+    }
+
+    ///Case of the locale in the generated file names, default: AsIs
+    ///
+    /// The provider still receives the locale as given, eg: with `zh-cn` as target and
+    /// `FilenameCase::Bcp47` the file is `zh-CN.yml`. rust-i18n matches file names case
+    /// sensitively. The report records the name, see `TranslationReportEntry::file_name`
+    pub fn filename_case(&mut self, case: FilenameCase) -> &mut Self {
+        self.filename_case = case;
+        self
+    }
+
+    ///Read the source from the namespace files of `<locales_dir>/<source>/`, eg: `en/common.yml`,
+    /// `en/errors.yml`, and write a single file per locale with each namespace under its file
+    /// name, eg: `fr.json` with `common` and `errors` keys. Default: false
+    ///
+    /// The run fails if two namespace files define the same key. Without an `output_format`
+    /// the combined files are json.
+    pub fn combine_output(&mut self, combine: bool) -> &mut Self {
+        self.combine_output = combine;
+        self
+    }
+
+    ///Command run on each generated file after it is written, eg: a formatter
+    ///
+    /// `{file}` in the arguments is replaced by the path of the file, the path is added as the
+    /// last argument otherwise. eg: `vec!["prettier", "--write", "{file}"]` or
+    /// `vec!["dprint", "fmt"]`. The run fails when the command exits with an error
+    pub fn post_format_command<S: Into<String>>(&mut self, command: Vec<S>) -> &mut Self {
+        self.post_format_command = command.into_iter().map(Into::into).collect();
+        self
+    }
+
+    ///Mode of the generated files, eg: `0o640`
+    ///
+    /// Set after the file is written and formatted. Only applies on unix, a no-op elsewhere.
+    pub fn target_file_permissions(&mut self, mode: u32) -> &mut Self {
+        self.target_file_permissions = Some(mode);
+        self
+    }
+
+    ///Make the generated files read-only to discourage manual edits. Default: false
+    ///
+    /// The next runs make the file writable again before updating it.
+    pub fn read_only_output(&mut self, read_only: bool) -> &mut Self {
+        self.read_only_output = read_only;
+        self
+    }
+
+    ///Run the translations, default: true
+    ///
+    /// A disabled run returns right away without touching the files, the cache or the network.
+    /// eg: `enabled(std::env::var("RUN_TRANSLATIONS").is_ok())` to only spend on the api when asked in CI
+    pub fn enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        self
+    }
+
+    ///Translate even when the source file did not change since the last run, default: false
+    ///
+    /// For when another input changed, eg: the provider, the glossary or the formality.
+    /// Cached translations are still reused, use `cache_mode(CacheMode::WriteOnly)` for
+    /// fresh ones
+    pub fn force(&mut self, force: bool) -> &mut Self {
+        self.force = force;
+        self
+    }
+
+    ///Delete the files and cached translations of the locales that are not a source or target
+    ///of the config. Default: false
+    ///
+    /// Without it a locale left out of `target_locales` is not touched, eg: a run for `fr`
+    /// alone keeps `de.json` and its cached translations.
+    pub fn prune_untracked(&mut self, prune: bool) -> &mut Self {
+        self.prune_untracked = prune;
+        self
+    }
+
+    ///Remove the cached translation of the old value when a source value is edited. Default: false
+    ///
+    /// eg: `hello: Hello` edited to `hello: Hi` removes `Hello` from the cache once `Hi` is
+    /// translated. The edits are found with a checksum of the value of each key recorded in the
+    /// cache, the values of removed keys are kept, see `TranslationAPI::prune_cache` for those.
+    pub fn evict_stale_cache(&mut self, evict: bool) -> &mut Self {
+        self.evict_stale_cache = evict;
+        self
+    }
+
+    ///Produce the Chinese targets in `script` by converting the translation of the other script
+    ///with a local command instead of sending them to the provider
+    ///
+    /// eg: with `zh-CN` and `zh-TW` as targets, `zh_convert(ZhScript::Traditional,
+    /// vec!["opencc", "-c", "s2twp.json"])` translates `zh-CN` and converts it to `zh-TW`. The
+    /// command reads the values as a json array on stdin and writes the converted array on
+    /// stdout, OpenCC leaves the json syntax alone. A target without a counterpart in the other
+    /// script is translated as usual. The report sets `converted_from` on the converted locales.
+    pub fn zh_convert<S: Into<String>>(&mut self, script: ZhScript, command: Vec<S>) -> &mut Self {
+        self.zh_convert = Some(script);
+        self.zh_convert_command = command.into_iter().map(Into::into).collect();
+        self
+    }
+
+    ///TMX file of human reviewed translations, eg: exported by a CAT tool
+    ///
+    /// Before a locale is translated its source values are looked up in the memory, exact
+    /// matches are used and only the other values are sent to the provider. The matches are
+    /// added to the cache, the translations already cached are kept. Needs a `cache_mode`
+    /// that reads, see also `TranslationAPI::import_tmx`
+    pub fn translation_memory<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.translation_memory = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    ///Sidecar file mapping the source keys to a description, eg: `locales/en.descriptions.yml`
+    ///
+    /// The description of a key is sent as context to the providers supporting it (DeepL),
+    /// other providers ignore it
+    pub fn context_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.context_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// DeepL glossary of the language pair, variants like `en-GB` use the `en` glossary
+    ///
+    /// The pair as given wins over the other pairs of the same languages, which are taken in
+    /// alphabetical order, eg: `(en, fr-CA)` before `(en, fr-FR)`
+    pub fn deepl_glossary(&self, source_lang: &str, target_lang: &str) -> Option<&String> {
+        let primary = |lang: &str| {
+            lang.split(['-', '_'])
+                .next()
+                .unwrap_or(lang)
+                .to_ascii_lowercase()
+        };
+        let pair = (primary(source_lang), primary(target_lang));
+
+        let exact = |(source, target): &(String, String)| {
+            source.eq_ignore_ascii_case(source_lang) && target.eq_ignore_ascii_case(target_lang)
+        };
+
+        self.deepl_glossaries
+            .iter()
+            .filter(|((source, target), _)| (primary(source), primary(target)) == pair)
+            .min_by_key(|(languages, _)| (!exact(languages), *languages))
+            .map(|(_, glossary_id)| glossary_id)
+    }
+
+    /// Source or target of the config or of one of its groups, also in the case of the file names
+    pub fn is_known_locale(&self, locale: &str) -> bool {
+        let known = |l: &String| l == locale || self.filename_case.apply(l) == locale;
+        known(&self.source_locale)
+            || self.target_locales.iter().any(known)
+            || self
+                .groups
+                .iter()
+                .any(|group| known(&group.source_locale) || group.target_locales.iter().any(known))
+    }
+
+    /// Config translating a target with its provider, see `Config::auto_route`
+    pub fn routed(&self, target_locale: &str) -> Cow<'_, Config> {
+        let provider = self
+            .route_providers
+            .iter()
+            .find(|provider| normalize_lang(provider, target_locale).is_ok());
+        match provider {
+            Some(provider) if *provider != self.provider => {
+                let mut routed = self.clone();
+                routed.provider = provider.clone();
+                Cow::Owned(routed)
+            }
+            _ => Cow::Borrowed(self),
+        }
+    }
+
+    /// Target in the other Chinese script a target is converted from, see `Config::zh_convert`
+    pub fn converted_from(&self, locale: &str) -> Option<&str> {
+        let script = self.zh_convert?;
+        if zh_script(locale) != Some(script) {
+            return None;
+        }
+
+        self.target_locales
+            .iter()
+            .map(String::as_str)
+            .find(|target| zh_script(target).is_some_and(|other| other != script))
+    }
+
+    /// Hash of the settings the generated files depend on, besides the source file
+    ///
+    /// A run translates again when it changed, eg: another provider or glossary
+    pub fn settings_hash(&self) -> String {
+        let deepl_glossaries: BTreeMap<_, _> = self.deepl_glossaries.iter().collect();
+        //the content of the files, a term edited in place changes the translations too
+        let dictionary_file = self
+            .dictionary_file
+            .as_ref()
+            .map(|path| sha256::try_digest(path).ok());
+        let glossary_files: BTreeMap<_, _> = self
+            .glossary_files
+            .iter()
+            .map(|(locale, path)| (locale, sha256::try_digest(path).ok()))
+            .collect();
+        let settings = (
+            (
+                (&self.provider, &self.route_providers),
+                (&self.deepl_instructions, &self.deepl_model_type),
+                &self.deepl_tag_options,
+                deepl_glossaries,
+                self.deepl_default_english,
+                self.deepl_default_portuguese,
+            ),
+            (
+                &self.glossary,
+                glossary_files,
+                &self.only_path,
+                &self.context_file,
+                &self.translation_memory,
+                dictionary_file,
+                self.pseudo_expansion,
+                self.icu_messageformat,
+                (
+                    self.protect_placeholders,
+                    self.preserve_html_whitespace,
+                    self.numbered_batches,
+                ),
+            ),
+            (
+                &self.output_format,
+                (&self.output_name_template, self.filename_case),
+                self.generated_header,
+                self.emit_direction,
+                self.strings_encoding,
+                self.properties_encoding,
+                self.combine_output,
+                &self.post_format_command,
+            ),
+            (&self.locked_keys, self.lock_policy),
+            (self.zh_convert, &self.zh_convert_command),
+        );
+
+        sha256::digest(format!("{settings:?}"))
+    }
+
     /// Build the config
     pub fn build(&self) -> Self {
+        let mut target_locales = self.target_locales.clone();
+        if self.targets_from_existing {
+            for locale in existing_locales(self) {
+                if !target_locales.contains(&locale) {
+                    target_locales.push(locale);
+                }
+            }
+        }
+
         Config {
             locales_dir: self.locales_dir.clone(),
             source_locale: self.source_locale.clone(),
-            target_locales: self.target_locales.clone(),
-            use_cache: self.use_cache,
+            target_locales,
+            cache_mode: self.cache_mode,
             provider: self.provider.clone(),
+            route_providers: self.route_providers.clone(),
+            max_chars: self.max_chars,
+            output_format: self.output_format.clone(),
+            generated_header: self.generated_header,
+            emit_direction: self.emit_direction,
+            strings_encoding: self.strings_encoding,
+            properties_encoding: self.properties_encoding,
+            on_unsupported: self.on_unsupported,
+            track_billing: self.track_billing,
+            report_changes: self.report_changes,
+            schema_file: self.schema_file.clone(),
+            on_schema_violation: self.on_schema_violation,
+            deepl_instructions: self.deepl_instructions.clone(),
+            deepl_model_type: self.deepl_model_type.clone(),
+            deepl_tag_options: self.deepl_tag_options.clone(),
+            before_locale: self.before_locale.clone(),
+            after_locale: self.after_locale.clone(),
+            retry_policy: self.retry_policy.clone(),
+            locale_extensions: self.locale_extensions.clone(),
+            deeplx_endpoint: self.deeplx_endpoint.clone(),
+            icu_messageformat: self.icu_messageformat,
+            protect_placeholders: self.protect_placeholders,
+            strict_placeholders: self.strict_placeholders,
+            preserve_html_whitespace: self.preserve_html_whitespace,
+            numbered_batches: self.numbered_batches,
+            dictionary_file: self.dictionary_file.clone(),
+            output_name_template: self.output_name_template.clone(),
+            filename_case: self.filename_case,
+            combine_output: self.combine_output,
+            post_format_command: self.post_format_command.clone(),
+            target_file_permissions: self.target_file_permissions,
+            read_only_output: self.read_only_output,
+            enabled: self.enabled,
+            force: self.force,
+            prune_untracked: self.prune_untracked,
+            evict_stale_cache: self.evict_stale_cache,
+            zh_convert: self.zh_convert,
+            zh_convert_command: self.zh_convert_command.clone(),
+            context_file: self.context_file.clone(),
+            translation_memory: self.translation_memory.clone(),
+            targets_from_existing: self.targets_from_existing,
+            glossary: self.glossary.clone(),
+            glossary_files: self.glossary_files.clone(),
+            cache_key_normalization: self.cache_key_normalization,
+            groups: self.groups.clone(),
+            only_path: self.only_path.clone(),
+            deepl_glossaries: self.deepl_glossaries.clone(),
+            require_api_key: self.require_api_key,
+            pseudo_expansion: self.pseudo_expansion,
+            price_per_million: self.price_per_million,
+            overwrite: self.overwrite,
+            retry_failed: self.retry_failed,
+            in_memory_cache: self.in_memory_cache,
+            deepl_plan: self.deepl_plan,
+            deepl_default_english: self.deepl_default_english,
+            deepl_default_portuguese: self.deepl_default_portuguese,
+            locked_keys: self.locked_keys.clone(),
+            lock_policy: self.lock_policy,
+            max_concurrent_requests: self.max_concurrent_requests,
+            batch_size: self.batch_size,
+            http_agent: self.http_agent.clone(),
+            resumable: self.resumable,
+            max_log_level: self.max_log_level,
         }
     }
 }
+
+#[test]
+fn test_hooks_compared_by_identity() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let config = Config::new()
+        .before_locale(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build();
+
+    let cloned = config.clone();
+    assert_eq!(config, cloned);
+    assert_ne!(config, Config::new().before_locale(|_| {}).build());
+
+    if let Some(hook) = &cloned.before_locale {
+        (hook.0)("fr");
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_config_send_sync() {
+    fn assert_send_sync<T: Send + Sync + Clone + Eq>() {}
+    assert_send_sync::<Config>();
+
+    let config = Config::new()
+        .before_locale(|_| {})
+        .after_locale(|_, _| {})
+        .build();
+    let cloned = std::thread::scope(|scope| scope.spawn(|| config.clone()).join().unwrap());
+    assert_eq!(config, cloned);
+}
+
+#[test]
+fn test_output_format_from_extension() {
+    for format in OutputFormat::all() {
+        assert_eq!(
+            OutputFormat::from_extension(format.extension()),
+            Some(*format)
+        );
+    }
+    assert_eq!(
+        OutputFormat::from_extension("YAML"),
+        Some(OutputFormat::Yaml)
+    );
+    assert_eq!(OutputFormat::from_extension("xml"), None);
+}
+
+#[test]
+fn test_locales_directory_relative_to_manifest() {
+    let config = Config::new()
+        .locales_directory_relative_to_manifest("src/config")
+        .build();
+
+    assert_eq!(
+        config.locales_dir,
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/config")
+            .normalize()
+            .unwrap()
+            .as_path()
+    );
+}
+
+#[test]
+fn test_cache_key_normalization() {
+    let trim = CacheKeyNormalization::Trim;
+    assert_eq!(trim.key("Save "), trim.key("Save\n"));
+    assert_ne!(trim.key("Caf\u{e9}"), trim.key("Cafe\u{301}"));
+
+    let nfc = CacheKeyNormalization::TrimNfc;
+    assert_eq!(nfc.key(" Cafe\u{301}"), nfc.key("Caf\u{e9}"));
+    assert_eq!(CacheKeyNormalization::None.key("Save "), "Save ");
+}
+
+#[test]
+fn test_deepl_glossary_per_pair() {
+    let config = Config::new()
+        .deepl_glossaries(HashMap::from([
+            (("en".to_string(), "de".to_string()), "gl-en-de".to_string()),
+            (("en".to_string(), "fr".to_string()), "gl-en-fr".to_string()),
+            (
+                ("en".to_string(), "pt-PT".to_string()),
+                "gl-en-pt-pt".to_string(),
+            ),
+            (
+                ("en".to_string(), "pt-BR".to_string()),
+                "gl-en-pt-br".to_string(),
+            ),
+        ]))
+        .build();
+
+    assert_eq!(
+        config.deepl_glossary("EN", "DE").map(|s| s.as_str()),
+        Some("gl-en-de")
+    );
+    assert_eq!(
+        config.deepl_glossary("en", "fr-CA").map(|s| s.as_str()),
+        Some("gl-en-fr")
+    );
+    assert_eq!(config.deepl_glossary("de", "en"), None);
+    assert_eq!(
+        config.deepl_glossary("en", "pt-PT").map(|s| s.as_str()),
+        Some("gl-en-pt-pt")
+    );
+    assert_eq!(
+        config.deepl_glossary("en", "pt").map(|s| s.as_str()),
+        Some("gl-en-pt-br")
+    );
+}
+
+#[test]
+fn test_cache_mode() {
+    assert_eq!(
+        Config::new().use_cache(false).build().cache_mode,
+        CacheMode::Off
+    );
+    assert_eq!(Config::new().build().cache_mode, CacheMode::ReadWrite);
+
+    assert!(CacheMode::ReadOnly.reads() && !CacheMode::ReadOnly.writes());
+    assert!(!CacheMode::WriteOnly.reads() && CacheMode::WriteOnly.writes());
+    assert!(!CacheMode::Off.reads() && !CacheMode::Off.writes());
+}
+
+#[test]
+fn test_pseudo_expansion_factor() {
+    assert_eq!(Config::new().build().pseudo_expansion, 130);
+    assert_eq!(
+        Config::new().pseudo_expansion(1.4).build().pseudo_expansion,
+        140
+    );
+    assert_eq!(
+        Config::new()
+            .pseudo_expansion(1.25)
+            .build()
+            .pseudo_expansion,
+        125
+    );
+    assert_eq!(
+        Config::new()
+            .pseudo_expansion(-1.0)
+            .build()
+            .pseudo_expansion,
+        0
+    );
+}
+
+#[test]
+fn test_http_agent() {
+    let agent = ureq::Agent::new_with_defaults();
+    let config = Config::new().http_agent(agent.clone()).build();
+
+    assert_eq!(config.clone(), config);
+    assert_ne!(config, Config::new().http_agent(agent).build());
+    assert_eq!(Config::new().build().http_agent, None);
+}
+
+#[test]
+fn test_settings_hash_glossary_content() {
+    let dir = TempDir::new("settings_hash_glossary_test");
+    let path = &dir.join("settings_hash_glossary_test.json");
+    std::fs::write(path, r#"{"Dashboard": "Tableau de bord"}"#).unwrap();
+    let config = Config::new().glossary_file("fr", path).build();
+
+    let before = config.settings_hash();
+    let unchanged = config.settings_hash();
+    std::fs::write(path, r#"{"Dashboard": "Tableau"}"#).unwrap();
+    let edited = config.settings_hash();
+
+    assert_eq!(before, unchanged);
+    assert_ne!(before, edited);
+}
@@ -0,0 +1,194 @@
+//!
+//! _Errors_
+//!
+//! Errors returned by the translation api
+//!
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Errors for the Translation API
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TranslationError {
+    #[error("Character budget of {budget} exceeded after sending {sent} characters")]
+    /// The next request would go over the budget set with `Config::max_chars`
+    BudgetExceeded {
+        /// Billable characters sent before the run was stopped
+        sent: usize,
+        /// The configured budget
+        budget: usize,
+    },
+    #[error("The language `{0}` is not supported by the provider")]
+    /// The target language is not supported and `Config::on_unsupported` is `Abort`
+    UnsupportedLanguage(String),
+    #[error("No file found for the source locale `{0}`")]
+    /// The locales directory has no file for the source locale
+    SourceLocaleNotFound(String),
+    #[error("Could not parse the source file `{file}`: {error}")]
+    /// The source file exists but is not valid, the error names the line
+    SourceParse {
+        /// Path of the source file
+        file: String,
+        /// Parser error
+        error: String,
+    },
+    #[error("The key `{0}` is defined by more than one namespace file")]
+    /// Two source namespace files define the same key, see `Config::combine_output`
+    KeyCollision(String),
+    #[error("Unsupported locale file format `{0}`")]
+    /// The source or output extension is not a supported format, see `OutputFormat::all`
+    UnsupportedFormat(String),
+    #[error("The generated files do not match the schema: {}", schema_violations(.0))]
+    /// Generated files failed `Config::validate_schema`, the violations keyed by locale
+    SchemaViolation(BTreeMap<String, Vec<String>>),
+    #[error("The post format command failed: {0}")]
+    /// `Config::post_format_command` exited with an error, the file is written unformatted
+    FormatCommand(String),
+    #[error("The translation failed for {}", locale_errors(.0))]
+    /// The provider failed to translate some locales, the others were written. The errors keyed
+    /// by locale, see `SkipReason::Failed`
+    LocalesFailed(BTreeMap<String, String>),
+    #[error(transparent)]
+    /// The provider answered with an error, see [`ProviderError`]
+    Provider(#[from] ProviderError),
+    #[error("{0}")]
+    /// Provider, IO or parsing failure
+    Other(String),
+}
+
+fn locale_errors(errors: &BTreeMap<String, String>) -> String {
+    errors
+        .iter()
+        .map(|(locale, error)| format!("{locale}: {error}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn schema_violations(violations: &BTreeMap<String, Vec<String>>) -> String {
+    violations
+        .iter()
+        .map(|(locale, errors)| format!("{locale}: {}", errors.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Error response of a provider
+///
+/// Lets callers tell a quota (`RESOURCE_EXHAUSTED` for Google, `456` for DeepL)
+/// from a bad request or an authentication failure without matching the message.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("The provider returned {code}{}: {message}", status.as_ref().map(|s| format!(" {s}")).unwrap_or_default())]
+pub struct ProviderError {
+    /// HTTP status of the response, or the code of the error body
+    pub code: u16,
+    /// Message of the provider, the raw body when it is not an error json
+    pub message: String,
+    /// Status name of the error, eg: `INVALID_ARGUMENT`, only sent by Google
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GoogleErrorBody {
+    error: GoogleError,
+}
+
+#[derive(Deserialize)]
+struct GoogleError {
+    code: Option<u16>,
+    message: String,
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeepLErrorBody {
+    message: String,
+    detail: Option<String>,
+}
+
+impl ProviderError {
+    /// Error of a response without an error json
+    pub(crate) fn raw(code: u16, body: &str) -> Self {
+        ProviderError {
+            code,
+            message: body.trim().to_string(),
+            status: None,
+        }
+    }
+
+    /// Parse the `{ "error": { "code", "message", "status" } }` body of Google
+    pub(crate) fn google(code: u16, body: &str) -> Self {
+        match serde_json::from_str::<GoogleErrorBody>(body) {
+            Ok(GoogleErrorBody { error }) => ProviderError {
+                code: error.code.unwrap_or(code),
+                message: error.message,
+                status: error.status,
+            },
+            Err(_) => Self::raw(code, body),
+        }
+    }
+
+    /// Parse the `{ "message", "detail" }` body of DeepL
+    pub(crate) fn deepl(code: u16, body: &str) -> Self {
+        match serde_json::from_str::<DeepLErrorBody>(body) {
+            Ok(DeepLErrorBody { message, detail }) => ProviderError {
+                code,
+                message: match detail {
+                    Some(detail) => format!("{message}, {detail}"),
+                    None => message,
+                },
+                status: None,
+            },
+            Err(_) => Self::raw(code, body),
+        }
+    }
+
+    /// Whether the error is about the account rather than the values sent,
+    /// eg: a wrong key, an exhausted quota or too many requests
+    pub fn is_account_error(&self) -> bool {
+        matches!(self.code, 401 | 403 | 429 | 456)
+            || matches!(
+                self.status.as_deref(),
+                Some("UNAUTHENTICATED" | "PERMISSION_DENIED" | "RESOURCE_EXHAUSTED")
+            )
+    }
+}
+
+impl From<String> for TranslationError {
+    fn from(value: String) -> Self {
+        TranslationError::Other(value)
+    }
+}
+
+impl From<&str> for TranslationError {
+    fn from(value: &str) -> Self {
+        TranslationError::Other(value.to_string())
+    }
+}
+
+#[test]
+fn test_provider_error() {
+    let google = ProviderError::google(
+        429,
+        r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED"}}"#,
+    );
+    assert_eq!(google.status.as_deref(), Some("RESOURCE_EXHAUSTED"));
+    assert_eq!(
+        google.to_string(),
+        "The provider returned 429 RESOURCE_EXHAUSTED: Quota exceeded"
+    );
+
+    let deepl = ProviderError::deepl(456, r#"{"message": "Quota Exceeded"}"#);
+    assert_eq!(
+        (deepl.code, deepl.message.as_str()),
+        (456, "Quota Exceeded")
+    );
+
+    let raw = ProviderError::google(502, "<html>Bad Gateway</html>\n");
+    assert_eq!(raw, ProviderError::raw(502, "<html>Bad Gateway</html>"));
+
+    assert!(google.is_account_error() && deepl.is_account_error());
+    assert!(!raw.is_account_error());
+    assert!(!ProviderError::raw(400, "Bad request").is_account_error());
+}
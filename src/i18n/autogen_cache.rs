@@ -1,64 +1,927 @@
+//!
+//! _Translation memory_
+//!
+//! The autogen cache holds every translation made, keyed by source locale, target locale and
+//! source value. Each source locale has its own translations, the same value in two sources
+//! may not mean the same thing. It can be pre-seeded with known-good translations or shared
+//! between repositories.
+//!
+//! On disk it is a TOML file, `.autogen.cache` in the locales directory. The extension keeps
+//! rust-i18n from loading it as a locale:
+//!
+//! ```toml
+//! # autogenerated by rust-i18n-autotranslate
+//! # can be checked into version control
+//!
+//! # source locale -> sha256 of its file, a changed file is translated again
+//! [checksums]
+//! en = "5d41402abc4b2a76b9719d911017c592..."
+//!
+//! # source locale -> sha256 of the settings, see `Config::settings_hash`
+//! [settings]
+//! en = "9b71d224bd62f3785d96d46ad3ea3d73..."
+//!
+//! # source locale -> key -> short checksum of its value, see `Config::evict_stale_cache`
+//! [value_checksums.en]
+//! "hello" = "185f8db32271fe25"
+//!
+//! # source locale -> target locale -> source value -> translation
+//! [translations.en.fr]
+//! "Hello" = "Bonjour"
+//! "Save" = "Enregistrer"
+//! # a value sent with a context, see `Config::context_file`
+//! "Menu item\u0004Open" = "Ouvrir"
+//!
+//! # provider -> source locale -> target locale -> source value -> reason, see `Config::retry_failed`
+//! [failed.GOOGLE.en.fr]
+//! "🎉" = "No translation returned"
+//!
+//! # source locale -> target locale -> locked key -> source value when it was locked, see `Config::lock_key`
+//! [locks.en.fr]
+//! "legal.terms" = "By using the app you agree to the terms"
+//!
+//! # source locale -> target locale -> sha256 of the last map translated, see `TranslationAPI::translate_from_map`
+//! [map_checksums.en]
+//! fr = "2c26b46b68ffc68ff99b453c1d304134..."
+//! ```
+//!
+//! Caches written before the translations were keyed by source have a `[data.fr]` table per
+//! target locale, [`Autogen::load`] moves it under the source locale of the config. The
+//! configs of the locale groups leave it to the run of the config, see [`Autogen::load_migrating`].
+//!
+
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet},
     fs::{File, OpenOptions},
-    io::{BufWriter, Read, Write},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(test)]
+use crate::utils::TempDir;
+use crate::{
+    config::{Config, TranslationProvider},
+    error::TranslationError,
+    utils::logging::{info, warn},
+};
+
+/// Name of the cache file in the locales directory, not matched by the locale files glob of rust-i18n
+const AUTOGEN_FILE_NAME: &str = ".autogen.cache";
+
+/// Location of the cache before it moved to the locales directory, still read when present
+const LEGACY_AUTOGEN_FILE: &str = "./.autogen.toml";
+
+/// Source locale -> target locale -> source value or key -> value
+pub type PerLocale = BTreeMap<String, BTreeMap<String, BTreeMap<String, String>>>;
+
+/// Translation memory, see the [module docs](self) for the file format
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Autogen {
+    /// Checksum of the source file, from before sources had their own checksum
     pub checksum: Option<String>,
-    pub data: HashMap<String, HashMap<String, String>>,
+    /// Source locale -> checksum of its file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// Source locale -> hash of the settings its files were generated with
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub settings: BTreeMap<String, String>,
+    /// Source locale -> key -> checksum of its source value, to find the keys edited in place
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub value_checksums: BTreeMap<String, BTreeMap<String, String>>,
+    /// Source locale -> target locale -> source value -> translation
+    #[serde(default, rename = "translations")]
+    pub data: PerLocale,
+    /// Target locale -> source value -> translation, from before the source was part of the key
+    #[serde(default, rename = "data", skip_serializing_if = "BTreeMap::is_empty")]
+    legacy: BTreeMap<String, BTreeMap<String, String>>,
+    /// Provider -> source locale -> target locale -> source value -> reason, values that could
+    /// not be translated
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub failed: BTreeMap<String, PerLocale>,
+    /// Source locale -> target locale -> locked key -> source value when the key was locked
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub locks: PerLocale,
+    /// Source locale -> target locale -> checksum of the last in-memory map translated into it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub map_checksums: BTreeMap<String, BTreeMap<String, String>>,
+    /// Cache file, [`LEGACY_AUTOGEN_FILE`] when not set
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Locales removed this run, dropped from the file on update
+    #[serde(skip)]
+    removed: BTreeSet<String>,
+    /// Everything removed this run, the file is replaced on update
+    #[serde(skip)]
+    cleared: bool,
+    /// Checksums removed this run
+    #[serde(skip)]
+    removed_checksums: BTreeSet<String>,
+    /// Translations removed this run, (source locale, target locale, source value)
+    #[serde(skip)]
+    pruned: BTreeSet<(String, String, String)>,
+    /// Source locale the legacy translations are moved to, see [`Autogen::migrate`]
+    #[serde(skip)]
+    legacy_source: Option<String>,
+    /// Failures that were translated this run, (provider, source locale, target locale, source value)
+    #[serde(skip)]
+    resolved: BTreeSet<(String, String, String, String)>,
+    /// Never read or written to disk, see `Config::in_memory_cache`
+    #[serde(skip)]
+    in_memory: bool,
 }
 
 impl Autogen {
-    pub fn load() -> Self {
-        //Using just create was replacing the file always, hence the additional check
-        let auto_translate_file = File::create_new("./.autogen.toml");
-
-        match auto_translate_file {
-            Ok(_) => Autogen::default(),
-            Err(_) => {
-                let existing_file = File::open("./.autogen.toml");
-                if let Ok(mut file) = existing_file {
-                    let mut buffer = Vec::new();
-                    let string_bytes = file.read_to_end(&mut buffer);
-
-                    if let Ok(_) = string_bytes {
-                        let parsed = toml::from_slice::<Autogen>(&buffer);
-                        if let Ok(autogen) = parsed {
-                            autogen
-                        } else {
-                            Autogen::default()
-                        }
-                    } else {
-                        Autogen::default()
-                    }
-                } else {
-                    Autogen::default()
-                }
+    /// Cache of the config, an empty in-memory one with `Config::in_memory_cache`
+    ///
+    /// The file is read from [`Autogen::path_for`], an empty cache is returned when it
+    /// does not exist or cannot be parsed. A file that cannot be parsed is never written over.
+    pub fn load(config: &Config) -> Self {
+        Self::load_migrating(config, Some(&config.source_locale))
+    }
+
+    /// Same as [`Autogen::load`], the translations of an older cache are moved to `legacy_source`
+    ///
+    /// The config of a locale group passes the source locale of the config it was made from,
+    /// or `None` to leave them in the file
+    pub fn load_migrating(config: &Config, legacy_source: Option<&str>) -> Self {
+        if config.in_memory_cache {
+            Self {
+                in_memory: true,
+                ..Default::default()
+            }
+        } else {
+            let mut autogen = Self::load_from(Self::path_for(config));
+            if let Some(source_locale) = legacy_source {
+                autogen.migrate(source_locale);
+            }
+            autogen
+        }
+    }
+
+    /// Move the translations of a cache from before they were keyed by source to the source
+    /// locale, the file is migrated on update
+    pub fn migrate(&mut self, source_locale: &str) {
+        let legacy = std::mem::take(&mut self.legacy);
+        if !legacy.is_empty() {
+            info!("Moving the cached translations under the source locale `{source_locale}`");
+        }
+
+        let source = self.data.entry(source_locale.to_string()).or_default();
+        for (target_locale, translations) in legacy {
+            let existing = source.entry(target_locale).or_default();
+            for (value, translation) in translations {
+                existing.entry(value).or_insert(translation);
+            }
+        }
+        if source.is_empty() {
+            self.data.remove(source_locale);
+        }
+        self.legacy_source = Some(source_locale.to_string());
+    }
+
+    /// `.autogen.cache` in the locales directory, or `.autogen.toml` in the working directory
+    /// if only the cache of an older version is there
+    pub fn path_for(config: &Config) -> PathBuf {
+        let path = config.locales_dir.join(AUTOGEN_FILE_NAME);
+        let legacy = Path::new(LEGACY_AUTOGEN_FILE);
+
+        if !path.exists() && legacy.exists() {
+            info!(
+                "Using the cache in the working directory, move it to `{}` to keep it with the locales",
+                path.display()
+            );
+            return legacy.to_path_buf();
+        }
+
+        path
+    }
+
+    /// Read the cache from a file, the file is only created on update
+    ///
+    /// A file that cannot be read is logged and taken as an empty cache, see
+    /// [`Autogen::try_load_from`] to handle the error
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+
+        Self::try_load_from(path).unwrap_or_else(|e| {
+            warn!(
+                "Could not read the cache {}, starting from an empty one: {e}",
+                path.display()
+            );
+            Autogen {
+                path: Some(path.to_path_buf()),
+                ..Default::default()
             }
+        })
+    }
+
+    /// Read the cache from a file, an empty cache when the file does not exist
+    ///
+    /// Any other error, eg: a permission denied or a file that is not a valid cache, is returned
+    /// instead of taken as a missing file
+    pub fn try_load_from<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let mut autogen = match File::open(path) {
+            Ok(mut file) => {
+                let mut buffer = Vec::new();
+                file.read_to_end(&mut buffer)?;
+                toml::from_slice::<Autogen>(&buffer)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Autogen::default(),
+            Err(e) => return Err(e),
+        };
+
+        autogen.path = Some(path.to_path_buf());
+        Ok(autogen)
+    }
+
+    fn path(&self) -> &Path {
+        self.path
+            .as_deref()
+            .unwrap_or(Path::new(LEGACY_AUTOGEN_FILE))
+    }
+
+    /// Cached translations from a source locale into a target locale
+    pub fn translations(
+        &self,
+        source_locale: &str,
+        target_locale: &str,
+    ) -> Option<&BTreeMap<String, String>> {
+        self.data
+            .get(source_locale)
+            .and_then(|targets| targets.get(target_locale))
+    }
+
+    /// Cached translations from a source locale into a target locale, to add to them
+    pub fn translations_mut(
+        &mut self,
+        source_locale: &str,
+        target_locale: &str,
+    ) -> &mut BTreeMap<String, String> {
+        self.data
+            .entry(source_locale.to_string())
+            .or_default()
+            .entry(target_locale.to_string())
+            .or_default()
+    }
+
+    /// Cached translation of a source value
+    pub fn get(
+        &self,
+        source_locale: &str,
+        target_locale: &str,
+        source_value: &str,
+    ) -> Option<&str> {
+        self.translations(source_locale, target_locale)
+            .and_then(|translations| translations.get(source_value))
+            .map(String::as_str)
+    }
+
+    /// Add or replace the translation of a source value, written on update
+    pub fn insert<S: Into<String>, T: Into<String>>(
+        &mut self,
+        source_locale: &str,
+        target_locale: &str,
+        source_value: S,
+        translation: T,
+    ) {
+        let source_value = source_value.into();
+        self.pruned.remove(&(
+            source_locale.to_string(),
+            target_locale.to_string(),
+            source_value.clone(),
+        ));
+        self.translations_mut(source_locale, target_locale)
+            .insert(source_value, translation.into());
+    }
+
+    /// Remove the translation of a source value, also from the file on update
+    pub fn remove(
+        &mut self,
+        source_locale: &str,
+        target_locale: &str,
+        source_value: &str,
+    ) -> Option<String> {
+        let removed = self
+            .data
+            .get_mut(source_locale)
+            .and_then(|targets| targets.get_mut(target_locale))
+            .and_then(|translations| translations.remove(source_value))?;
+        self.pruned.insert((
+            source_locale.to_string(),
+            target_locale.to_string(),
+            source_value.to_string(),
+        ));
+        Some(removed)
+    }
+
+    /// Source value of a locked key when it was locked
+    pub fn locked_source(
+        &self,
+        source_locale: &str,
+        target_locale: &str,
+        key: &str,
+    ) -> Option<&str> {
+        self.locks
+            .get(source_locale)
+            .and_then(|targets| targets.get(target_locale))
+            .and_then(|locks| locks.get(key))
+            .map(String::as_str)
+    }
+
+    /// Record the source value of a locked key
+    pub fn lock(
+        &mut self,
+        source_locale: &str,
+        target_locale: &str,
+        key: &str,
+        source_value: &str,
+    ) {
+        self.locks
+            .entry(source_locale.to_string())
+            .or_default()
+            .entry(target_locale.to_string())
+            .or_default()
+            .insert(key.to_string(), source_value.to_string());
+    }
+
+    /// Checksum of the last in-memory map of a source locale translated into a target locale
+    pub fn map_checksum_of(&self, source_locale: &str, target_locale: &str) -> Option<&String> {
+        self.map_checksums
+            .get(source_locale)
+            .and_then(|targets| targets.get(target_locale))
+    }
+
+    /// Record the checksum of the in-memory map of a source locale translated into a target locale
+    pub fn set_map_checksum(&mut self, source_locale: &str, target_locale: &str, checksum: String) {
+        self.map_checksums
+            .entry(source_locale.to_string())
+            .or_default()
+            .insert(target_locale.to_string(), checksum);
+    }
+
+    /// Checksum of the source file of a source locale
+    pub fn checksum_of(&self, source_locale: &str) -> Option<&String> {
+        self.checksums.get(source_locale).or(self.checksum.as_ref())
+    }
+
+    /// Set or remove the checksum of the source file of a source locale
+    pub fn set_checksum(&mut self, source_locale: &str, checksum: Option<String>) {
+        self.checksum = None;
+        match checksum {
+            Some(checksum) => {
+                self.removed_checksums.remove(source_locale);
+                self.checksums.insert(source_locale.to_string(), checksum);
+            }
+            None => {
+                self.checksums.remove(source_locale);
+                self.removed_checksums.insert(source_locale.to_string());
+            }
+        }
+    }
+
+    /// Hash of the settings the files of a source locale were generated with
+    pub fn settings_of(&self, source_locale: &str) -> Option<&String> {
+        self.settings.get(source_locale)
+    }
+
+    /// Record the settings the files of a source locale were generated with
+    pub fn set_settings(&mut self, source_locale: &str, settings_hash: String) {
+        self.settings
+            .insert(source_locale.to_string(), settings_hash);
+    }
+
+    /// Checksums of the source values of a source locale by key, see [`value_checksum`]
+    pub fn value_checksums_of(&self, source_locale: &str) -> Option<&BTreeMap<String, String>> {
+        self.value_checksums.get(source_locale)
+    }
+
+    /// Record the checksums of the source values of a source locale, replacing the previous ones
+    pub fn set_value_checksums(
+        &mut self,
+        source_locale: &str,
+        checksums: BTreeMap<String, String>,
+    ) {
+        self.value_checksums
+            .insert(source_locale.to_string(), checksums);
+    }
+
+    /// Remove the cached translations into a locale, also from the file on update
+    pub fn remove_locale(&mut self, locale: &str) {
+        self.forget_locale(locale);
+        self.removed.insert(locale.to_string());
+    }
+
+    fn forget_locale(&mut self, locale: &str) {
+        for targets in self.data.values_mut() {
+            targets.remove(locale);
+        }
+        for targets in self.locks.values_mut() {
+            targets.remove(locale);
+        }
+        for targets in self.map_checksums.values_mut() {
+            targets.remove(locale);
+        }
+        for sources in self.failed.values_mut() {
+            for targets in sources.values_mut() {
+                targets.remove(locale);
+            }
+        }
+    }
+
+    /// Remove all the cached translations, also from the file on update
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.failed.clear();
+        self.locks.clear();
+        self.map_checksums.clear();
+        self.cleared = true;
+    }
+
+    /// Values that could not be translated by the provider into the target locale
+    pub fn failures(
+        &self,
+        provider: &TranslationProvider,
+        source_locale: &str,
+        target_locale: &str,
+    ) -> Option<&BTreeMap<String, String>> {
+        self.failed
+            .get(&format!("{provider:?}"))
+            .and_then(|sources| sources.get(source_locale))
+            .and_then(|failed| failed.get(target_locale))
+    }
+
+    /// Record a value the provider could not translate into the target locale
+    pub fn record_failure(
+        &mut self,
+        provider: &TranslationProvider,
+        source_locale: &str,
+        target_locale: &str,
+        value: &str,
+        reason: &str,
+    ) {
+        let provider = format!("{provider:?}");
+        self.resolved.remove(&(
+            provider.clone(),
+            source_locale.to_string(),
+            target_locale.to_string(),
+            value.to_string(),
+        ));
+        self.failed
+            .entry(provider)
+            .or_default()
+            .entry(source_locale.to_string())
+            .or_default()
+            .entry(target_locale.to_string())
+            .or_default()
+            .insert(value.to_string(), reason.to_string());
+    }
+
+    /// Forget a failure once the value was translated, also from the file on update
+    pub fn resolve_failure(
+        &mut self,
+        provider: &TranslationProvider,
+        source_locale: &str,
+        target_locale: &str,
+        value: &str,
+    ) {
+        let provider = format!("{provider:?}");
+        if let Some(failed) = self
+            .failed
+            .get_mut(&provider)
+            .and_then(|sources| sources.get_mut(source_locale))
+            .and_then(|failed| failed.get_mut(target_locale))
+            && failed.remove(value).is_some()
+        {
+            self.resolved.insert((
+                provider,
+                source_locale.to_string(),
+                target_locale.to_string(),
+                value.to_string(),
+            ));
         }
     }
 
-    pub fn update_cache(&self) -> Result<(), &'static str> {
-        let auto_translate_file = OpenOptions::new()
+    /// Merge this run into the latest cache on disk and write it
+    ///
+    /// The file is locked while merging so concurrent runs (eg: one CI job per language)
+    /// do not lose each other's translations
+    pub fn update_cache(&self) -> Result<(), TranslationError> {
+        if self.in_memory {
+            return Ok(());
+        }
+
+        let mut auto_translate_file = OpenOptions::new()
+            .read(true)
             .write(true)
-            .truncate(true)
-            .open("./.autogen.toml")
-            .map_err(|_| "Could not open autogen")?;
-        let mut writer = BufWriter::new(auto_translate_file);
+            .create(true)
+            .truncate(false)
+            .open(self.path())
+            .map_err(|e| self.cache_error("open", e))?;
+        auto_translate_file
+            .lock()
+            .map_err(|e| self.cache_error("lock", e))?;
+
+        let mut latest = if self.cleared {
+            Autogen::default()
+        } else {
+            let mut buffer = Vec::new();
+            auto_translate_file
+                .read_to_end(&mut buffer)
+                .map_err(|e| self.cache_error("read", e))?;
+            //a corrupt cache is kept for the user to fix rather than replaced by this run
+            toml::from_slice::<Autogen>(&buffer).map_err(|e| self.cache_error("parse", e))?
+        };
+
+        if let Some(source_locale) = &self.legacy_source {
+            latest.migrate(source_locale);
+        }
+        for locale in &self.removed {
+            latest.forget_locale(locale);
+        }
+        for (source_locale, locale, value) in &self.pruned {
+            if let Some(translations) = latest
+                .data
+                .get_mut(source_locale)
+                .and_then(|targets| targets.get_mut(locale))
+            {
+                translations.remove(value);
+            }
+        }
+        for (source_locale, targets) in &self.locks {
+            for (locale, locks) in targets {
+                latest
+                    .locks
+                    .entry(source_locale.to_string())
+                    .or_default()
+                    .entry(locale.to_string())
+                    .or_default()
+                    .extend(locks.clone());
+            }
+        }
+        for (provider, source_locale, locale, value) in &self.resolved {
+            if let Some(failed) = latest
+                .failed
+                .get_mut(provider)
+                .and_then(|sources| sources.get_mut(source_locale))
+                .and_then(|failed| failed.get_mut(locale))
+            {
+                failed.remove(value);
+            }
+        }
+        for (provider, sources) in &self.failed {
+            for (source_locale, locales) in sources {
+                for (locale, failed) in locales {
+                    latest
+                        .failed
+                        .entry(provider.to_string())
+                        .or_default()
+                        .entry(source_locale.to_string())
+                        .or_default()
+                        .entry(locale.to_string())
+                        .or_default()
+                        .extend(failed.clone());
+                }
+            }
+        }
+        latest.failed.retain(|_, sources| {
+            sources.retain(|_, locales| {
+                locales.retain(|_, failed| !failed.is_empty());
+                !locales.is_empty()
+            });
+            !sources.is_empty()
+        });
+        for (source_locale, targets) in &self.data {
+            for (locale, translations) in targets {
+                latest
+                    .translations_mut(source_locale, locale)
+                    .extend(translations.clone());
+            }
+        }
+        latest.checksum = self.checksum.clone();
+        for source_locale in &self.removed_checksums {
+            latest.checksums.remove(source_locale);
+        }
+        latest.checksums.extend(self.checksums.clone());
+        latest.settings.extend(self.settings.clone());
+        latest.value_checksums.extend(self.value_checksums.clone());
+        for (source_locale, targets) in &self.map_checksums {
+            latest
+                .map_checksums
+                .entry(source_locale.to_string())
+                .or_default()
+                .extend(targets.clone());
+        }
 
         let comment =
             "# autogenerated by rust-i18n-autotranslate\n# can be checked into version control\n\n";
 
-        let toml = toml::to_string(self).map_err(|_| "Could not serialize data")?;
+        let toml = toml::to_string(&latest).map_err(|e| self.cache_error("serialize", e))?;
 
         let final_write = format!("{comment}{toml}");
 
-        writer
-            .write_all(final_write.as_bytes())
-            .map_err(|_| "Could not write autogen data")
+        auto_translate_file
+            .set_len(0)
+            .and_then(|_| auto_translate_file.seek(SeekFrom::Start(0)))
+            .and_then(|_| auto_translate_file.write_all(final_write.as_bytes()))
+            .map_err(|e| self.cache_error("write", e))
+    }
+
+    fn cache_error(&self, action: &str, error: impl std::fmt::Display) -> TranslationError {
+        TranslationError::Other(format!(
+            "Could not {action} the cache `{}`: {error}",
+            self.path().display()
+        ))
     }
 }
+
+/// Checksum of a source value as cached, the first 16 hex digits of its sha256
+///
+/// Short to keep the cache small, it only tells the values of a key apart
+pub fn value_checksum(value: &str) -> String {
+    let mut checksum = sha256::digest(value);
+    checksum.truncate(16);
+    checksum
+}
+
+#[test]
+fn test_autogen_serialize_deterministic() {
+    let pairs = [
+        ("hello", "bonjour"),
+        ("cat", "chat"),
+        ("god", "dieu"),
+        ("feline", "félin"),
+    ];
+
+    let mut first = Autogen::default();
+    let mut second = Autogen::default();
+    for (source, target) in pairs.iter() {
+        first
+            .translations_mut("en", "fr")
+            .insert(source.to_string(), target.to_string());
+    }
+    for (source, target) in pairs.iter().rev() {
+        second
+            .translations_mut("en", "fr")
+            .insert(source.to_string(), target.to_string());
+    }
+
+    assert_eq!(toml::to_string(&first), toml::to_string(&second));
+}
+
+#[test]
+fn test_checksum_per_source() {
+    let dir = TempDir::new("autogen_checksum_test");
+    let path = &dir.join("autogen_checksum_test.toml");
+
+    let mut autogen = Autogen::load_from(path);
+    autogen.checksum = Some("legacy".to_string());
+    assert_eq!(
+        autogen.checksum_of("ja").map(|s| s.as_str()),
+        Some("legacy")
+    );
+
+    autogen.set_checksum("en", Some("en-sha".to_string()));
+    autogen.set_checksum("ja", Some("ja-sha".to_string()));
+    autogen.update_cache().unwrap();
+
+    let mut reloaded = Autogen::load_from(path);
+    reloaded.set_checksum("ja", None);
+    reloaded.update_cache().unwrap();
+    let reloaded = Autogen::load_from(path);
+
+    assert_eq!(reloaded.checksum, None);
+    assert_eq!(
+        reloaded.checksum_of("en").map(|s| s.as_str()),
+        Some("en-sha")
+    );
+    assert_eq!(reloaded.checksum_of("ja"), None);
+}
+
+#[test]
+fn test_update_cache_concurrent_merge() {
+    let dir = TempDir::new("autogen_concurrent_test");
+    let path = dir.join("autogen_concurrent_test.toml");
+
+    let handles: Vec<_> = ["fr", "de"]
+        .into_iter()
+        .map(|locale| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                for idx in 0..20 {
+                    let mut autogen = Autogen::load_from(&path);
+                    autogen
+                        .translations_mut("en", locale)
+                        .insert(format!("value {idx}"), format!("{locale} {idx}"));
+                    autogen.update_cache().unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let mut merged = Autogen::load_from(&path);
+    let fr_len = merged.translations("en", "fr").map(|d| d.len());
+    let de_len = merged.translations("en", "de").map(|d| d.len());
+
+    merged.remove_locale("de");
+    merged.update_cache().unwrap();
+    let removed = Autogen::load_from(&path);
+
+    assert_eq!((fr_len, de_len), (Some(20), Some(20)));
+    assert_eq!(removed.translations("en", "de"), None);
+    assert_eq!(removed.translations("en", "fr").map(|d| d.len()), Some(20));
+}
+
+#[test]
+fn test_failures() {
+    let dir = TempDir::new("autogen_failures_test");
+    let path = &dir.join("autogen_failures_test.toml");
+    let provider = TranslationProvider::GOOGLE;
+
+    let mut autogen = Autogen::load_from(path);
+    autogen.record_failure(&provider, "en", "fr", "🎉", "Empty translation");
+    autogen.record_failure(&provider, "en", "fr", "%%%", "Could not parse the response");
+    autogen.update_cache().unwrap();
+
+    let mut reloaded = Autogen::load_from(path);
+    let failures = reloaded.failures(&provider, "en", "fr").cloned();
+    reloaded.resolve_failure(&provider, "en", "fr", "%%%");
+    reloaded.update_cache().unwrap();
+    let resolved = Autogen::load_from(path);
+
+    assert_eq!(
+        failures.map(|f| f.into_keys().collect::<Vec<_>>()),
+        Some(vec!["%%%".to_string(), "🎉".to_string()])
+    );
+    assert_eq!(
+        resolved.failures(&provider, "en", "fr").map(|f| f.len()),
+        Some(1)
+    );
+    assert_eq!(
+        resolved.failures(&TranslationProvider::DEEPL, "en", "fr"),
+        None
+    );
+    assert_eq!(resolved.failures(&provider, "ja", "fr"), None);
+}
+
+#[test]
+fn test_in_memory() {
+    let config = Config::new().in_memory_cache(true).build();
+
+    let mut autogen = Autogen::load(&config);
+    autogen.insert("en", "fr", "hello", "bonjour");
+
+    assert_eq!(autogen.update_cache(), Ok(()));
+    assert_eq!(autogen.path, None);
+    assert_eq!(autogen.get("en", "fr", "hello"), Some("bonjour"));
+    assert_eq!(autogen.get("en", "de", "hello"), None);
+}
+
+#[test]
+fn test_path_for() {
+    let locales = &TempDir::new("locales_autogen_path");
+    let config = Config::new().locales_directory(locales).build();
+
+    let path = Autogen::path_for(&config);
+
+    if Path::new(LEGACY_AUTOGEN_FILE).exists() {
+        assert_eq!(path, Path::new(LEGACY_AUTOGEN_FILE));
+        return;
+    }
+    assert_eq!(path, config.locales_dir.join(AUTOGEN_FILE_NAME));
+}
+
+#[test]
+fn test_remove() {
+    let dir = TempDir::new("autogen_remove_test");
+    let path = &dir.join("autogen_remove_test.toml");
+
+    let mut autogen = Autogen::load_from(path);
+    autogen.insert("en", "fr", "hello", "bonjour");
+    autogen.insert("en", "fr", "cat", "chat");
+    autogen.update_cache().unwrap();
+
+    let mut reloaded = Autogen::load_from(path);
+    let removed = reloaded.remove("en", "fr", "cat");
+    let missing = reloaded.remove("en", "de", "cat");
+    reloaded.update_cache().unwrap();
+    let pruned = Autogen::load_from(path);
+
+    assert_eq!(removed.as_deref(), Some("chat"));
+    assert_eq!(missing, None);
+    assert_eq!(pruned.get("en", "fr", "hello"), Some("bonjour"));
+    assert_eq!(pruned.get("en", "fr", "cat"), None);
+}
+
+#[test]
+fn test_migrate_legacy_data() {
+    let locales = &TempDir::new("locales_autogen_migrate");
+    let config = Config::new()
+        .locales_directory(locales)
+        .source_lang("en")
+        .build();
+    let path = config.locales_dir.join(AUTOGEN_FILE_NAME);
+    std::fs::write(&path, "[data.fr]\nHello = \"Bonjour\"\n").unwrap();
+
+    let mut autogen = Autogen::load(&config);
+    let migrated = autogen.get("en", "fr", "Hello").map(str::to_string);
+    //the same value in another source is another translation
+    autogen.insert("ja", "fr", "Hello", "Salut");
+    autogen.update_cache().unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    let reloaded = Autogen::load_from(&path);
+
+    assert_eq!(migrated.as_deref(), Some("Bonjour"));
+    assert!(!content.contains("[data"));
+    assert_eq!(reloaded.get("en", "fr", "Hello"), Some("Bonjour"));
+    assert_eq!(reloaded.get("ja", "fr", "Hello"), Some("Salut"));
+}
+
+#[test]
+fn test_try_load_from_error() {
+    let missing_dir = TempDir::new("autogen_missing_test");
+    let missing = &missing_dir.join("autogen_missing_test.toml");
+    //reading a directory fails with another error than a missing file
+    let unreadable_dir = TempDir::new("autogen_unreadable_test");
+    let unreadable: &Path = &unreadable_dir;
+
+    let empty = Autogen::try_load_from(missing);
+    let failed = Autogen::try_load_from(unreadable);
+    let fallback = Autogen::load_from(unreadable);
+
+    assert!(empty.is_ok_and(|autogen| autogen.data.is_empty()));
+    assert!(failed.is_err_and(|e| e.kind() != io::ErrorKind::NotFound));
+    assert_eq!(fallback.path.as_deref(), Some(unreadable));
+    assert!(!missing.exists());
+}
+
+#[test]
+fn test_corrupt_cache_kept() {
+    let dir = TempDir::new("autogen_corrupt_test");
+    let path = &dir.join("autogen_corrupt_test.toml");
+    std::fs::write(path, "[translations.en.fr\n\"Hello\" = ").unwrap();
+
+    let failed = Autogen::try_load_from(path);
+    let mut autogen = Autogen::load_from(path);
+    autogen
+        .translations_mut("en", "fr")
+        .insert("Cat".to_string(), "Chat".to_string());
+    let updated = autogen.update_cache();
+    let content = std::fs::read_to_string(path).unwrap();
+
+    assert!(failed.is_err_and(|e| e.kind() == io::ErrorKind::InvalidData));
+    assert!(autogen.translations("en", "fr").is_some());
+    assert!(updated.is_err_and(|e| e.to_string().contains("Could not parse the cache")));
+    assert_eq!(content, "[translations.en.fr\n\"Hello\" = ");
+}
+
+#[test]
+fn test_cache_not_loaded_as_locale() {
+    let locales = &TempDir::new("locales_autogen_glob");
+    std::fs::write(locales.join("en.yml"), "Hello: Hello\n").unwrap();
+    let config = Config::new().locales_directory(locales).build();
+
+    let mut autogen = Autogen::load(&config);
+    autogen.insert("en", "fr", "Hello", "Bonjour");
+    autogen.update_cache().unwrap();
+    let cache_file = config.locales_dir.join(AUTOGEN_FILE_NAME).exists();
+    let loaded = rust_i18n_support::load_locales(locales.to_str().unwrap(), |_| false);
+
+    assert!(cache_file);
+    assert_eq!(loaded.keys().collect::<Vec<_>>(), ["en"]);
+    assert_eq!(loaded["en"].len(), 1);
+}
+
+#[test]
+fn test_migrate_legacy_data_of_group() {
+    let locales = &TempDir::new("locales_autogen_migrate_group");
+    let config = Config::new()
+        .locales_directory(locales)
+        .source_lang("en")
+        .add_group("ja", vec!["fr"])
+        .build();
+    let mut group_config = config.clone();
+    group_config.source_locale = "ja".to_string();
+    let path = config.locales_dir.join(AUTOGEN_FILE_NAME);
+    std::fs::write(&path, "[data.fr]\nHello = \"Bonjour\"\n").unwrap();
+
+    let untouched = Autogen::load_migrating(&group_config, None);
+    untouched.update_cache().unwrap();
+    let kept = std::fs::read_to_string(&path).unwrap();
+    let autogen = Autogen::load_migrating(&group_config, Some(&config.source_locale));
+    autogen.update_cache().unwrap();
+    let reloaded = Autogen::load_from(&path);
+
+    assert!(kept.contains("[data.fr]"));
+    assert_eq!(untouched.get("ja", "fr", "Hello"), None);
+    assert_eq!(autogen.get("en", "fr", "Hello"), Some("Bonjour"));
+    assert_eq!(reloaded.get("ja", "fr", "Hello"), None);
+    assert_eq!(reloaded.get("en", "fr", "Hello"), Some("Bonjour"));
+}
@@ -1 +1,7 @@
+//!
+//! _Translation memory_
+//!
+//! The cache of the translations, see [`autogen_cache::Autogen`]
+//!
+
 pub mod autogen_cache;
@@ -35,6 +35,8 @@
 //!  - DeepL (Cloud Translate - Fallback to deeplx)
 //!  - DeepLX (Needs installation [Install DeepLX](<https://deeplx.owo.network/install/>))
 //!  - LibreTranslate (Fallback - [Install Self Hosted](<https://docs.libretranslate.com/#self-hosted>)))
+//!  - Dictionary (Offline lookups of common words, for tests and offline dev)
+//!  - Pseudo (Pseudolocalization, eg: `en-XA`, to find hardcoded strings and truncation bugs)
 //!  - Yandex (Planned)
 //!  - aws ML (Planned)
 //!
@@ -80,23 +82,57 @@
 //!
 //!
 
-use log::{error, info};
+use jsonschema::Validator;
+use normpath::PathExt;
 use rust_i18n_support::load_locales;
 
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
 
+#[cfg(test)]
+use crate::utils::TempDir;
 use crate::{
-    api::translate_data,
-    config::Config,
-    i18n::autogen_cache::Autogen,
-    utils::{match_sha256, verify_locales, write_locale_file},
+    api::{
+        ProviderUsage, billable_chars, check_api_key, duplicate_count, translate_data,
+        translate_data_with_context,
+    },
+    config::{
+        CacheMode, Config, LocaleGroup, OutputFormat, Overwrite, SchemaViolation,
+        TranslationProvider, UnsupportedLanguage,
+    },
+    error::TranslationError,
+    i18n::autogen_cache::{Autogen, value_checksum},
+    report::{
+        CostEstimate, FileWrite, KeyChanges, SkipReason, TranslationReport, TranslationReportEntry,
+    },
+    utils::{
+        apply_locks, convert_zh, existing_translations, format_locale_file, git_show, in_path,
+        journal::JournalScope,
+        kept_locked_keys,
+        languages::normalize_lang,
+        load_contexts, locale_file_stem, locale_value,
+        logging::{LogScope, debug, error, info, warn},
+        map_locked_translations, match_sha256, merge_outside_path, namespace_files,
+        output_extension, output_file_name, output_file_path, parse_locale_content,
+        read_locale_file, schema, set_file_permissions, source_path, tmx, verify_locales,
+        write_locale_file,
+    },
 };
 
 mod api;
 pub mod config;
-mod i18n;
+pub mod error;
+pub mod i18n;
+pub mod report;
 mod utils;
 
+/// The http client, to build the agent of `Config::http_agent`
+pub use ureq;
+
 //TODO:: Setup errors correctly
 
 /// The translation api
@@ -117,11 +153,11 @@ impl TranslationAPI {
     ///- DEEPL_FREE_API_KEY="xxx"
     ///- DEEPL_PRO_API_KEY="xxx"
     ///- LIBRE_TRANSLATE_API_KEY="xxx"
+    ///
     ///`If both deepl api keys are set, priority is given to the free key`
     ///
     /// Cache: Use cache to save and reuse translations.
     ///
-
     /// Example:
     /// ```rust,no_run
     ///use rust_i18n_autotranslate::{
@@ -144,217 +180,3856 @@ impl TranslationAPI {
     ///}
     /// ```
     /// ## Language codes need to be in [ISO-639](<https://wikipedia.org/wiki/ISO_639>) format
-    pub fn translate(config: Config) -> Result<(), String> {
-        //verify that the sha256 checksums are different then only proceed
-        let locale_path = config.locales_dir.clone();
+    pub fn translate(config: Config) -> Result<(), TranslationError> {
+        Self::translate_with_report(config)?.locale_errors()
+    }
 
-        let verify_locales = verify_locales(
-            locale_path.as_path(),
-            &config.source_locale,
-            &config.target_locales,
-        );
+    /// Same as [`TranslationAPI::translate`] but returns a report of what was done for each target locale
+    ///
+    /// A locale the provider failed to translate is in the report as `SkipReason::Failed`, the
+    /// other locales are still translated. [`TranslationAPI::translate`] returns the failures as
+    /// `TranslationError::LocalesFailed`.
+    pub fn translate_with_report(config: Config) -> Result<TranslationReport, TranslationError> {
+        Self::translate_run(config, None)
+    }
+
+    /// Translate every group of the config, only the keys changed since `git_ref` when set
+    fn translate_run(
+        config: Config,
+        git_ref: Option<&str>,
+    ) -> Result<TranslationReport, TranslationError> {
+        let _log = LogScope::enter(&config);
+        let mut report = TranslationReport {
+            provider: Some(config.provider.clone()),
+            ..Default::default()
+        };
+
+        if !config.enabled {
+            info!("Translations disabled, nothing to do");
+            return Ok(report);
+        }
+
+        dotenvy::dotenv().ok();
+        check_api_key(&config)?;
+
+        let journal = JournalScope::enter(&config);
+        //billable characters sent this run, the budget is shared by the groups
+        let mut chars_sent = 0;
+        for_each_group(&config, |group_config| {
+            Self::translate_group(
+                group_config,
+                &config.source_locale,
+                git_ref,
+                &mut report,
+                &mut chars_sent,
+            )
+        })?;
+
+        journal.complete();
 
-        let mut autogen = Autogen::load();
+        Ok(report)
+    }
+
+    /// Translate a single source locale into its targets
+    ///
+    /// `legacy_source` is the source locale of the config the group was made from, the
+    /// translations of an older cache belong to it. With `git_ref` the keys unchanged since the
+    /// ref keep the translations of the target files, see [`TranslationAPI::translate_since`]
+    fn translate_group(
+        config: Config,
+        legacy_source: &str,
+        git_ref: Option<&str>,
+        report: &mut TranslationReport,
+        chars_sent: &mut usize,
+    ) -> Result<(), TranslationError> {
+        //the entries of the group, the report also has the ones of the groups before
+        let first_entry = report.locales.len();
+
+        //verify that the sha256 checksums are different then only proceed
+        let verify_locales = verify_locales(&config);
+
+        let mut autogen = Autogen::load_migrating(&config, Some(legacy_source));
 
         if config.target_locales.is_empty() {
             info!("Already on latest");
-            autogen.data.clear();
-            let _ = autogen.update_cache();
+            //the cache also holds the translations of the other groups
+            if config.groups.is_empty() {
+                autogen.clear();
+                let _ = autogen.update_cache();
+            }
             return Ok(());
         }
 
+        let schema = load_schema(&config)?;
+
+        //a cache from before the settings were recorded is taken as up to date
+        let settings_hash = config.settings_hash();
+        let settings_changed = autogen
+            .settings_of(&config.source_locale)
+            .is_some_and(|previous| *previous != settings_hash);
+
+        //a forced run matches against no checksum, it always gets the current one
         let checksum_res = match_sha256(
-            locale_path.as_path(),
-            &config.source_locale,
-            &autogen.checksum.unwrap_or_default(),
+            &config,
+            &autogen
+                .checksum_of(&config.source_locale)
+                .cloned()
+                .filter(|_| !config.force && !settings_changed)
+                .unwrap_or_default(),
         );
+        if config.force {
+            info!("Forced run, translating regardless of the source checksum");
+        } else if settings_changed {
+            info!("The settings changed since the last run, translating again without the cache");
+        }
 
         if checksum_res.is_some() || verify_locales.is_err() {
+            //keep the old sha2 around in case the run is stopped early
+            let previous_checksum = autogen.checksum_of(&config.source_locale).cloned();
+            let previous_settings = autogen.settings_of(&config.source_locale).cloned();
             //update the sha2
-            autogen.checksum = checksum_res;
+            autogen.set_checksum(&config.source_locale, checksum_res);
+            autogen.set_settings(&config.source_locale, settings_hash);
+
+            if config.cache_mode.reads() {
+                seed_from_memory(&config, &mut autogen)?;
+            }
+
+            //the files are written unformatted when the formatter fails
+            let mut format_res = Ok(());
+
+            info!(
+                provider:? = config.provider,
+                source = config.source_locale.as_str(),
+                targets = config.target_locales.len();
+                "Translation run started"
+            );
 
             //Preload google api key from env
             dotenvy::dotenv().ok();
 
-            let mut locales_data =
-                load_locales(config.locales_dir.to_str().unwrap_or_default(), |_| false);
-
-            let source_locale_data = locales_data.get_mut(&config.source_locale);
+            let mut locales_data = load_locales_data(&config)?;
+            let source_data = locales_data
+                .entry(config.source_locale.clone())
+                .or_default();
+            if let Some(path) = &config.only_path {
+                source_data.retain(|key, _| in_path(key, path));
+            }
 
             //use the source locale data
-            if let Some(source_data) = source_locale_data {
-                source_data.remove("_version");
-
-                if config.use_cache {
-                    //use autogen cache
-                    for target_locale in config.target_locales {
-                        let autogen_data = autogen
-                            .data
-                            .get(&target_locale)
-                            .cloned()
-                            .unwrap_or_default();
-
-                        let mut to_translate_keys = Vec::with_capacity(source_data.len());
-                        let mut to_translate_values = Vec::with_capacity(source_data.len());
-                        let mut og_keys = Vec::with_capacity(source_data.len());
-
-                        for (key, value) in source_data.iter() {
-                            //TODO: Find a more performant solution to clones and duplications
-                            //maintain a seperate copy iter later
-                            og_keys.push(key.as_str());
-                            //if it doesnt exist in the autogen cache then send for translate
-                            if autogen_data.get(value).is_none() {
-                                to_translate_keys.push(key.as_str());
-                                to_translate_values.push(value.as_str());
-                            }
-                        }
+            let contexts = load_contexts(&config);
+            let since = git_ref.and_then(|git_ref| source_at(&config, git_ref));
 
-                        let translated_values = translate_data(
-                            &config.provider,
-                            &to_translate_values,
-                            &config.source_locale,
-                            &target_locale,
-                        )?;
-
-                        //get the already present data
-                        let mut autogen_locale = autogen
-                            .data
-                            .get(&target_locale)
-                            .cloned()
-                            .unwrap_or_default();
-
-                        //combine the translated values
-                        let mut translated_kv = BTreeMap::new();
-
-                        if translated_values.len() == to_translate_keys.len() {
-                            if translated_values.len() > 0 && to_translate_keys.len() > 0 {
-                                //Updating the autogen values
-                                for (index, value) in to_translate_values.iter().enumerate() {
-                                    autogen_locale.insert(
-                                        value.to_string(),
-                                        translated_values[index].clone(),
-                                    );
-                                }
-                                //update the autogen value
-                                autogen
-                                    .data
-                                    .insert(target_locale.to_string(), autogen_locale.clone());
-
-                                for (og_key, og_value) in source_data.iter() {
-                                    //if contains then it was sent for translation else use cached value
-                                    if let Some(pos) =
-                                        to_translate_keys.iter().position(|x| x == &og_key)
-                                    {
-                                        //translated value
-                                        // use the pos to get value from translated value
-                                        let translated_value = translated_values.get(pos);
-                                        if let Some(value) = translated_value {
-                                            translated_kv
-                                                .insert(og_key.to_string(), value.to_string());
-                                        } else {
-                                            translated_kv
-                                                .insert(og_key.to_string(), og_value.to_string());
-                                        }
-                                    } else {
-                                        //cached value
-                                        let res = autogen_locale.get(og_value);
-                                        if let Some(auto_data) = res {
-                                            translated_kv
-                                                .insert(og_key.to_string(), auto_data.to_string());
-                                        } else {
-                                            //default = not found = insert source value
-                                            translated_kv
-                                                .insert(og_key.to_string(), og_value.to_string());
-                                        }
-                                    }
-                                }
-                            } else {
-                                //cached value
-                                for (og_key, og_value) in source_data.iter() {
-                                    let res = autogen_locale.get(og_value);
-                                    if let Some(auto_data) = res {
-                                        translated_kv
-                                            .insert(og_key.to_string(), auto_data.to_string());
-                                    } else {
-                                        //default = not found = insert source value
-                                        translated_kv
-                                            .insert(og_key.to_string(), og_value.to_string());
-                                    }
-                                }
-                            }
-
-                            //write the locale file
-                            let write_res = write_locale_file(
-                                &locale_path,
-                                &translated_kv,
-                                &config.source_locale,
-                                &target_locale,
-                            );
+            //the checksums of the last run tell the keys edited in place
+            let value_checksums = config
+                .evict_stale_cache
+                .then(|| value_checksums(&config, source_data, &contexts));
+            let edited_values = value_checksums
+                .as_ref()
+                .map(|current| {
+                    edited_values(autogen.value_checksums_of(&config.source_locale), current)
+                })
+                .unwrap_or_default();
 
-                            if let Err(e) = write_res {
-                                error!("{e}");
-                            }
-                        } else {
-                            //some translations may have failed, so discard the whole translation
-                            continue;
-                        }
+            let run = GroupRun {
+                config: &config,
+                source_data,
+                contexts: &contexts,
+                schema: schema.as_ref(),
+                edited_values: &edited_values,
+                reads_cache: config.cache_mode.reads() && !settings_changed,
+                previous_checksum: previous_checksum.as_ref(),
+                previous_settings: previous_settings.as_ref(),
+                since: since.as_ref(),
+            };
+            //an error stops the run, the cache is still saved below
+            let mut stop_res = Ok(());
+            if source_data.is_empty() {
+                warn!("No keys in the source locale, nothing to translate");
+                for target_locale in &config.target_locales {
+                    report.skip(target_locale, SkipReason::EmptySource);
+                    after_locale(&config, report);
+                }
+            } else {
+                for target_locale in &config.target_locales {
+                    //converted once the other script is written
+                    if config.converted_from(target_locale).is_some() {
+                        continue;
                     }
-                } else {
-                    //no use autogen
-                    let mut keys = Vec::with_capacity(source_data.len());
-                    let mut values = Vec::with_capacity(source_data.len());
-                    for (key, value) in source_data {
-                        keys.push(key.as_str());
-                        values.push(value.as_str());
+                    if let Err(e) = translate_locale(
+                        &run,
+                        &mut autogen,
+                        report,
+                        chars_sent,
+                        &mut format_res,
+                        target_locale,
+                    ) {
+                        stop_res = Err(e);
+                        break;
                     }
+                }
+            }
+            if !source_data.is_empty() {
+                convert_zh_locales(&config, schema.as_ref(), report, &mut format_res);
+            }
 
-                    for target_locale in config.target_locales {
-                        let translated = translate_data(
-                            &config.provider,
-                            &values,
-                            &config.source_locale,
-                            &target_locale,
-                        )?;
-
-                        //combine the translated
-                        if translated.len() == keys.len() {
-                            //combine the translated values
-                            let mut translated_kv = BTreeMap::new();
-                            for (index, key) in keys.iter().enumerate() {
-                                translated_kv.insert(key.to_string(), translated[index].clone());
-                            }
-
-                            //write the locale file
-                            let write_res = write_locale_file(
-                                &locale_path,
-                                &translated_kv,
-                                &config.source_locale,
-                                &target_locale,
-                            );
+            let violations = schema_violations(&report.locales[first_entry..]);
+            //the locales that failed are translated again by the next run
+            let failed = report.locales[first_entry..]
+                .iter()
+                .any(|entry| matches!(entry.skipped, Some(SkipReason::Failed(_))));
 
-                            if let Err(e) = write_res {
-                                error!("{e}");
-                            }
-                        } else {
-                            //some translations may have failed, so discard the whole translation
-                            continue;
-                        }
-                    }
+            let stopped =
+                stop_res.is_err() || failed || format_res.is_err() || !violations.is_empty();
+            //stopped early or limited to `only_path`, keep the completed work but make sure the
+            //next run picks up the rest
+            if stopped || config.only_path.is_some() {
+                autogen.set_checksum(&config.source_locale, previous_checksum);
+                if let Some(previous_settings) = previous_settings {
+                    autogen.set_settings(&config.source_locale, previous_settings);
                 }
+            }
+            if !stopped && let Some(value_checksums) = value_checksums {
+                //the keys outside `only_path` keep the checksums of the run that translated them
+                let mut checksums = match config.only_path {
+                    Some(_) => autogen
+                        .value_checksums_of(&config.source_locale)
+                        .cloned()
+                        .unwrap_or_default(),
+                    None => BTreeMap::new(),
+                };
+                checksums.extend(value_checksums);
+                autogen.set_value_checksums(&config.source_locale, checksums);
+            }
 
-                //update autogen
-                let autogen_update_res = autogen.update_cache();
-                if let Err(err) = autogen_update_res {
-                    error!("{}", err);
-                }
+            //update autogen
+            let autogen_update_res = autogen.update_cache();
+            if let Err(err) = autogen_update_res {
+                error!("{}", err);
+            }
 
-                Ok(())
-            } else {
-                Err("Could not find source locale data".to_string())
+            stop_res?;
+            format_res?;
+            if !violations.is_empty() {
+                return Err(TranslationError::SchemaViolation(violations));
             }
+            Ok(())
         } else {
             info!("Already on latest");
             Ok(())
         }
     }
+
+    /// Translate only the keys of the source file that were added or changed since `git_ref`.
+    ///
+    /// Keys missing from a target file are translated as well, everything else is kept from the
+    /// existing target files. Otherwise the same as [`TranslationAPI::translate`], eg: the
+    /// values already translated are taken from the autogen cache depending on `cache_mode`.
+    /// Falls back to a full translation if git is not available or the source file does not
+    /// exist at `git_ref`.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::Config};
+    ///
+    ///let cfg = Config::new()
+    ///    .locales_directory("./locales")
+    ///    .source_lang("en")
+    ///    .add_target_lang("fr")
+    ///    .build();
+    ///
+    ///TranslationAPI::translate_since(cfg, "origin/main").unwrap()
+    /// ```
+    pub fn translate_since(config: Config, git_ref: &str) -> Result<(), TranslationError> {
+        Self::translate_since_with_report(config, git_ref)?.locale_errors()
+    }
+
+    /// Same as [`TranslationAPI::translate_since`] but returns a report of what was done for
+    /// each target locale, see [`TranslationAPI::translate_with_report`]
+    pub fn translate_since_with_report(
+        config: Config,
+        git_ref: &str,
+    ) -> Result<TranslationReport, TranslationError> {
+        Self::translate_run(config, Some(git_ref))
+    }
+
+    /// Translate an in-memory source map into every target locale of the config.
+    ///
+    /// The locales directory is not read or written, the translated maps are returned keyed by
+    /// target locale. Depending on `cache_mode` already translated values are reused from the autogen cache
+    /// and only the new ones are sent to the provider. The cache is not used when it was written
+    /// with other settings, eg: another provider, see `Config::settings_hash`.
+    ///
+    /// The checksum of the map is cached per target locale like the one of a source file, a map
+    /// translated before is returned from the cache without sending anything. The contexts of
+    /// `Config::context_file` and the locked keys of `Config::lock_key` apply as for the files,
+    /// a locked key keeps the translation of its value when it was locked.
+    ///
+    /// A target that fails does not stop the others, the translations of the other targets are
+    /// cached and `TranslationError::LocalesFailed` is returned with the failed ones.
+    ///
+    /// The targets of the groups with the same source locale are translated as well, the map
+    /// has nothing to translate for the groups of another source.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use std::collections::BTreeMap;
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::Config};
+    ///
+    ///let mut source = BTreeMap::new();
+    ///source.insert("menu.open".to_string(), "Open".to_string());
+    ///
+    ///let cfg = Config::new()
+    ///    .source_lang("en")
+    ///    .add_target_lang("fr")
+    ///    .use_cache(true)
+    ///    .build();
+    ///
+    ///let translated = TranslationAPI::translate_from_map(cfg, source).unwrap();
+    /// ```
+    pub fn translate_from_map(
+        config: Config,
+        source_map: BTreeMap<String, String>,
+    ) -> Result<HashMap<String, BTreeMap<String, String>>, TranslationError> {
+        let mut targets = config.targets();
+        for group in &config.groups {
+            if group.source_locale == config.source_locale {
+                targets.extend(group.target_locales.iter().map(String::as_str));
+            } else {
+                info!(source = group.source_locale.as_str(); "Skipping the group of `{}`, the map is in `{}`", group.source_locale, config.source_locale);
+            }
+        }
+        Self::translate_from_map_targets(&config, &source_map, &targets)
+    }
+
+    /// Translate an in-memory source map into the given target locales, the targets of the config are ignored.
+    ///
+    /// Borrows the config, source and targets, for translating repeatedly at runtime.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use std::collections::BTreeMap;
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::Config};
+    ///
+    ///let mut source = BTreeMap::new();
+    ///source.insert("menu.open".to_string(), "Open".to_string());
+    ///
+    ///let cfg = Config::new().source_lang("en").use_cache(true).build();
+    ///
+    ///let translated = TranslationAPI::translate_from_map_targets(&cfg, &source, &["fr", "de"]).unwrap();
+    /// ```
+    pub fn translate_from_map_targets(
+        config: &Config,
+        source_map: &BTreeMap<String, String>,
+        targets: &[&str],
+    ) -> Result<HashMap<String, BTreeMap<String, String>>, TranslationError> {
+        let _log = LogScope::enter(config);
+        if !config.enabled {
+            info!("Translations disabled, nothing to do");
+            return Ok(HashMap::new());
+        }
+
+        dotenvy::dotenv().ok();
+        let journal = JournalScope::enter(config);
+
+        let mut autogen = if config.cache_mode != CacheMode::Off {
+            Autogen::load(config)
+        } else {
+            Autogen::default()
+        };
+
+        //the cached translations were made with other settings, eg: another provider
+        let settings_changed = autogen
+            .settings_of(&config.source_locale)
+            .is_some_and(|previous| *previous != config.settings_hash());
+        if settings_changed {
+            info!("The settings differ from the ones of the cache, translating without it");
+        }
+        let reads = config.cache_mode.reads() && !settings_changed;
+        let writes = config.cache_mode.writes() && !settings_changed;
+
+        //an unchanged map is served from the cache, nothing is sent
+        let contexts = load_contexts(config);
+        let checksum = map_checksum(source_map, &contexts);
+        let cache_key = config.cache_key_normalization;
+
+        let mut translated_locales = HashMap::with_capacity(targets.len());
+        let mut report = TranslationReport::default();
+        let mut chars_sent = 0;
+        let mut stop_res = Ok(());
+
+        for &target_locale in targets {
+            let config = config.routed(target_locale);
+            if let Some(hook) = &config.before_locale {
+                (hook.0)(target_locale);
+            }
+            match skips_unsupported(&config, target_locale) {
+                Ok(false) => {}
+                Ok(true) => {
+                    report.skip(target_locale, SkipReason::UnsupportedLanguage);
+                    after_locale(&config, &report);
+                    continue;
+                }
+                //`UnsupportedLanguage::Abort` stops the run
+                Err(e) => {
+                    record_locale_error(&config, &mut report, target_locale, &e);
+                    stop_res = Err(e);
+                    break;
+                }
+            }
+
+            let up_to_date = reads
+                && !config.force
+                && autogen
+                    .map_checksum_of(&config.source_locale, target_locale)
+                    .is_some_and(|previous| *previous == checksum);
+            let locked = map_locked_translations(
+                &config,
+                &mut autogen,
+                source_map,
+                &contexts,
+                target_locale,
+            );
+            let cached = autogen
+                .translations(&config.source_locale, target_locale)
+                .filter(|_| reads);
+
+            let mut values = Vec::with_capacity(source_map.len());
+            let mut value_contexts = Vec::with_capacity(source_map.len());
+            for (key, value) in source_map {
+                let context = contexts.get(key).map(String::as_str);
+                if up_to_date
+                    || locked.contains_key(key)
+                    || cached.is_some_and(|cached| {
+                        cached.contains_key(&*cache_key.context_key(value, context))
+                    })
+                {
+                    continue;
+                }
+                values.push(value.as_str());
+                value_contexts.push(context);
+            }
+
+            info!(
+                locale = target_locale,
+                keys = values.len();
+                "Translating locale"
+            );
+            debug!(
+                locale = target_locale,
+                cache_hits = source_map.len() - values.len();
+                "Cache hits"
+            );
+
+            let billable = billable_chars(&config.provider, &values);
+            if let Some(budget) = config.max_chars
+                && chars_sent + billable > budget
+            {
+                report.skip(target_locale, SkipReason::BudgetExceeded);
+                after_locale(&config, &report);
+                stop_res = Err(TranslationError::BudgetExceeded {
+                    sent: chars_sent,
+                    budget,
+                });
+                break;
+            }
+            chars_sent += billable;
+
+            let mut usage = ProviderUsage::default();
+            let translated_values = match translate_data_with_context(
+                &config,
+                &values,
+                &value_contexts,
+                target_locale,
+                &mut usage,
+            ) {
+                Ok(translated_values) => translated_values,
+                Err(e) => {
+                    //the other targets are still translated
+                    record_locale_error(&config, &mut report, target_locale, &e);
+                    continue;
+                }
+            };
+            chars_sent += usage.retried_characters;
+
+            if translated_values.len() != values.len() {
+                //some translations may have failed, so discard the whole translation
+                warn!(locale = target_locale; "Skipping `{target_locale}`, some translations failed");
+                report.fail(target_locale, Vec::new());
+                after_locale(&config, &report);
+                continue;
+            }
+
+            let autogen_locale = autogen.translations_mut(&config.source_locale, target_locale);
+            for ((value, context), translated) in
+                values.iter().zip(&value_contexts).zip(&translated_values)
+            {
+                autogen_locale.insert(
+                    cache_key.context_key(value, *context).into_owned(),
+                    translated.clone(),
+                );
+            }
+
+            let translated_kv = source_map
+                .iter()
+                .map(|(key, value)| {
+                    let context = contexts.get(key).map(String::as_str);
+                    let translated = locked
+                        .get(key)
+                        .or_else(|| autogen_locale.get(&*cache_key.context_key(value, context)))
+                        .unwrap_or(value);
+                    (key.to_string(), translated.to_string())
+                })
+                .collect();
+            autogen.set_map_checksum(&config.source_locale, target_locale, checksum.clone());
+
+            let entry = report.translated(target_locale);
+            entry.provider = Some(config.provider.clone());
+            entry.keys = source_map.len();
+            entry.cache_hits = source_map.len() - values.len();
+            entry.characters = billable + usage.retried_characters;
+            entry.requests = usage.requests;
+            entry.retries = usage.retries;
+            after_locale(&config, &report);
+            translated_locales.insert(target_locale.to_string(), translated_kv);
+            info!(locale = target_locale; "Locale finished");
+        }
+
+        if writes && let Err(err) = autogen.update_cache() {
+            error!("{}", err);
+        }
+
+        stop_res?;
+        report.locale_errors()?;
+        journal.complete();
+        Ok(translated_locales)
+    }
+
+    /// Translate the source locale with each provider and return the translations side by side.
+    ///
+    /// Nothing is written and the cache is not used, handy to pick the best provider for a language.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use rust_i18n_autotranslate::{
+    ///    TranslationAPI,
+    ///    config::{Config, TranslationProvider},
+    ///};
+    ///
+    ///let cfg = Config::new()
+    ///    .locales_directory("./locales")
+    ///    .source_lang("en")
+    ///    .build();
+    ///
+    ///let compared = TranslationAPI::compare(
+    ///    cfg,
+    ///    &[TranslationProvider::GOOGLE, TranslationProvider::DEEPL],
+    ///    "fr",
+    ///)
+    ///.unwrap();
+    ///
+    ///for (key, translations) in compared {
+    ///    println!("{key}: {translations:?}");
+    ///}
+    /// ```
+    pub fn compare(
+        config: Config,
+        providers: &[TranslationProvider],
+        target_lang: &str,
+    ) -> Result<HashMap<String, HashMap<TranslationProvider, String>>, TranslationError> {
+        let _log = LogScope::enter(&config);
+        if !config.enabled {
+            info!("Translations disabled, nothing to do");
+            return Ok(HashMap::new());
+        }
+
+        dotenvy::dotenv().ok();
+
+        let mut locales_data = load_locales_data(&config)?;
+        let source_data = locales_data
+            .remove(&config.source_locale)
+            .unwrap_or_default();
+
+        let values: Vec<&str> = source_data.values().map(|v| v.as_str()).collect();
+
+        let mut compared: HashMap<String, HashMap<TranslationProvider, String>> = HashMap::new();
+
+        for provider in providers {
+            let mut provider_config = config.clone();
+            provider_config.translation_provider(provider.clone());
+
+            let translated = translate_data(
+                &provider_config,
+                &values,
+                target_lang,
+                &mut ProviderUsage::default(),
+            )?;
+
+            if translated.len() != values.len() {
+                return Err(format!("Some translations failed with {provider:?}").into());
+            }
+
+            for (key, translation) in source_data.keys().zip(translated) {
+                compared
+                    .entry(key.to_string())
+                    .or_default()
+                    .insert(provider.clone(), translation);
+            }
+        }
+
+        Ok(compared)
+    }
+
+    /// Translate a map of key -> value without touching the locales directory or the cache.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use std::collections::BTreeMap;
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::TranslationProvider};
+    ///
+    ///let mut source = BTreeMap::new();
+    ///source.insert("menu.open".to_string(), "Open".to_string());
+    ///
+    ///let translated =
+    ///    TranslationAPI::translate_map(&TranslationProvider::GOOGLE, &source, "en", "fr").unwrap();
+    /// ```
+    pub fn translate_map(
+        provider: &TranslationProvider,
+        source_map: &BTreeMap<String, String>,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<BTreeMap<String, String>, TranslationError> {
+        dotenvy::dotenv().ok();
+
+        let values: Vec<&str> = source_map.values().map(|v| v.as_str()).collect();
+
+        let config = Config::new()
+            .translation_provider(provider.clone())
+            .source_lang(source_lang)
+            .build();
+
+        let translated =
+            translate_data(&config, &values, target_lang, &mut ProviderUsage::default())?;
+
+        if translated.len() != values.len() {
+            return Err("Some translations failed".into());
+        }
+
+        Ok(source_map.keys().cloned().zip(translated).collect())
+    }
+
+    /// Read a JSON object of key -> value from `reader` and write the translated JSON object to `writer`.
+    ///
+    /// Useful for composing with other tools, see [`TranslationAPI::translate_stdio`]
+    pub fn translate_json<R: Read, W: Write>(
+        provider: &TranslationProvider,
+        source_lang: &str,
+        target_lang: &str,
+        reader: R,
+        writer: W,
+    ) -> Result<(), TranslationError> {
+        let source_map: BTreeMap<String, String> =
+            serde_json::from_reader(reader).map_err(|e| format!("Invalid JSON input: {e}"))?;
+
+        let translated = Self::translate_map(provider, &source_map, source_lang, target_lang)?;
+
+        let mut writer = writer;
+        serde_json::to_writer_pretty(&mut writer, &translated).map_err(|e| e.to_string())?;
+        writeln!(writer).map_err(|e| e.to_string().into())
+    }
+
+    /// Translate a JSON object of key -> value read from stdin and write the result to stdout.
+    ///
+    /// Bypasses the locales directory and the cache, handy for piping through `jq` and friends.
+    pub fn translate_stdio(
+        provider: &TranslationProvider,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<(), TranslationError> {
+        Self::translate_json(
+            provider,
+            source_lang,
+            target_lang,
+            io::stdin().lock(),
+            io::stdout().lock(),
+        )
+    }
+
+    /// Write the cached translations to a TMX file, for CAT tools like OmegaT.
+    ///
+    /// Every source value is a `<tu>` with the source locale as `srclang`.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::Config};
+    ///
+    ///let cfg = Config::new().locales_directory("./locales").source_lang("en").build();
+    ///
+    ///TranslationAPI::export_tmx(&cfg, "./memory.tmx").unwrap();
+    /// ```
+    pub fn export_tmx<P: AsRef<Path>>(config: &Config, path: P) -> Result<(), TranslationError> {
+        let _log = LogScope::enter(config);
+        let autogen = Autogen::load(config);
+        let tmx = tmx::to_tmx(
+            &config.source_locale,
+            autogen
+                .data
+                .get(&config.source_locale)
+                .unwrap_or(&BTreeMap::new()),
+        );
+
+        fs::write(path, tmx).map_err(|e| e.to_string().into())
+    }
+
+    /// Add the translations of a TMX file to the cache, returns the number of translations read.
+    ///
+    /// Units without a segment in the source locale are ignored. Regional languages of the file are
+    /// matched to the target locales, eg: `fr-FR` is stored as `fr` when `fr` is a target.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::Config};
+    ///
+    ///let cfg = Config::new()
+    ///    .locales_directory("./locales")
+    ///    .source_lang("en")
+    ///    .add_target_lang("fr")
+    ///    .build();
+    ///
+    ///let imported = TranslationAPI::import_tmx(&cfg, "./memory.tmx").unwrap();
+    /// ```
+    pub fn import_tmx<P: AsRef<Path>>(config: &Config, path: P) -> Result<usize, TranslationError> {
+        let _log = LogScope::enter(config);
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let memory = tmx::from_tmx(&content, &config.source_locale, |lang| {
+            tmx_locale(config, lang)
+        })?;
+
+        let mut autogen = Autogen::load(config);
+        let mut imported = 0;
+        for (locale, translations) in memory {
+            for (source, translation) in translations {
+                autogen.insert(
+                    &config.source_locale,
+                    &locale,
+                    config.cache_key_normalization.key(&source),
+                    translation,
+                );
+                imported += 1;
+            }
+        }
+        autogen.update_cache()?;
+
+        info!(imported; "Imported {imported} translations");
+        Ok(imported)
+    }
+
+    /// Remove the cached translations of source values that are no longer in the source file,
+    /// returns the number of translations removed.
+    ///
+    /// Only the target locales of the config are pruned, with the source of their group.
+    /// Unlike [`Autogen::clear`] the translations still in use are kept.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::Config};
+    ///
+    ///let cfg = Config::new()
+    ///    .locales_directory("./locales")
+    ///    .source_lang("en")
+    ///    .add_target_lang("fr")
+    ///    .build();
+    ///
+    ///let removed = TranslationAPI::prune_cache(cfg).unwrap();
+    /// ```
+    pub fn prune_cache(config: Config) -> Result<usize, TranslationError> {
+        let _log = LogScope::enter(&config);
+        if !config.enabled {
+            info!("Translations disabled, nothing to do");
+            return Ok(0);
+        }
+
+        let mut autogen = Autogen::load(&config);
+        let mut removed = 0;
+
+        let contexts = load_contexts(&config);
+        for group_config in group_configs(&config) {
+            let locales_data = load_locales_data(&group_config)?;
+            let cache_key = config.cache_key_normalization;
+            let in_use: BTreeSet<_> = locales_data
+                .get(&group_config.source_locale)
+                .into_iter()
+                .flatten()
+                .map(|(key, value)| {
+                    cache_key
+                        .context_key(value, contexts.get(key).map(String::as_str))
+                        .into_owned()
+                })
+                .collect();
+
+            for target_locale in &group_config.target_locales {
+                let stale: Vec<String> = autogen
+                    .translations(&group_config.source_locale, target_locale)
+                    .into_iter()
+                    .flat_map(|translations| translations.keys())
+                    .filter(|value| !in_use.contains(*value))
+                    .cloned()
+                    .collect();
+
+                for value in stale {
+                    autogen.remove(&group_config.source_locale, target_locale, &value);
+                    removed += 1;
+                }
+            }
+        }
+        autogen.update_cache()?;
+
+        info!(removed; "Removed {removed} translations from the cache");
+        Ok(removed)
+    }
+
+    /// Billable characters a run would send for each target locale, nothing is sent.
+    ///
+    /// Values found in the cache and locked keys are not counted, nor are duplicates or the
+    /// targets skipped as unsupported, see `Config::on_unsupported`. With
+    /// `Config::price_per_million` the estimate has the approximate cost of each locale and the
+    /// total. Unlike [`TranslationAPI::translate`] the checksum of the source is ignored.
+    ///
+    /// Example:
+    /// ```rust,no_run
+    ///use rust_i18n_autotranslate::{TranslationAPI, config::Config};
+    ///
+    ///let cfg = Config::new()
+    ///    .locales_directory("./locales")
+    ///    .source_lang("en")
+    ///    .add_target_lang("fr")
+    ///    .price_per_million(20.0)
+    ///    .build();
+    ///
+    ///let estimate = TranslationAPI::estimate(cfg).unwrap();
+    ///println!("{} characters, ~{:.2}", estimate.characters, estimate.cost.unwrap());
+    /// ```
+    pub fn estimate(config: Config) -> Result<CostEstimate, TranslationError> {
+        let _log = LogScope::enter(&config);
+        let mut estimate = CostEstimate {
+            provider: Some(config.provider.clone()),
+            ..Default::default()
+        };
+        if !config.enabled {
+            info!("Translations disabled, nothing to do");
+            return Ok(estimate);
+        }
+
+        let autogen = Autogen::load(&config);
+        let cache_key = config.cache_key_normalization;
+        let contexts = load_contexts(&config);
+
+        for group_config in group_configs(&config) {
+            let mut locales_data = load_locales_data(&group_config)?;
+            let mut source_data = locales_data
+                .remove(&group_config.source_locale)
+                .unwrap_or_default();
+            if let Some(path) = &config.only_path {
+                source_data.retain(|key, _| in_path(key, path));
+            }
+
+            //a run translates again without the cache, see `Config::settings_hash`
+            let settings_changed = autogen
+                .settings_of(&group_config.source_locale)
+                .is_some_and(|previous| *previous != group_config.settings_hash());
+
+            for target_locale in &group_config.target_locales {
+                //converted locally, nothing is sent
+                if group_config.converted_from(target_locale).is_some() {
+                    continue;
+                }
+                let target_config = group_config.routed(target_locale);
+                if skips_unsupported(&target_config, target_locale)? {
+                    continue;
+                }
+                let locked =
+                    kept_locked_keys(&target_config, &autogen, &source_data, target_locale);
+                let cached = autogen
+                    .translations(&group_config.source_locale, target_locale)
+                    .filter(|_| config.cache_mode.reads() && !settings_changed);
+                let failures = autogen
+                    .failures(
+                        &target_config.provider,
+                        &group_config.source_locale,
+                        target_locale,
+                    )
+                    .filter(|_| config.cache_mode.reads() && !config.retry_failed);
+
+                let values: Vec<&str> = source_data
+                    .iter()
+                    .filter(|(key, value)| {
+                        let locked = locked.contains(key.as_str());
+                        let key =
+                            cache_key.context_key(value, contexts.get(*key).map(String::as_str));
+                        !locked
+                            && !cached.is_some_and(|cached| cached.contains_key(&*key))
+                            && !failures.is_some_and(|failures| failures.contains_key(&*key))
+                    })
+                    .map(|(_, value)| value.as_str())
+                    .collect();
+
+                estimate.add(
+                    target_locale,
+                    &target_config.provider,
+                    source_data.len(),
+                    source_data.len() - values.len(),
+                    billable_chars(&target_config.provider, &values),
+                    config.price_per_million,
+                );
+            }
+        }
+
+        Ok(estimate)
+    }
+}
+
+/// Config of every group of target locales with its source locale
+///
+/// The main source is a group of its own when it has targets
+fn group_configs(config: &Config) -> Vec<Config> {
+    let main_group = LocaleGroup {
+        source_locale: config.source_locale.clone(),
+        target_locales: config.target_locales.clone(),
+    };
+
+    (!main_group.target_locales.is_empty())
+        .then_some(main_group)
+        .into_iter()
+        .chain(config.groups.iter().cloned())
+        .map(|group| {
+            let mut group_config = config.clone();
+            group_config.source_locale = group.source_locale;
+            group_config.target_locales = group.target_locales;
+            group_config
+        })
+        .collect()
+}
+
+/// Run `translate` with the config, or with the config of each group when it has some
+///
+/// A group that fails does not stop the others, the first error is returned once they are
+/// done. Going over the budget stops the run, the budget is shared by the groups.
+fn for_each_group(
+    config: &Config,
+    mut translate: impl FnMut(Config) -> Result<(), TranslationError>,
+) -> Result<(), TranslationError> {
+    if config.groups.is_empty() {
+        return translate(config.clone());
+    }
+
+    let mut first_err = None;
+    for group_config in group_configs(config) {
+        let source = group_config.source_locale.clone();
+        match translate(group_config) {
+            Ok(()) => {}
+            Err(e @ TranslationError::BudgetExceeded { .. }) => return Err(first_err.unwrap_or(e)),
+            Err(e) => {
+                warn!(source = source.as_str(); "Could not translate the group of `{source}`: {e}");
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+
+    first_err.map_or(Ok(()), Err)
+}
+
+/// Locales data of the locales directory, with an entry for the source locale
+///
+/// Tells a missing source file apart from an empty one (warning, empty output)
+/// and an invalid one (error naming the file and line)
+fn load_locales_data(
+    config: &Config,
+) -> Result<BTreeMap<String, BTreeMap<String, String>>, TranslationError> {
+    let source_path = source_path(config)
+        .ok_or_else(|| TranslationError::SourceLocaleNotFound(config.source_locale.clone()))?;
+
+    if config.combine_output {
+        let source_data = load_namespaces(config)?;
+        //the namespace files would be read as locales named after the namespace
+        let namespaces = source_path.normalize().map(|p| p.into_path_buf());
+        let mut locales_data =
+            load_locales(config.locales_dir.to_str().unwrap_or_default(), |path| {
+                namespaces
+                    .as_ref()
+                    .is_ok_and(|namespaces| Path::new(path).starts_with(namespaces))
+            });
+        locales_data.insert(config.source_locale.clone(), source_data);
+        return Ok(locales_data);
+    }
+
+    let (content, encoding) =
+        read_locale_file(&source_path).map_err(|error| TranslationError::SourceParse {
+            file: source_path.display().to_string(),
+            error,
+        })?;
+    if let Some(encoding) = encoding {
+        info!(
+            "The source file {} is {encoding}, converted to UTF-8",
+            source_path.display()
+        );
+    }
+
+    let empty = content.trim().is_empty();
+    //the formats load_locales can read
+    let ext = source_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    if !matches!(ext, "json" | "yml" | "yaml" | "toml") {
+        return Err(TranslationError::UnsupportedFormat(ext.to_string()));
+    }
+
+    let output_ext = output_extension(config, &source_path);
+    if OutputFormat::from_extension(output_ext).is_none() {
+        return Err(TranslationError::UnsupportedFormat(output_ext.to_string()));
+    }
+
+    let parsed = if empty {
+        warn!("The source file {} is empty", source_path.display());
+        BTreeMap::new()
+    } else {
+        parse_locale_content(&content, ext).map_err(|error| TranslationError::SourceParse {
+            file: source_path.display().to_string(),
+            error,
+        })?
+    };
+
+    //load_locales panics on invalid and empty files, checked above,
+    //and can not read a byte order mark or UTF-16
+    let mut locales_data = load_locales(config.locales_dir.to_str().unwrap_or_default(), |path| {
+        (empty || encoding.is_some()) && std::path::Path::new(path) == source_path
+    });
+
+    let source_data = locales_data
+        .entry(config.source_locale.clone())
+        .or_default();
+    if encoding.is_some() {
+        *source_data = parsed;
+    } else {
+        expand_arrays(source_data, &parsed);
+    }
+    source_data.remove("_version");
+
+    Ok(locales_data)
+}
+
+/// Keys of the source namespace files under the name of their file, see `Config::combine_output`
+fn load_namespaces(config: &Config) -> Result<BTreeMap<String, String>, TranslationError> {
+    let mut source_data = BTreeMap::new();
+
+    for path in namespace_files(config) {
+        let namespace = locale_file_stem(&path, &config.locale_extensions).unwrap_or_default();
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let parse_error = |error: String| TranslationError::SourceParse {
+            file: path.display().to_string(),
+            error,
+        };
+
+        let (content, _) = read_locale_file(&path).map_err(parse_error)?;
+        let data = if content.trim().is_empty() {
+            BTreeMap::new()
+        } else {
+            parse_locale_content(&content, ext).map_err(parse_error)?
+        };
+
+        for (key, value) in data {
+            if key == "_version" {
+                continue;
+            }
+            let key = format!("{namespace}.{key}");
+            if source_data.contains_key(&key) {
+                return Err(TranslationError::KeyCollision(key));
+            }
+            source_data.insert(key, value);
+        }
+    }
+
+    Ok(source_data)
+}
+
+/// Schema of `Config::validate_schema`, compiled once per run
+fn load_schema(config: &Config) -> Result<Option<Validator>, TranslationError> {
+    config
+        .schema_file
+        .as_deref()
+        .map(schema::load)
+        .transpose()
+        .map_err(TranslationError::Other)
+}
+
+/// Keys of the target file that do not match the schema
+fn schema_errors(
+    config: &Config,
+    schema: Option<&Validator>,
+    data: &BTreeMap<String, String>,
+    target_locale: &str,
+) -> Vec<String> {
+    let Some(schema) = schema else {
+        return vec![];
+    };
+
+    let errors = schema::violations(schema, &locale_value(config, data, target_locale));
+    for e in &errors {
+        match config.on_schema_violation {
+            SchemaViolation::Fail => error!(locale = target_locale; "{e}"),
+            SchemaViolation::Warn => warn!(locale = target_locale; "{e}"),
+        }
+    }
+    errors
+}
+
+/// The file is not written, see `Config::on_schema_violation`
+fn rejects(config: &Config, schema_errors: &[String]) -> bool {
+    !schema_errors.is_empty() && config.on_schema_violation == SchemaViolation::Fail
+}
+
+/// Schema errors of the locales not written because of them, see `Config::on_schema_violation`
+fn schema_violations(entries: &[TranslationReportEntry]) -> BTreeMap<String, Vec<String>> {
+    entries
+        .iter()
+        .filter(|entry| entry.skipped == Some(SkipReason::SchemaViolation))
+        .map(|entry| (entry.locale.clone(), entry.schema_errors.clone()))
+        .collect()
+}
+
+/// Whether the target is skipped as the provider does not support it, see
+/// `Config::on_unsupported`
+fn skips_unsupported(config: &Config, target_locale: &str) -> Result<bool, TranslationError> {
+    if normalize_lang(&config.provider, target_locale).is_ok() {
+        return Ok(false);
+    }
+    match config.on_unsupported {
+        UnsupportedLanguage::Abort => Err(TranslationError::UnsupportedLanguage(
+            target_locale.to_string(),
+        )),
+        UnsupportedLanguage::Skip => {
+            warn!(locale = target_locale; "Skipping `{target_locale}`, not supported by the provider");
+            Ok(true)
+        }
+    }
+}
+
+/// What the target locales of a group share, see [`translate_locale`]
+struct GroupRun<'a> {
+    config: &'a Config,
+    source_data: &'a BTreeMap<String, String>,
+    contexts: &'a BTreeMap<String, String>,
+    schema: Option<&'a Validator>,
+    /// Checksums of the old values of the keys edited in place, see `Config::evict_stale_cache`
+    edited_values: &'a BTreeSet<String>,
+    /// Translations of the cache are reused, not when they were made with other settings
+    reads_cache: bool,
+    /// Checksum of the source saved with the checkpoints, see [`checkpoint`]
+    previous_checksum: Option<&'a String>,
+    /// Settings saved with the checkpoints, see [`checkpoint`]
+    previous_settings: Option<&'a String>,
+    /// Source at the git ref of `TranslationAPI::translate_since`
+    since: Option<&'a BTreeMap<String, String>>,
+}
+
+/// Translate the source of the group into a target locale and write its file
+///
+/// With `reads_cache` only the values missing from the cache are sent. The outcome is recorded
+/// in the report and passed to the `after_locale` hook. A provider error is recorded as
+/// `SkipReason::Failed` for the next locale to go on, the errors returned stop the run, eg: going
+/// over `Config::max_chars`.
+fn translate_locale(
+    run: &GroupRun,
+    autogen: &mut Autogen,
+    report: &mut TranslationReport,
+    chars_sent: &mut usize,
+    format_res: &mut Result<(), TranslationError>,
+    target_locale: &str,
+) -> Result<(), TranslationError> {
+    let (source_data, contexts) = (run.source_data, run.contexts);
+    let config = run.config.routed(target_locale);
+    let cache_key = config.cache_key_normalization;
+    if let Some(hook) = &config.before_locale {
+        (hook.0)(target_locale);
+    }
+
+    match skips_unsupported(&config, target_locale) {
+        Ok(false) => {}
+        Ok(true) => {
+            report.skip(target_locale, SkipReason::UnsupportedLanguage);
+            after_locale(&config, report);
+            return Ok(());
+        }
+        //`UnsupportedLanguage::Abort` stops the run
+        Err(e) => {
+            record_locale_error(&config, report, target_locale, &e);
+            return Err(e);
+        }
+    }
+    if keeps_existing_file(&config, target_locale) {
+        info!(locale = target_locale; "Skipping `{target_locale}`, the file already exists");
+        report.skip(target_locale, SkipReason::FileExists);
+        after_locale(&config, report);
+        return Ok(());
+    }
+
+    //the existing translations of the locked keys are kept
+    let locked = kept_locked_keys(&config, autogen, source_data, target_locale);
+    //and the ones of the keys unchanged since the git ref
+    let kept = run
+        .since
+        .map(|since| unchanged_translations(&config, source_data, since, target_locale))
+        .unwrap_or_default();
+    let cached = autogen
+        .translations(&config.source_locale, target_locale)
+        .filter(|_| run.reads_cache);
+    let failures = autogen
+        .failures(&config.provider, &config.source_locale, target_locale)
+        .filter(|_| run.reads_cache && !config.retry_failed);
+
+    let mut keys = Vec::with_capacity(source_data.len());
+    let mut values = Vec::with_capacity(source_data.len());
+    let mut value_contexts = Vec::with_capacity(source_data.len());
+    let mut cached_keys = Vec::new();
+    let mut untranslatable_keys = Vec::new();
+    for (key, value) in source_data {
+        if locked.contains(key.as_str()) || kept.contains_key(key) {
+            continue;
+        }
+        let context = contexts.get(key).map(String::as_str);
+        let value_key = cache_key.context_key(value, context);
+        if failures.is_some_and(|failures| failures.contains_key(&*value_key)) {
+            //failed in a previous run, written as the source value
+            untranslatable_keys.push(key.to_string());
+        } else if cached.is_some_and(|cached| cached.contains_key(&*value_key)) {
+            cached_keys.push(key.to_string());
+        } else {
+            keys.push(key.as_str());
+            values.push(value.as_str());
+            value_contexts.push(context);
+        }
+    }
+
+    info!(
+        locale = target_locale,
+        keys = values.len();
+        "Translating locale"
+    );
+    debug!(
+        locale = target_locale,
+        cache_hits = cached_keys.len();
+        "Cache hits"
+    );
+
+    let billable = billable_chars(&config.provider, &values);
+    if let Some(budget) = config.max_chars
+        && *chars_sent + billable > budget
+    {
+        report.skip(target_locale, SkipReason::BudgetExceeded);
+        after_locale(&config, report);
+        return Err(TranslationError::BudgetExceeded {
+            sent: *chars_sent,
+            budget,
+        });
+    }
+    *chars_sent += billable;
+
+    let mut usage = ProviderUsage::default();
+    let translated_res = match config.batch_size {
+        Some(batch_size) => translate_in_batches(
+            &config,
+            autogen,
+            Batches {
+                keys: &mut keys,
+                values: &mut values,
+                contexts: &mut value_contexts,
+                untranslatable_keys: &mut untranslatable_keys,
+                previous_checksum: run.previous_checksum,
+                previous_settings: run.previous_settings,
+                budget: config
+                    .max_chars
+                    .map(|budget| budget.saturating_sub(*chars_sent)),
+            },
+            batch_size,
+            target_locale,
+            &mut usage,
+        ),
+        None => translate_data_with_context(
+            &config,
+            &values,
+            &value_contexts,
+            target_locale,
+            &mut usage,
+        ),
+    };
+
+    let translated = match translated_res {
+        Ok(translated) if translated.len() == values.len() => translated,
+        //the batches already looked for the failing values
+        res => match (config.cache_mode.writes() && config.batch_size.is_none())
+            .then(|| {
+                isolate_failures(
+                    &config,
+                    &values,
+                    &value_contexts,
+                    target_locale,
+                    &res,
+                    config
+                        .max_chars
+                        .map(|budget| budget.saturating_sub(*chars_sent)),
+                    &mut usage,
+                )
+            })
+            .flatten()
+        {
+            Some(results) => {
+                //record the failing values, keep the others
+                let mut translated = Vec::with_capacity(results.len());
+                let mut kept = Vec::with_capacity(results.len());
+                for (((key, value), context), result) in
+                    keys.iter().zip(&values).zip(&value_contexts).zip(results)
+                {
+                    match result {
+                        Ok(translation) => {
+                            translated.push(translation);
+                            kept.push((*key, *value, *context));
+                        }
+                        Err(reason) => {
+                            warn!(locale = target_locale, key; "Could not translate `{key}`: {reason}");
+                            autogen.record_failure(
+                                &config.provider,
+                                &config.source_locale,
+                                target_locale,
+                                &cache_key.context_key(value, *context),
+                                &reason,
+                            );
+                            untranslatable_keys.push(key.to_string());
+                        }
+                    }
+                }
+                keys = kept.iter().map(|(key, _, _)| *key).collect();
+                values = kept.iter().map(|(_, value, _)| *value).collect();
+                value_contexts = kept.iter().map(|(_, _, context)| *context).collect();
+                translated
+            }
+            None => match res {
+                Ok(translated) => translated,
+                Err(e) => {
+                    record_locale_error(&config, report, target_locale, &e);
+                    return Ok(());
+                }
+            },
+        },
+    };
+
+    if translated.len() != keys.len() {
+        //some translations may have failed, so discard the whole translation
+        report.fail(target_locale, keys.iter().map(|k| k.to_string()).collect());
+        after_locale(&config, report);
+        return Ok(());
+    }
+
+    if config.retry_failed {
+        for (value, context) in values.iter().zip(&value_contexts) {
+            autogen.resolve_failure(
+                &config.provider,
+                &config.source_locale,
+                target_locale,
+                &cache_key.context_key(value, *context),
+            );
+        }
+    }
+
+    //update the autogen values
+    if config.cache_mode.writes() && !translated.is_empty() {
+        let autogen_locale = autogen.translations_mut(&config.source_locale, target_locale);
+        for ((value, context), translation) in values.iter().zip(&value_contexts).zip(&translated) {
+            autogen_locale.insert(
+                cache_key.context_key(value, *context).into_owned(),
+                translation.clone(),
+            );
+        }
+    }
+
+    //keys sent for translation, the others keep their translation or use the cached value or
+    //the source value
+    let sent: HashMap<&str, &String> = keys.iter().copied().zip(&translated).collect();
+    let cached = autogen
+        .translations(&config.source_locale, target_locale)
+        .filter(|_| run.reads_cache);
+    let mut translated_kv = BTreeMap::new();
+    for (key, value) in source_data {
+        let translation = sent
+            .get(key.as_str())
+            .copied()
+            .or_else(|| kept.get(key))
+            .or_else(|| {
+                let context = contexts.get(key).map(String::as_str);
+                cached.and_then(|cached| cached.get(&*cache_key.context_key(value, context)))
+            });
+        translated_kv.insert(key.to_string(), translation.unwrap_or(value).to_string());
+    }
+
+    //write the locale file
+    let translated_kv = merge_outside_path(&config, translated_kv, target_locale);
+    let translated_kv = apply_locks(&config, autogen, source_data, translated_kv, target_locale);
+    evict_edited_values(
+        &config,
+        autogen,
+        source_data,
+        run.edited_values,
+        target_locale,
+    );
+    //the retries are billed as well
+    *chars_sent += usage.retried_characters;
+    let entry = finish_locale(
+        &config,
+        run.schema,
+        report,
+        format_res,
+        &translated_kv,
+        target_locale,
+    );
+    entry.provider = Some(config.provider.clone());
+    entry.keys = source_data.len();
+    entry.cache_hits = cached_keys.len();
+    entry.duplicates = duplicate_count(&values);
+    entry.characters = billable + usage.retried_characters;
+    entry.billed_characters = usage.billed_characters;
+    entry.requests = usage.requests;
+    entry.retries = usage.retries;
+    entry.untranslated = usage.untranslated;
+    entry.glossary_id = used_glossary(&config, target_locale);
+    entry.cached_keys = cached_keys;
+    entry.untranslatable_keys = untranslatable_keys;
+    entry.translated_keys = keys.iter().map(|k| k.to_string()).collect();
+    info!(locale = target_locale; "Locale finished");
+    after_locale(&config, report);
+    Ok(())
+}
+
+/// Source data at the git ref, see `TranslationAPI::translate_since`
+///
+/// `None` when git is not available or the source file does not exist at the ref, the whole
+/// source is translated then
+fn source_at(config: &Config, git_ref: &str) -> Option<BTreeMap<String, String>> {
+    let source_path = source_path(config)?;
+    let ext = source_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("json");
+
+    match git_show(&source_path, git_ref).and_then(|c| parse_locale_content(&c, ext)) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            warn!("Could not read the source at `{git_ref}`: {e}");
+            info!("Running a full translation...");
+            None
+        }
+    }
+}
+
+/// Translations of the target file for the keys whose source value is the same as at the git
+/// ref, see `TranslationAPI::translate_since`
+fn unchanged_translations(
+    config: &Config,
+    source_data: &BTreeMap<String, String>,
+    since: &BTreeMap<String, String>,
+    target_locale: &str,
+) -> BTreeMap<String, String> {
+    let existing = match existing_translations(config, target_locale) {
+        Some(Ok(existing)) => existing,
+        Some(Err(e)) => {
+            warn!(locale = target_locale; "{e}, translating every key");
+            return BTreeMap::new();
+        }
+        None => return BTreeMap::new(),
+    };
+
+    let kept: BTreeMap<String, String> = existing
+        .into_iter()
+        .filter(|(key, _)| {
+            source_data
+                .get(key)
+                .is_some_and(|value| since.get(key) == Some(value))
+        })
+        .collect();
+    info!(
+        locale = target_locale,
+        keys = source_data.len() - kept.len();
+        "{} keys changed or missing since the git ref",
+        source_data.len() - kept.len()
+    );
+    kept
+}
+
+/// Validate, write, format and set the permissions of a target file, shared by every path
+/// writing one
+///
+/// A file rejected by the schema is not written. The outcome is recorded in the report entry
+/// of the locale, returned for the stats of the caller
+fn finish_locale<'r>(
+    config: &Config,
+    schema: Option<&Validator>,
+    report: &'r mut TranslationReport,
+    format_res: &mut Result<(), TranslationError>,
+    translated_kv: &BTreeMap<String, String>,
+    target_locale: &str,
+) -> &'r mut TranslationReportEntry {
+    let previous = previous_translations(config, target_locale);
+    let schema_errors = schema_errors(config, schema, translated_kv, target_locale);
+    let write_res = if rejects(config, &schema_errors) {
+        Err(SkipReason::SchemaViolation)
+    } else {
+        write_locale_file(config, translated_kv, target_locale).map_err(SkipReason::WriteFailed)
+    };
+
+    if let Err(SkipReason::WriteFailed(e)) = &write_res {
+        error!(locale = target_locale; "{e}");
+    }
+    if write_res == Ok(FileWrite::Written) {
+        //the files are written unformatted when the formatter fails
+        if let Err(e) = format_locale_file(config, target_locale) {
+            error!(locale = target_locale; "{e}");
+            if format_res.is_ok() {
+                *format_res = Err(TranslationError::FormatCommand(e));
+            }
+        }
+        if let Err(e) = set_file_permissions(config, target_locale) {
+            error!(locale = target_locale; "{e}");
+        }
+    }
+
+    let entry = report.translated(target_locale);
+    match write_res {
+        Ok(file) => {
+            entry.file = Some(file);
+            entry.file_name =
+                source_path(config).map(|path| output_file_name(config, &path, target_locale));
+            entry.changes = key_changes(previous, translated_kv, file);
+        }
+        Err(reason) => entry.skipped = Some(reason),
+    }
+    entry.schema_errors = schema_errors;
+    entry
+}
+
+/// Write the Chinese targets converted from the target in the other script, see
+/// `Config::zh_convert`
+fn convert_zh_locales(
+    config: &Config,
+    schema: Option<&Validator>,
+    report: &mut TranslationReport,
+    format_res: &mut Result<(), TranslationError>,
+) {
+    for target_locale in &config.target_locales {
+        let Some(base) = config.converted_from(target_locale) else {
+            continue;
+        };
+        if let Some(hook) = &config.before_locale {
+            (hook.0)(target_locale);
+        }
+        if keeps_existing_file(config, target_locale) {
+            report.skip(target_locale, SkipReason::FileExists);
+            after_locale(config, report);
+            continue;
+        }
+
+        let converted = match existing_translations(config, base) {
+            Some(Ok(mut translations)) => {
+                if config.emit_direction {
+                    translations.remove("_dir");
+                }
+                let values: Vec<&str> = translations.values().map(String::as_str).collect();
+                convert_zh(config, &values).map(|converted| {
+                    translations
+                        .keys()
+                        .cloned()
+                        .zip(converted)
+                        .collect::<BTreeMap<_, _>>()
+                })
+            }
+            Some(Err(e)) => Err(e),
+            None => Err(format!("`{base}` has no file to convert")),
+        };
+        let converted = match converted {
+            Ok(converted) => converted,
+            Err(e) => {
+                error!(locale = target_locale.as_str(); "Could not convert `{base}`: {e}");
+                report.skip(target_locale, SkipReason::ConversionFailed(e));
+                after_locale(config, report);
+                continue;
+            }
+        };
+
+        info!(locale = target_locale.as_str(); "Converted from `{base}`");
+        let entry = finish_locale(
+            config,
+            schema,
+            report,
+            format_res,
+            &converted,
+            target_locale,
+        );
+        entry.keys = converted.len();
+        entry.converted_from = Some(base.to_string());
+        after_locale(config, report);
+    }
+}
+
+/// Checksums of the source values by key as cached, see `Config::evict_stale_cache`
+fn value_checksums(
+    config: &Config,
+    source_data: &BTreeMap<String, String>,
+    contexts: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let cache_key = config.cache_key_normalization;
+    source_data
+        .iter()
+        .map(|(key, value)| {
+            let value = cache_key.context_key(value, contexts.get(key).map(String::as_str));
+            (key.to_string(), value_checksum(&value))
+        })
+        .collect()
+}
+
+/// Checksums of the old values of the keys edited since the checksums were recorded
+fn edited_values(
+    previous: Option<&BTreeMap<String, String>>,
+    current: &BTreeMap<String, String>,
+) -> BTreeSet<String> {
+    let Some(previous) = previous else {
+        return BTreeSet::new();
+    };
+    current
+        .iter()
+        .filter_map(|(key, checksum)| previous.get(key).filter(|old| *old != checksum))
+        .cloned()
+        .collect()
+}
+
+/// Remove the cached translations of the old source values of the edited keys, see
+/// `Config::evict_stale_cache`
+///
+/// `edited` has the checksums of the old values, a cached value still in the source is kept
+fn evict_edited_values(
+    config: &Config,
+    autogen: &mut Autogen,
+    source_data: &BTreeMap<String, String>,
+    edited: &BTreeSet<String>,
+    target_locale: &str,
+) {
+    if edited.is_empty() || !config.evict_stale_cache || !config.cache_mode.writes() {
+        return;
+    }
+    let cache_key = config.cache_key_normalization;
+    let contexts = load_contexts(config);
+    let in_use: BTreeSet<_> = source_data
+        .iter()
+        .map(|(key, value)| cache_key.context_key(value, contexts.get(key).map(String::as_str)))
+        .collect();
+
+    let stale: Vec<String> = autogen
+        .translations(&config.source_locale, target_locale)
+        .into_iter()
+        .flatten()
+        .map(|(value, _)| value)
+        .filter(|value| !in_use.contains(value.as_str()) && edited.contains(&value_checksum(value)))
+        .cloned()
+        .collect();
+
+    for value in stale {
+        debug!(locale = target_locale; "Evicting the edited value `{value}` from the cache");
+        autogen.remove(&config.source_locale, target_locale, &value);
+    }
+}
+
+/// Translations of the target file before it is written, see `Config::report_changes`
+fn previous_translations(config: &Config, target_locale: &str) -> Option<BTreeMap<String, String>> {
+    if !config.report_changes {
+        return None;
+    }
+
+    match existing_translations(config, target_locale) {
+        None => Some(BTreeMap::new()),
+        Some(Ok(mut existing)) => {
+            if config.emit_direction {
+                existing.remove("_dir");
+            }
+            Some(existing)
+        }
+        Some(Err(e)) => {
+            warn!(locale = target_locale; "Not reporting the changes, {e}");
+            None
+        }
+    }
+}
+
+/// Keys the write changed, the file is untouched when kept
+fn key_changes(
+    previous: Option<BTreeMap<String, String>>,
+    translated: &BTreeMap<String, String>,
+    file: FileWrite,
+) -> Option<KeyChanges> {
+    match file {
+        FileWrite::Written => previous.map(|previous| KeyChanges::between(&previous, translated)),
+        FileWrite::Unchanged => previous.map(|_| KeyChanges::default()),
+        FileWrite::Kept => None,
+    }
+}
+
+/// `load_locales` keeps arrays as empty values, use their items instead, eg: `features.0.title`
+fn expand_arrays(source_data: &mut BTreeMap<String, String>, parsed: &BTreeMap<String, String>) {
+    let arrays: Vec<String> = source_data
+        .iter()
+        .filter(|(key, value)| {
+            value.is_empty()
+                && !parsed.contains_key(*key)
+                && parsed.keys().any(|item| in_path(item, key))
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    for key in arrays {
+        source_data.remove(&key);
+        source_data.extend(
+            parsed
+                .iter()
+                .filter(|(item, _)| in_path(item, &key))
+                .map(|(item, value)| (item.clone(), value.clone())),
+        );
+    }
+}
+
+/// DeepL glossary sent for the target locale
+fn used_glossary(config: &Config, target_locale: &str) -> Option<String> {
+    if config.provider != TranslationProvider::DEEPL {
+        return None;
+    }
+
+    let glossary_id = config
+        .deepl_glossary(&config.source_locale, target_locale)
+        .cloned();
+    if let Some(glossary_id) = &glossary_id {
+        info!(locale = target_locale, glossary = glossary_id.as_str(); "Used DeepL glossary `{glossary_id}`");
+    }
+
+    glossary_id
+}
+
+/// Add the matches of `Config::translation_memory` missing from the cache of the targets
+fn seed_from_memory(config: &Config, autogen: &mut Autogen) -> Result<(), TranslationError> {
+    let Some(path) = &config.translation_memory else {
+        return Ok(());
+    };
+    let content = fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Could not read the translation memory {}: {e}",
+            path.display()
+        )
+    })?;
+    let memory = tmx::from_tmx(&content, &config.source_locale, |lang| {
+        tmx_locale(config, lang)
+    })?;
+
+    let mut seeded = 0;
+    for target_locale in &config.target_locales {
+        for (source, translation) in memory.get(target_locale).into_iter().flatten() {
+            let key = config.cache_key_normalization.key(source);
+            if autogen
+                .get(&config.source_locale, target_locale, &key)
+                .is_none()
+            {
+                autogen.insert(
+                    &config.source_locale,
+                    target_locale,
+                    key,
+                    translation.as_str(),
+                );
+                seeded += 1;
+            }
+        }
+    }
+
+    info!(seeded; "Seeded {seeded} translations from the translation memory");
+    Ok(())
+}
+
+/// Locale of a TMX language, the source or a target locale when it matches
+fn tmx_locale(config: &Config, lang: &str) -> String {
+    let primary = |code: &str| {
+        code.split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+    };
+
+    std::iter::once(&config.source_locale)
+        .chain(&config.target_locales)
+        .find(|locale| locale.eq_ignore_ascii_case(lang))
+        .or_else(|| {
+            std::iter::once(&config.source_locale)
+                .chain(&config.target_locales)
+                .find(|locale| primary(locale) == primary(lang))
+        })
+        .cloned()
+        .unwrap_or_else(|| lang.to_string())
+}
+
+/// Find the values of a failed batch that fail, by sending its halves again until the failing
+/// values are alone
+///
+/// The characters sent again are counted in `ProviderUsage::retried_characters`, `budget` is
+/// what is left of `Config::max_chars` for them.
+///
+/// `None` when every value fails or the failure is about the account, it is then not specific
+/// to the values (eg: network down or invalid api key) and nothing should be recorded. `None`
+/// as well when the budget can't cover the values sent again.
+fn isolate_failures(
+    config: &Config,
+    values: &[&str],
+    contexts: &[Option<&str>],
+    target_locale: &str,
+    failure: &Result<Vec<String>, TranslationError>,
+    budget: Option<usize>,
+    usage: &mut ProviderUsage,
+) -> Option<Vec<Result<String, String>>> {
+    if values.len() < 2
+        || matches!(failure, Err(TranslationError::Provider(e)) if e.is_account_error())
+    {
+        return None;
+    }
+    debug!(locale = target_locale, values = values.len(); "Batch failed, sending its halves again");
+
+    let mut results = Vec::with_capacity(values.len());
+    //ranges left to send, the next one on top
+    let half = values.len() / 2;
+    let mut pending = vec![half..values.len(), 0..half];
+    while let Some(range) = pending.pop() {
+        let resent = billable_chars(&config.provider, &values[range.clone()]);
+        if let Some(budget) = budget
+            && usage.retried_characters + resent > budget
+        {
+            warn!(locale = target_locale; "The budget can't cover finding the failing values");
+            return None;
+        }
+        usage.retried_characters += resent;
+
+        let range_contexts = contexts.get(range.clone()).unwrap_or_default();
+        match translate_data_with_context(
+            config,
+            &values[range.clone()],
+            range_contexts,
+            target_locale,
+            usage,
+        ) {
+            Ok(translated) if translated.len() == range.len() => {
+                results.extend(translated.into_iter().map(Ok))
+            }
+            Err(TranslationError::Provider(e)) if e.is_account_error() => return None,
+            res if range.len() == 1 => results.push(match res {
+                Err(e) => Err(e.to_string()),
+                Ok(_) => Err("No translation returned".to_string()),
+            }),
+            _ => {
+                let half = range.start + range.len() / 2;
+                pending.push(half..range.end);
+                pending.push(range.start..half);
+            }
+        }
+    }
+
+    results.iter().any(Result::is_ok).then_some(results)
+}
+
+/// Values of a locale left to translate, see [`translate_in_batches`]
+struct Batches<'a, 'b> {
+    keys: &'b mut Vec<&'a str>,
+    values: &'b mut Vec<&'a str>,
+    contexts: &'b mut Vec<Option<&'a str>>,
+    untranslatable_keys: &'b mut Vec<String>,
+    /// Checksum of the source saved with a checkpoint, a stopped run translates again
+    previous_checksum: Option<&'b String>,
+    /// Settings saved with a checkpoint, the cache may still hold translations made with them
+    previous_settings: Option<&'b String>,
+    /// What is left of `Config::max_chars` to find the failing values, see [`isolate_failures`]
+    budget: Option<usize>,
+}
+
+/// Translate the values in batches of `Config::batch_size`, saving the cache after each one
+///
+/// The target file is only written once the locale is done, through the usual pipeline. A
+/// stopped run resumes from the translations saved in the cache.
+///
+/// The values that failed are removed from the batches and recorded in the cache like
+/// [`isolate_failures`] does for a whole locale, the translations are in the order of the
+/// remaining values. Without cache writes a failed batch stops the locale, the translations
+/// are then fewer than the values.
+fn translate_in_batches(
+    config: &Config,
+    autogen: &mut Autogen,
+    batches: Batches,
+    batch_size: usize,
+    target_locale: &str,
+    usage: &mut ProviderUsage,
+) -> Result<Vec<String>, TranslationError> {
+    let cache_key = config.cache_key_normalization;
+    let writes = config.cache_mode.writes();
+    let mut translated = Vec::with_capacity(batches.values.len());
+    let mut kept = vec![true; batches.values.len()];
+
+    for (batch, start) in (0..batches.values.len()).step_by(batch_size).enumerate() {
+        let end = (start + batch_size).min(batches.values.len());
+        let values = &batches.values[start..end];
+        let contexts = &batches.contexts[start..end];
+        debug!(locale = target_locale, batch, values = values.len(); "Translating batch");
+
+        let results: Vec<Result<String, String>> =
+            match translate_data_with_context(config, values, contexts, target_locale, usage) {
+                Ok(batch) if batch.len() == values.len() => batch.into_iter().map(Ok).collect(),
+                res => match writes
+                    .then(|| {
+                        isolate_failures(
+                            config,
+                            values,
+                            contexts,
+                            target_locale,
+                            &res,
+                            batches.budget,
+                            usage,
+                        )
+                    })
+                    .flatten()
+                {
+                    Some(results) => results,
+                    None => {
+                        translated.extend(res?);
+                        return Ok(translated);
+                    }
+                },
+            };
+
+        for (idx, result) in (start..end).zip(results) {
+            let value = batches.values[idx];
+            match result {
+                Ok(translation) => {
+                    if writes {
+                        autogen.insert(
+                            &config.source_locale,
+                            target_locale,
+                            cache_key.context_key(value, batches.contexts[idx]),
+                            translation.as_str(),
+                        );
+                    }
+                    translated.push(translation);
+                }
+                Err(reason) => {
+                    let key = batches.keys[idx];
+                    warn!(locale = target_locale, key; "Could not translate `{key}`: {reason}");
+                    autogen.record_failure(
+                        &config.provider,
+                        &config.source_locale,
+                        target_locale,
+                        &cache_key.context_key(value, batches.contexts[idx]),
+                        &reason,
+                    );
+                    batches.untranslatable_keys.push(key.to_string());
+                    kept[idx] = false;
+                }
+            }
+        }
+
+        if writes {
+            checkpoint(
+                config,
+                autogen,
+                batches.previous_checksum,
+                batches.previous_settings,
+            );
+        }
+    }
+
+    let mut kept_iter = kept.iter();
+    batches.keys.retain(|_| *kept_iter.next().unwrap_or(&true));
+    let mut kept_iter = kept.iter();
+    batches
+        .values
+        .retain(|_| *kept_iter.next().unwrap_or(&true));
+    let mut kept_iter = kept.iter();
+    batches
+        .contexts
+        .retain(|_| *kept_iter.next().unwrap_or(&true));
+
+    Ok(translated)
+}
+
+/// Save the cache with the translations done so far
+///
+/// The checksum and the settings of the last completed run are saved with it, so the source
+/// is translated again from the cache when the run is stopped.
+fn checkpoint(
+    config: &Config,
+    autogen: &mut Autogen,
+    previous_checksum: Option<&String>,
+    previous_settings: Option<&String>,
+) {
+    let checksum = autogen.checksum_of(&config.source_locale).cloned();
+    let settings = autogen.settings_of(&config.source_locale).cloned();
+    autogen.set_checksum(&config.source_locale, previous_checksum.cloned());
+    if let Some(previous_settings) = previous_settings {
+        autogen.set_settings(&config.source_locale, previous_settings.clone());
+    }
+    if let Err(e) = autogen.update_cache() {
+        error!("{e}");
+    }
+    autogen.set_checksum(&config.source_locale, checksum);
+    if let Some(settings) = settings {
+        autogen.set_settings(&config.source_locale, settings);
+    }
+}
+
+/// `Config::overwrite` is `Never` and the target file exists, no need to translate it
+fn keeps_existing_file(config: &Config, target_locale: &str) -> bool {
+    config.overwrite == Overwrite::Never
+        && source_path(config).is_some_and(|source_path| {
+            output_file_path(config, &source_path, target_locale).exists()
+        })
+}
+
+/// Record the error of a locale and run the `after_locale` hook
+fn record_locale_error(
+    config: &Config,
+    report: &mut TranslationReport,
+    target_locale: &str,
+    error: &TranslationError,
+) {
+    error!(locale = target_locale; "Could not translate `{target_locale}`: {error}");
+    report.skip(target_locale, SkipReason::Failed(error.to_string()));
+    after_locale(config, report);
+}
+
+/// Checksum of an in-memory source map and the contexts of its keys
+fn map_checksum(
+    source_map: &BTreeMap<String, String>,
+    contexts: &BTreeMap<String, String>,
+) -> String {
+    let mut content = String::new();
+    for (key, value) in source_map {
+        let context = contexts.get(key).map(String::as_str).unwrap_or_default();
+        content.push_str(&format!("{key}\u{0}{value}\u{0}{context}\n"));
+    }
+    sha256::digest(content)
+}
+
+/// Run the `after_locale` hook with the last entry of the report
+fn after_locale(config: &Config, report: &TranslationReport) {
+    if let Some(hook) = &config.after_locale
+        && let Some(entry) = report.locales.last()
+    {
+        (hook.0)(&entry.locale, entry);
+    }
+}
+
+/// Translate a single string, no config or locales directory needed.
+///
+/// Example:
+/// ```rust,no_run
+///use rust_i18n_autotranslate::{config::TranslationProvider, quick_translate};
+///
+///let translated = quick_translate("Hello", "en", "fr", TranslationProvider::GOOGLE).unwrap();
+/// ```
+pub fn quick_translate(
+    text: &str,
+    source_lang: &str,
+    target_lang: &str,
+    provider: TranslationProvider,
+) -> Result<String, TranslationError> {
+    dotenvy::dotenv().ok();
+
+    let config = Config::new()
+        .translation_provider(provider)
+        .source_lang(source_lang)
+        .build();
+
+    let translated = translate_data(&config, &[text], target_lang, &mut ProviderUsage::default())?;
+
+    translated
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No translation returned by the provider".into())
+}
+
+#[test]
+fn test_translate_json_invalid_input() {
+    let mut out = Vec::new();
+    let res = TranslationAPI::translate_json(
+        &TranslationProvider::GOOGLE,
+        "en",
+        "fr",
+        "[\"not\", \"an object\"]".as_bytes(),
+        &mut out,
+    );
+
+    assert!(
+        res.unwrap_err()
+            .to_string()
+            .starts_with("Invalid JSON input")
+    );
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_translate_from_map_skips_unsupported() {
+    let mut source = BTreeMap::new();
+    source.insert("menu.open".to_string(), "Open".to_string());
+
+    let config = Config::new()
+        .source_lang("en")
+        .add_target_lang("not-a-language")
+        .use_cache(false)
+        .on_unsupported(UnsupportedLanguage::Skip)
+        .build();
+
+    let translated = TranslationAPI::translate_from_map(config, source.clone());
+    assert_eq!(translated, Ok(HashMap::new()));
+
+    let config = Config::new()
+        .source_lang("en")
+        .add_target_lang("not-a-language")
+        .use_cache(false)
+        .build();
+
+    assert_eq!(
+        TranslationAPI::translate_from_map(config, source),
+        Err(TranslationError::UnsupportedLanguage(
+            "not-a-language".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_translate_from_map_targets() {
+    let mut source = BTreeMap::new();
+    source.insert("menu.save".to_string(), "Save".to_string());
+
+    let config = Config::new()
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .add_target_lang("ja")
+        .use_cache(false)
+        .build();
+
+    let translated = TranslationAPI::translate_from_map_targets(&config, &source, &["fr", "de"]);
+
+    let translated = translated.unwrap();
+    let mut locales: Vec<&String> = translated.keys().collect();
+    locales.sort();
+    assert_eq!(locales, ["de", "fr"]);
+    assert_eq!(translated["fr"]["menu.save"], "Enregistrer");
+}
+
+#[test]
+fn test_translate_from_map_failed_target() {
+    use std::sync::atomic::Ordering;
+
+    let (endpoint, served) = mock_deeplx_failing(&[("Hello", "Hallo")], &["FR"]);
+    let locales = &TempDir::new("locales_from_map_failed");
+    let source = BTreeMap::from([("a".to_string(), "Hello".to_string())]);
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .build();
+    let failed = TranslationAPI::translate_from_map_targets(&config, &source, &["de", "fr", "nl"]);
+    let autogen = Autogen::load(&config);
+    let requests = served.load(Ordering::SeqCst);
+    //the map is unchanged, nothing is sent
+    let translated = TranslationAPI::translate_from_map_targets(&config, &source, &["de", "nl"]);
+    let unchanged_requests = served.load(Ordering::SeqCst);
+
+    assert!(matches!(
+        failed,
+        Err(TranslationError::LocalesFailed(errors)) if errors.keys().eq(["fr"])
+    ));
+    assert_eq!(autogen.get("en", "de", "Hello"), Some("Hallo"));
+    assert_eq!(autogen.get("en", "nl", "Hello"), Some("Hallo"));
+    assert!(autogen.map_checksum_of("en", "de").is_some());
+    assert!(autogen.map_checksum_of("en", "fr").is_none());
+    let translated = translated.unwrap();
+    assert_eq!(translated["de"]["a"], "Hallo");
+    assert_eq!(translated["nl"]["a"], "Hallo");
+    assert_eq!(unchanged_requests, requests);
+}
+
+#[test]
+fn test_translate_from_map_locks_and_contexts() {
+    use std::fs;
+
+    let (endpoint, _) = mock_deeplx(&[("Hello", "Hallo"), ("Hi", "Moin")]);
+    let locales = &TempDir::new("locales_from_map_locks");
+    let contexts = locales.join("contexts.json");
+    fs::write(&contexts, r#"{"a": "Greeting"}"#).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .context_file(&contexts)
+        .lock_key("a")
+        .build();
+    let first = TranslationAPI::translate_from_map_targets(
+        &config,
+        &BTreeMap::from([("a".to_string(), "Hello".to_string())]),
+        &["de"],
+    );
+    //the locked key keeps the translation of its value when it was locked
+    let second = TranslationAPI::translate_from_map_targets(
+        &config,
+        &BTreeMap::from([
+            ("a".to_string(), "Hi".to_string()),
+            ("b".to_string(), "Hi".to_string()),
+        ]),
+        &["de"],
+    );
+    let autogen = Autogen::load(&config);
+
+    assert_eq!(first.unwrap()["de"]["a"], "Hallo");
+    let second = second.unwrap();
+    assert_eq!(second["de"]["a"], "Hallo");
+    assert_eq!(second["de"]["b"], "Moin");
+    let context_key = config
+        .cache_key_normalization
+        .context_key("Hello", Some("Greeting"));
+    assert_eq!(autogen.get("en", "de", &context_key), Some("Hallo"));
+    assert_eq!(autogen.locked_source("en", "de", "a"), Some("Hello"));
+}
+
+#[test]
+fn test_compare_dictionary() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_compare");
+    fs::write(locales.join("en.json"), r#"{"menu": {"save": "Save"}}"#).unwrap();
+
+    let config = Config::new().locales_directory(locales).build();
+    let compared = TranslationAPI::compare(config, &[TranslationProvider::DICTIONARY], "de");
+
+    let mut expected = HashMap::new();
+    expected.insert(
+        "menu.save".to_string(),
+        HashMap::from([(TranslationProvider::DICTIONARY, "Speichern".to_string())]),
+    );
+    assert_eq!(compared, Ok(expected));
+}
+
+#[test]
+fn test_translate_empty_source() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_empty_source");
+    fs::write(locales.join("en.json"), "{}").unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .build();
+    let report = TranslationAPI::translate_with_report(config);
+    let written = locales.join("fr.json").exists();
+
+    let report = report.unwrap();
+    assert_eq!(report.locales.len(), 1);
+    assert_eq!(report.locales[0].skipped, Some(SkipReason::EmptySource));
+    assert!(!written);
+}
+
+#[test]
+fn test_translate_disabled() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_disabled");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .enabled(false)
+        .build();
+
+    let report = TranslationAPI::translate_with_report(config);
+    let written = locales.join("fr.json").exists();
+
+    assert!(report.unwrap().locales.is_empty());
+    assert!(!written);
+}
+
+#[test]
+fn test_load_locales_data_source_errors() {
+    let locales = &TempDir::new("locales_source_errors");
+    let config = Config::new().locales_directory(locales).build();
+
+    let missing = load_locales_data(&config);
+
+    fs::write(locales.join("en.json"), "  \n").unwrap();
+    let empty = load_locales_data(&config);
+
+    fs::write(locales.join("en.json"), "{\n  \"hello\": \"Hello\",\n}").unwrap();
+    let invalid = load_locales_data(&config);
+
+    fs::remove_file(locales.join("en.json")).unwrap();
+    fs::write(locales.join("en.ini"), "hello=Hello").unwrap();
+    let mut ini_config = config.clone();
+    ini_config.locale_extensions.push("ini".to_string());
+    let unsupported = load_locales_data(&ini_config);
+
+    assert_eq!(
+        missing,
+        Err(TranslationError::SourceLocaleNotFound("en".to_string()))
+    );
+    assert_eq!(empty.unwrap().get("en"), Some(&BTreeMap::new()));
+    assert_eq!(
+        unsupported,
+        Err(TranslationError::UnsupportedFormat("ini".to_string()))
+    );
+    match invalid {
+        Err(TranslationError::SourceParse { file, error }) => {
+            assert!(file.ends_with("en.json"));
+            assert!(error.contains("line 3"), "{error}");
+        }
+        other => panic!("Expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_isolate_failures() {
+    use crate::error::ProviderError;
+    use std::fs;
+
+    let dictionary_dir = TempDir::new("isolate_failures_test");
+    let dictionary = &dictionary_dir.join("isolate_failures_test.json");
+    fs::write(
+        dictionary,
+        r#"{"fr": {"Hi __PH0__": "Salut", "Save": "Enregistrer"}}"#,
+    )
+    .unwrap();
+    let config = Config::new()
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .dictionary_file(dictionary)
+        .protect_placeholders(true)
+        .strict_placeholders(true)
+        .build();
+
+    let values = ["Hi %{name}", "Save"];
+    let mut usage = ProviderUsage::default();
+    let batch = translate_data(&config, &values, "fr", &mut usage);
+    let results = isolate_failures(&config, &values, &[], "fr", &batch, None, &mut usage);
+    let mut values = vec!["Save"; 6];
+    values[4] = "Hi %{name}";
+    let bisected = isolate_failures(&config, &values, &[], "fr", &batch, None, &mut usage);
+    let all_failed = isolate_failures(
+        &config,
+        &["Hi %{name}", "Hi %{name}"],
+        &[],
+        "fr",
+        &batch,
+        None,
+        &mut usage,
+    );
+    //an auth or quota error is not specific to the values
+    let quota = Err(TranslationError::Provider(ProviderError::raw(
+        456,
+        "Quota exceeded",
+    )));
+    let account = isolate_failures(&config, &values, &[], "fr", &quota, None, &mut usage);
+
+    assert!(batch.is_err());
+    let results = results.unwrap();
+    assert!(results[0].is_err());
+    assert_eq!(results[1], Ok("Enregistrer".to_string()));
+    let bisected = bisected.unwrap();
+    assert!(bisected[4].is_err());
+    assert_eq!(bisected.iter().filter(|result| result.is_ok()).count(), 5);
+    assert_eq!(all_failed, None);
+    assert_eq!(account, None);
+
+    //the values sent again are charged against the budget
+    let google = Config::new().build();
+    let mut usage = ProviderUsage::default();
+    let over_budget = isolate_failures(&google, &values, &[], "fr", &batch, Some(3), &mut usage);
+    assert_eq!(over_budget, None);
+    assert_eq!(usage.retried_characters, 0);
+}
+
+#[test]
+fn test_locked_keys_not_sent() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_locked_not_sent");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"legal": "Terms", "hello": "Hello"}"#,
+    )
+    .unwrap();
+    fs::write(locales.join("fr.json"), r#"{"legal": "Conditions"}"#).unwrap();
+    let dictionary = locales.join("dictionary.txt");
+    fs::write(&dictionary, r#"{"fr": {"Hello": "Bonjour"}}"#).unwrap();
+
+    let config = |provider| {
+        Config::new()
+            .locales_directory(locales)
+            .add_target_lang("fr")
+            .add_target_lang("xx")
+            .translation_provider(provider)
+            .dictionary_file(&dictionary)
+            .on_unsupported(UnsupportedLanguage::Skip)
+            .lock_key("legal")
+            .build()
+    };
+    let estimate = TranslationAPI::estimate(config(TranslationProvider::GOOGLE));
+    let report = TranslationAPI::translate_with_report(config(TranslationProvider::DICTIONARY));
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    //only `Hello` is priced, `xx` is not supported by Google
+    let estimate = estimate.unwrap();
+    assert_eq!(estimate.characters, 5);
+    assert_eq!(estimate.locales.len(), 1);
+
+    let report = report.unwrap();
+    assert_eq!(report.locales[0].translated_keys, ["hello"]);
+    assert!(fr.contains("Conditions") && fr.contains("Bonjour"));
+}
+
+#[test]
+fn test_prune_cache() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_prune_cache");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .build();
+    let mut autogen = Autogen::load(&config);
+    autogen.insert("en", "fr", "Hello", "Bonjour");
+    autogen.insert("en", "fr", "Goodbye", "Au revoir");
+    autogen.insert("en", "de", "Goodbye", "Tschüss");
+    autogen.update_cache().unwrap();
+
+    let removed = TranslationAPI::prune_cache(config.clone());
+    let pruned = Autogen::load(&config);
+
+    assert_eq!(removed.unwrap(), 1);
+    assert_eq!(pruned.get("en", "fr", "Hello"), Some("Bonjour"));
+    assert_eq!(pruned.get("en", "fr", "Goodbye"), None);
+    assert_eq!(pruned.get("en", "de", "Goodbye"), Some("Tschüss"));
+}
+
+#[test]
+fn test_translate_list_of_objects() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_list_of_objects_translate");
+    fs::write(
+        locales.join("en.yml"),
+        "features:\n- title: Fast\n  desc: Quick\n- title: Cached\n  desc: Cheap\n",
+    )
+    .unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::PSEUDO)
+        .build();
+    let source = load_locales_data(&config).map(|data| data["en"].clone());
+    let report = TranslationAPI::translate_with_report(config);
+    let output = fs::read_to_string(locales.join("fr.yml"));
+
+    assert_eq!(
+        source.unwrap().get("features.1.desc").map(String::as_str),
+        Some("Cheap")
+    );
+    report.unwrap();
+    let output = serde_yaml::from_str::<serde_json::Value>(&output.unwrap()).unwrap();
+    let features = output["features"].as_array().unwrap();
+    assert_eq!(features.len(), 2);
+    assert!(
+        features
+            .iter()
+            .all(|f| f["title"].is_string() && f["desc"].is_string())
+    );
+}
+
+#[test]
+fn test_report_changes() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_report_changes");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"hello": "Hello", "cat": "Cat"}"#,
+    )
+    .unwrap();
+    fs::write(
+        locales.join("fr.json"),
+        r#"{"hello": "Hello", "cat": "Old", "dog": "Chien"}"#,
+    )
+    .unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_target_lang("de")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .use_cache(false)
+        .report_changes(true)
+        .build();
+    let report = TranslationAPI::translate_with_report(config);
+
+    let report = report.unwrap();
+    let changes = |locale: &str| {
+        report
+            .locales
+            .iter()
+            .find(|entry| entry.locale == locale)
+            .and_then(|entry| entry.changes.clone())
+            .unwrap()
+    };
+    assert_eq!(changes("fr").removed_keys, vec!["dog".to_string()]);
+    assert!(changes("fr").changed_keys.contains(&"cat".to_string()));
+    assert_eq!(
+        changes("de").added_keys,
+        vec!["cat".to_string(), "hello".to_string()]
+    );
+}
+
+#[test]
+fn test_validate_schema() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_validate_schema");
+    //outside the locales directory, `load_locales` would read it as a locale
+    let schema_dir = TempDir::new("validate_schema_test");
+    let schema = &schema_dir.join("validate_schema_test.json");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"cart": {"items": "%{count} items"}}"#,
+    )
+    .unwrap();
+    //the French translation drops the placeholder
+    let dictionary = locales.join("dictionary.txt");
+    fs::write(
+        &dictionary,
+        r#"{"fr": {"%{count} items": "articles"}, "de": {"%{count} items": "%{count} Artikel"}}"#,
+    )
+    .unwrap();
+    fs::write(
+        schema,
+        r#"{
+            "type": "object",
+            "properties": {"cart": {"properties": {"items": {"pattern": "%\\{count\\}"}}}}
+        }"#,
+    )
+    .unwrap();
+
+    let config = |policy| {
+        Config::new()
+            .locales_directory(locales)
+            .add_target_lang("fr")
+            .add_target_lang("de")
+            .translation_provider(TranslationProvider::DICTIONARY)
+            .dictionary_file(&dictionary)
+            .use_cache(false)
+            .validate_schema(schema)
+            .on_schema_violation(policy)
+            .build()
+    };
+    let failed = TranslationAPI::translate_with_report(config(SchemaViolation::Fail));
+    let failed_written = (
+        locales.join("fr.json").exists(),
+        locales.join("de.json").exists(),
+    );
+    let loaded = rust_i18n_support::load_locales(&locales.to_string_lossy(), |_| false);
+    let warned = TranslationAPI::translate_with_report(config(SchemaViolation::Warn));
+    let warned_written = locales.join("fr.json").exists();
+
+    let Err(TranslationError::SchemaViolation(violations)) = failed else {
+        panic!("expected a schema violation, got {failed:?}");
+    };
+    assert_eq!(violations.len(), 1);
+    assert!(violations["fr"][0].contains("`cart.items`"));
+    assert_eq!(failed_written, (false, true));
+    assert!(
+        loaded
+            .keys()
+            .all(|locale| ["en", "de"].contains(&locale.as_str()))
+    );
+
+    let report = warned.unwrap();
+    assert_eq!(report.locales[0].skipped, None);
+    assert_eq!(report.locales[0].schema_errors, violations["fr"]);
+    assert!(warned_written);
+}
+
+#[test]
+fn test_combine_output() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_combine_output");
+    fs::create_dir_all(locales.join("en")).unwrap();
+    fs::write(
+        locales.join("en/common.yml"),
+        "hello: Hello\nmenu:\n  cat: Cat\n",
+    )
+    .unwrap();
+    fs::write(locales.join("en/errors.yml"), "not_found: Not found\n").unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .use_cache(false)
+        .combine_output(true)
+        .build();
+    let combined = TranslationAPI::translate(config.clone());
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+    //the same namespace in two formats
+    fs::write(locales.join("en/common.json"), r#"{"hello": "Hi"}"#).unwrap();
+    let collision = TranslationAPI::translate(config);
+
+    combined.unwrap();
+    let fr: serde_json::Value = serde_json::from_str(&fr).unwrap();
+    assert!(fr["common"]["hello"].is_string());
+    assert!(fr["common"]["menu"]["cat"].is_string());
+    assert!(fr["errors"]["not_found"].is_string());
+    assert_eq!(
+        collision,
+        Err(TranslationError::KeyCollision("common.hello".to_string()))
+    );
+}
+
+#[test]
+fn test_estimate() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_estimate");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"a": "Hello", "b": "Hello", "c": "World"}"#,
+    )
+    .unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_target_lang("de")
+        .translation_provider(TranslationProvider::GOOGLE)
+        .use_cache(false);
+    let unpriced = TranslationAPI::estimate(builder.build());
+    let priced = TranslationAPI::estimate(builder.price_per_million(20.0).build());
+
+    let unpriced = unpriced.unwrap();
+    assert_eq!(unpriced.characters, 20);
+    assert_eq!(unpriced.locales[0].keys, 3);
+    assert_eq!(unpriced.locales[0].characters, 10);
+    assert_eq!(unpriced.cost, None);
+    assert!(!serde_json::to_string(&unpriced).unwrap().contains("cost"));
+
+    let priced = priced.unwrap();
+    assert_eq!(priced.locales[1].cost, Some(0.0002));
+    assert_eq!(priced.cost, Some(0.0004));
+}
+
+#[test]
+fn test_translate_source_with_bom() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_source_bom");
+    fs::write(
+        locales.join("en.json"),
+        "\u{feff}{\"_version\": 1, \"hello\": \"Hello\", \"cat\": \"Cat\"}",
+    )
+    .unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .use_cache(false)
+        .build();
+    let source = load_locales_data(&config).map(|data| data["en"].clone());
+    let translated = TranslationAPI::translate(config);
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    let source = source.unwrap();
+    assert_eq!(source.keys().collect::<Vec<_>>(), vec!["cat", "hello"]);
+    translated.unwrap();
+    let fr: serde_json::Value = serde_json::from_str(&fr).unwrap();
+    assert!(fr["hello"].is_string());
+}
+
+#[test]
+fn test_force() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_force");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .use_cache(false);
+    let first = TranslationAPI::translate_with_report(builder.build());
+    let unchanged = TranslationAPI::translate_with_report(builder.build());
+    let forced = TranslationAPI::translate_with_report(builder.force(true).build());
+
+    assert_eq!(first.unwrap().locales.len(), 1);
+    assert!(unchanged.unwrap().locales.is_empty());
+    assert_eq!(forced.unwrap().locales.len(), 1);
+}
+
+#[test]
+fn test_translate_context_cache_key() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_context_cache_key");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"menu": {"open": "Open"}, "door": "Open"}"#,
+    )
+    .unwrap();
+    let descriptions_dir = TempDir::new("context_cache_key_descriptions");
+    let descriptions = &descriptions_dir.join("context_cache_key_descriptions.yml");
+    fs::write(descriptions, "menu:\n  open: Menu item opening a file\n").unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::PSEUDO)
+        .context_file(descriptions)
+        .build();
+    let translated = TranslationAPI::translate_with_report(config.clone());
+    let autogen = Autogen::load(&config);
+
+    assert!(translated.is_ok());
+    //the same value in another context is cached on its own
+    let cached: Vec<&String> = autogen
+        .translations("en", "fr")
+        .into_iter()
+        .flat_map(|translations| translations.keys())
+        .collect();
+    assert_eq!(cached, ["Menu item opening a file\u{4}Open", "Open"]);
+}
+
+#[test]
+fn test_settings_change() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_settings_change");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+    let dictionary = locales.join("dictionary.txt");
+    fs::write(&dictionary, r#"{"fr": {"Hello": "Salut"}}"#).unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .dictionary_file(&dictionary);
+    let first = TranslationAPI::translate_with_report(builder.build());
+    let unchanged = TranslationAPI::translate_with_report(builder.build());
+    //the cached translations of the old dictionary are not reused
+    fs::write(&dictionary, r#"{"fr": {"Hello": "Coucou"}}"#).unwrap();
+    let edited = TranslationAPI::translate_with_report(builder.build());
+    let edited_file = fs::read_to_string(locales.join("fr.json")).unwrap();
+    let switched = TranslationAPI::translate_with_report(
+        builder
+            .translation_provider(TranslationProvider::PSEUDO)
+            .build(),
+    );
+    let switched_file = fs::read_to_string(locales.join("fr.json")).unwrap();
+
+    assert_eq!(first.unwrap().locales.len(), 1);
+    assert!(unchanged.unwrap().locales.is_empty());
+    assert_eq!(edited.unwrap().locales.len(), 1);
+    assert!(edited_file.contains("Coucou"), "{edited_file}");
+    assert_eq!(switched.unwrap().locales.len(), 1);
+    assert!(switched_file.contains("Ḥéļļö"), "{switched_file}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_post_format_command() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_post_format");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .use_cache(false)
+        .force(true);
+    let formatted = TranslationAPI::translate(
+        builder
+            .post_format_command(vec![
+                "sh",
+                "-c",
+                "printf '{\"hello\": \"formatted\"}' > \"$0\"",
+            ])
+            .build(),
+    );
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+    let failed = TranslationAPI::translate(
+        builder
+            .post_format_command(vec!["sh", "-c", "echo bad input >&2; exit 3", "{file}"])
+            .build(),
+    );
+
+    formatted.unwrap();
+    assert_eq!(fr, r#"{"hello": "formatted"}"#);
+    let Err(TranslationError::FormatCommand(error)) = failed else {
+        panic!("expected the format command to fail, got {failed:?}");
+    };
+    assert!(error.contains("bad input"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_target_file_permissions() {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let locales = &TempDir::new("locales_file_permissions");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .use_cache(false)
+        .target_file_permissions(0o640)
+        .read_only_output(true)
+        .build();
+    let mode = || {
+        fs::metadata(locales.join("fr.json"))
+            .map(|m| m.permissions().mode() & 0o777)
+            .ok()
+    };
+    TranslationAPI::translate(config.clone()).unwrap();
+    let first = mode();
+    //the read-only file is updated by the next run
+    fs::write(locales.join("en.json"), r#"{"hello": "Save"}"#).unwrap();
+    let rerun = TranslationAPI::translate(config);
+    let second = mode();
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    rerun.unwrap();
+    assert_eq!(first, Some(0o440));
+    assert_eq!(second, Some(0o440));
+    assert!(fr.contains("Enregistrer"));
+}
+
+#[test]
+fn test_translation_memory() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_translation_memory");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"hello": "Hello", "cat": "Cat"}"#,
+    )
+    .unwrap();
+    let mut memory = tmx::Memory::new();
+    memory
+        .entry("fr-FR".to_string())
+        .or_default()
+        .insert("Hello".to_string(), "Salut".to_string());
+    fs::write(locales.join("memory.tmx"), tmx::to_tmx("en", &memory)).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .in_memory_cache(true)
+        .translation_memory(locales.join("memory.tmx"))
+        .build();
+    let report = TranslationAPI::translate_with_report(config);
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    let report = report.unwrap();
+    assert_eq!(report.locales[0].cached_keys, vec!["hello".to_string()]);
+    assert_eq!(report.locales[0].translated_keys, vec!["cat".to_string()]);
+    let fr: serde_json::Value = serde_json::from_str(&fr).unwrap();
+    assert_eq!(fr["hello"], "Salut");
+}
+
+#[test]
+fn test_translate_locales_in_sequence() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_in_sequence");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .use_cache(true);
+    for locale in ["de", "fr"] {
+        builder.target_locales = vec![];
+        TranslationAPI::translate(builder.add_target_lang(locale).build()).unwrap();
+    }
+    let config = builder.build();
+    let autogen = Autogen::load(&config);
+    let de_kept = locales.join("de.json").exists();
+
+    TranslationAPI::translate(builder.prune_untracked(true).force(true).build()).unwrap();
+    let pruned = Autogen::load(&config);
+    let de_pruned = !locales.join("de.json").exists();
+
+    assert!(de_kept);
+    assert_eq!(autogen.get("en", "de", "Hello"), Some("Hallo"));
+    assert_eq!(autogen.get("en", "fr", "Hello"), Some("Bonjour"));
+    assert!(de_pruned);
+    assert_eq!(pruned.get("en", "de", "Hello"), None);
+    assert_eq!(pruned.get("en", "fr", "Hello"), Some("Bonjour"));
+}
+
+#[test]
+fn test_only_path_checksum() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_only_path_checksum");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"menu": {"file": "File"}, "title": "Title"}"#,
+    )
+    .unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::PSEUDO)
+        .evict_stale_cache(true);
+    TranslationAPI::translate(builder.build()).unwrap();
+    let config = builder.build();
+    let full = Autogen::load(&config);
+
+    //both keys edited, only the menu is translated
+    fs::write(
+        locales.join("en.json"),
+        r#"{"menu": {"file": "Files"}, "title": "Heading"}"#,
+    )
+    .unwrap();
+    let mut only_path = builder.clone();
+    TranslationAPI::translate(only_path.only_path("menu").build()).unwrap();
+    let partial = Autogen::load(&config);
+    let next = TranslationAPI::translate_with_report(builder.build());
+
+    assert_eq!(partial.checksum_of("en"), full.checksum_of("en"));
+    let (full, partial) = (
+        full.value_checksums_of("en").unwrap(),
+        partial.value_checksums_of("en").unwrap(),
+    );
+    assert_ne!(partial["menu.file"], full["menu.file"]);
+    assert_eq!(partial["title"], full["title"]);
+    //the edit outside the path is still to do
+    assert_eq!(next.unwrap().locales[0].translated_keys, ["title"]);
+}
+
+#[test]
+fn test_evict_stale_cache() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_evict_stale");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"hello": "Hello", "save": "Save", "gone": "Goodbye", "wave": "Hi"}"#,
+    )
+    .unwrap();
+    let dictionary = locales.join("dictionary.txt");
+    fs::write(
+        &dictionary,
+        r#"{"fr": {"Hello": "Bonjour", "Hi": "Salut", "Hey": "Salut", "Save": "Enregistrer", "Goodbye": "Au revoir", "Good day": "Bonjour"}}"#,
+    )
+    .unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .dictionary_file(&dictionary)
+        .use_cache(true)
+        .evict_stale_cache(true)
+        .build();
+    TranslationAPI::translate(config.clone()).unwrap();
+    //`hello` is edited in place, `gone` is removed, `wave` is edited with the same translation
+    fs::write(
+        locales.join("en.json"),
+        r#"{"hello": "Good day", "save": "Save", "wave": "Hey"}"#,
+    )
+    .unwrap();
+    TranslationAPI::translate(config.clone()).unwrap();
+    let autogen = Autogen::load(&config);
+
+    assert_eq!(autogen.get("en", "fr", "Hello"), None);
+    assert_eq!(autogen.get("en", "fr", "Good day"), Some("Bonjour"));
+    assert_eq!(autogen.get("en", "fr", "Hi"), None);
+    assert_eq!(autogen.get("en", "fr", "Hey"), Some("Salut"));
+    assert_eq!(autogen.get("en", "fr", "Save"), Some("Enregistrer"));
+    assert_eq!(autogen.get("en", "fr", "Goodbye"), Some("Au revoir"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_zh_convert() {
+    use crate::config::ZhScript;
+    use std::fs;
+
+    let locales = &TempDir::new("locales_zh_convert");
+    fs::write(locales.join("en.json"), r#"{"learn": "Learn"}"#).unwrap();
+    let dictionary = locales.join("dictionary.txt");
+    fs::write(&dictionary, r#"{"zh-CN": {"Learn": "学习"}}"#).unwrap();
+
+    let report = TranslationAPI::translate_with_report(
+        Config::new()
+            .locales_directory(locales)
+            .add_target_lang("zh-TW")
+            .add_target_lang("zh-CN")
+            .translation_provider(TranslationProvider::DICTIONARY)
+            .dictionary_file(&dictionary)
+            .use_cache(false)
+            .zh_convert(
+                ZhScript::Traditional,
+                vec!["sh", "-c", "sed -e s/学/學/g -e s/习/習/g"],
+            )
+            .build(),
+    );
+    let zh_tw = fs::read_to_string(locales.join("zh-TW.json")).unwrap_or_default();
+
+    let report = report.unwrap();
+    let converted: Vec<_> = report
+        .locales
+        .iter()
+        .map(|entry| (entry.locale.as_str(), entry.converted_from.as_deref()))
+        .collect();
+    assert_eq!(converted, vec![("zh-CN", None), ("zh-TW", Some("zh-CN"))]);
+    assert!(zh_tw.contains("學習"));
+}
+
+#[test]
+fn test_batch_size() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_batch_size");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"a": "Hello", "b": "Goodbye", "c": "Welcome", "d": "Save", "e": "Hello"}"#,
+    )
+    .unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY);
+    let config = builder.batch_size(2).build();
+    let report = TranslationAPI::translate_with_report(config.clone());
+    let batched = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+    let autogen = Autogen::load(&config);
+
+    //without the cache the batches are sent as well
+    let uncached =
+        TranslationAPI::translate_with_report(builder.use_cache(false).force(true).build());
+    let batched_uncached = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+    let mut unbatched = builder.build();
+    unbatched.batch_size = None;
+    TranslationAPI::translate(unbatched).unwrap();
+    let unbatched = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    let report = report.unwrap();
+    assert_eq!(report.locales[0].translated_keys.len(), 5);
+    assert_eq!(uncached.unwrap().locales[0].translated_keys.len(), 5);
+    assert_eq!(batched, unbatched);
+    assert_eq!(batched_uncached, unbatched);
+    assert_eq!(autogen.get("en", "fr", "Welcome"), Some("Bienvenue"));
+    assert!(autogen.checksum_of("en").is_some());
+}
+
+#[test]
+fn test_translate_groups() {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    let (endpoint, _) =
+        mock_deeplx(&[("Hello", "Bonjour"), ("Ohayo", "Annyeong"), ("Hola", "Olá")]);
+    let locales = &TempDir::new("locales_translate_groups");
+    fs::write(locales.join("en.json"), r#"{"a": "Hello"}"#).unwrap();
+    fs::write(locales.join("ja.json"), r#"{"a": "Ohayo"}"#).unwrap();
+    fs::write(locales.join("es.json"), r#"{"a": "Hola"}"#).unwrap();
+
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let hook_finished = Arc::clone(&finished);
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_group("ja", vec!["ko"])
+        .add_group("es", vec!["pt"])
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .after_locale(move |locale, _| hook_finished.lock().unwrap().push(locale.to_string()));
+    let report = TranslationAPI::translate_with_report(builder.build());
+    let ko = fs::read_to_string(locales.join("ko.json")).unwrap_or_default();
+    //the map is in `en`, only the groups of `en` have something to translate
+    let mut map_config = builder.build();
+    map_config.groups.push(LocaleGroup {
+        source_locale: "en".to_string(),
+        target_locales: vec!["it".to_string()],
+    });
+    let from_map = TranslationAPI::translate_from_map(
+        map_config,
+        BTreeMap::from([("a".to_string(), "Hello".to_string())]),
+    );
+
+    //the groups share one budget, 5 characters for `en` and 5 for `ja` are over it
+    for file in ["fr.json", "ko.json", "pt.json", ".autogen.cache"] {
+        fs::remove_file(locales.join(file)).unwrap();
+    }
+    let over_budget = TranslationAPI::translate(builder.max_chars(8).build());
+    let over_budget_ko = locales.join("ko.json").exists();
+
+    //a group that fails does not stop the next one
+    fs::remove_file(locales.join("ja.json")).unwrap();
+    finished.lock().unwrap().clear();
+    let failed = TranslationAPI::translate(builder.max_chars(100).force(true).build());
+    let after_failure = finished.lock().unwrap().clone();
+
+    let locales: Vec<String> = report
+        .unwrap()
+        .locales
+        .iter()
+        .map(|entry| entry.locale.clone())
+        .collect();
+    assert_eq!(locales, ["fr", "ko", "pt"]);
+    assert!(ko.contains("Annyeong"));
+    let mut from_map: Vec<String> = from_map.unwrap().into_keys().collect();
+    from_map.sort();
+    assert_eq!(from_map, ["fr", "it"]);
+
+    assert_eq!(
+        over_budget,
+        Err(TranslationError::BudgetExceeded { sent: 5, budget: 8 })
+    );
+    assert!(!over_budget_ko);
+
+    assert_eq!(
+        failed,
+        Err(TranslationError::SourceLocaleNotFound("ja".to_string()))
+    );
+    assert_eq!(after_failure, ["fr", "pt"]);
+}
+
+#[test]
+fn test_max_chars() {
+    use std::fs;
+
+    let (endpoint, served) = mock_deeplx(&[("Hello", "Bonjour"), ("Goodbye", "Au revoir")]);
+    let locales = &TempDir::new("locales_max_chars");
+    fs::write(locales.join("en.json"), r#"{"a": "Hello"}"#).unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_target_lang("de")
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint);
+    //5 characters per locale, the second one is over the budget
+    let over_budget = TranslationAPI::translate(builder.max_chars(8).build());
+    let fr = locales.join("fr.json").exists();
+    let de = locales.join("de.json").exists();
+    TranslationAPI::translate(builder.max_chars(10).build()).unwrap();
+
+    //the keys changed since the commit count as well
+    git_commit_all(locales);
+    fs::write(locales.join("en.json"), r#"{"a": "Hello", "b": "Goodbye"}"#).unwrap();
+    let sent = served.load(std::sync::atomic::Ordering::SeqCst);
+    //without the cache a full translation would be over the budget from the start
+    let since_over_budget =
+        TranslationAPI::translate_since(builder.use_cache(false).max_chars(10).build(), "HEAD");
+    let fr_since = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+    let de_since = fs::read_to_string(locales.join("de.json")).unwrap_or_default();
+    let sent_since = served.load(std::sync::atomic::Ordering::SeqCst) - sent;
+
+    assert_eq!(
+        over_budget,
+        Err(TranslationError::BudgetExceeded { sent: 5, budget: 8 })
+    );
+    assert!(fr);
+    assert!(!de);
+
+    assert_eq!(
+        since_over_budget,
+        Err(TranslationError::BudgetExceeded {
+            sent: 7,
+            budget: 10
+        })
+    );
+    assert!(fr_since.contains("Au revoir"));
+    assert!(!de_since.contains("Goodbye") && !de_since.contains("\"b\""));
+    assert_eq!(sent_since, 1);
+}
+
+#[test]
+fn test_translate_since() {
+    use std::{fs, sync::atomic::Ordering};
+
+    let (endpoint, served) = mock_deeplx(&[
+        ("Hello", "Bonjour"),
+        ("Save", "Enregistrer"),
+        ("Goodbye", "Au revoir"),
+        ("Welcome", "Bienvenue"),
+    ]);
+    let locales = &TempDir::new("locales_since");
+    fs::write(locales.join("en.json"), r#"{"a": "Hello", "b": "Save"}"#).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .build();
+    TranslationAPI::translate(config.clone()).unwrap();
+    //translated by another run, the provider would not give the same
+    let mut autogen = Autogen::load(&config);
+    autogen.insert("en", "fr", "Welcome", "Bienvenue !");
+    autogen.update_cache().unwrap();
+    git_commit_all(locales);
+
+    //`b` edited, `c` added with a value in the cache
+    fs::write(
+        locales.join("en.json"),
+        r#"{"a": "Hello", "b": "Goodbye", "c": "Welcome"}"#,
+    )
+    .unwrap();
+    let sent = served.load(Ordering::SeqCst);
+    TranslationAPI::translate_since(config, "HEAD").unwrap();
+    let sent_since = served.load(Ordering::SeqCst) - sent;
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    let fr: serde_json::Value = serde_json::from_str(&fr).unwrap();
+    assert_eq!(
+        fr,
+        serde_json::json!({"a": "Bonjour", "b": "Au revoir", "c": "Bienvenue !"})
+    );
+    assert_eq!(sent_since, 1);
+}
+
+#[test]
+fn test_translate_since_only_path() {
+    use std::{fs, sync::atomic::Ordering};
+
+    let (endpoint, served) = mock_deeplx(&[
+        ("File", "Fichier"),
+        ("Files", "Fichiers"),
+        ("Edit", "Modifier"),
+        ("Title", "Titre"),
+        ("Heading", "En-tête"),
+    ]);
+    let locales = &TempDir::new("locales_since_only_path");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"menu": {"file": "File", "edit": "Edit"}, "title": "Title"}"#,
+    )
+    .unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint);
+    TranslationAPI::translate(builder.build()).unwrap();
+    git_commit_all(locales);
+
+    //both edited, only the menu is translated
+    fs::write(
+        locales.join("en.json"),
+        r#"{"menu": {"file": "Files", "edit": "Edit"}, "title": "Heading"}"#,
+    )
+    .unwrap();
+    let sent = served.load(Ordering::SeqCst);
+    let report =
+        TranslationAPI::translate_since_with_report(builder.only_path("menu").build(), "HEAD");
+    let sent_since = served.load(Ordering::SeqCst) - sent;
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    let fr: serde_json::Value = serde_json::from_str(&fr).unwrap();
+    assert_eq!(
+        fr,
+        serde_json::json!({"menu": {"file": "Fichiers", "edit": "Modifier"}, "title": "Titre"})
+    );
+    assert_eq!(report.unwrap().locales[0].translated_keys, ["menu.file"]);
+    assert_eq!(sent_since, 1);
+}
+
+#[test]
+fn test_translate_since_provider_error() {
+    use std::fs;
+
+    let dictionary = &[("Hello", "Hallo"), ("Goodbye", "Tschüss")];
+    let (endpoint, _) = mock_deeplx(dictionary);
+    let (failing_endpoint, _) = mock_deeplx_failing(dictionary, &["FR"]);
+    let locales = &TempDir::new("locales_since_error");
+    fs::write(locales.join("en.json"), r#"{"a": "Hello"}"#).unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_target_lang("de")
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint);
+    TranslationAPI::translate(builder.build()).unwrap();
+    git_commit_all(locales);
+
+    fs::write(locales.join("en.json"), r#"{"a": "Hello", "b": "Goodbye"}"#).unwrap();
+    let config = builder.deeplx_endpoint(failing_endpoint).build();
+    let report = TranslationAPI::translate_since_with_report(config.clone(), "HEAD");
+    let de = fs::read_to_string(locales.join("de.json")).unwrap_or_default();
+    let autogen = Autogen::load(&config);
+    let failed = TranslationAPI::translate_since(config, "HEAD");
+
+    let report = report.unwrap();
+    assert!(matches!(
+        report.locales[0].skipped,
+        Some(SkipReason::Failed(_))
+    ));
+    assert_eq!(report.locales[1].translated_keys, ["b"]);
+    assert!(de.contains("Tschüss"));
+    assert_eq!(autogen.get("en", "de", "Goodbye"), Some("Tschüss"));
+    assert!(matches!(failed, Err(TranslationError::LocalesFailed(_))));
+}
+
+#[test]
+fn test_translate_from_map_hooks() {
+    use std::sync::{Arc, Mutex};
+
+    let (endpoint, _) = mock_deeplx_failing(&[("Hello", "Hallo")], &["FR"]);
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let (hook_started, hook_finished) = (Arc::clone(&started), Arc::clone(&finished));
+    let config = Config::new()
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .use_cache(false)
+        .before_locale(move |locale| hook_started.lock().unwrap().push(locale.to_string()))
+        .after_locale(move |locale, entry| {
+            hook_finished
+                .lock()
+                .unwrap()
+                .push((locale.to_string(), entry.skipped.is_some()))
+        })
+        .build();
+    let source = BTreeMap::from([("a".to_string(), "Hello".to_string())]);
+    let failed = TranslationAPI::translate_from_map_targets(&config, &source, &["de", "fr"]);
+
+    assert!(matches!(failed, Err(TranslationError::LocalesFailed(_))));
+    assert_eq!(*started.lock().unwrap(), ["de", "fr"]);
+    assert_eq!(
+        *finished.lock().unwrap(),
+        [("de".to_string(), false), ("fr".to_string(), true)]
+    );
+}
+
+#[test]
+fn test_after_locale_on_every_exit() {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    let (endpoint, _) = mock_deeplx(&[("Hello", "Bonjour")]);
+    let locales = &TempDir::new("locales_after_locale_exit");
+    fs::write(locales.join("en.json"), r#"{"a": "Hello"}"#).unwrap();
+
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let (hook_started, hook_finished) = (Arc::clone(&started), Arc::clone(&finished));
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_target_lang("de")
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .before_locale(move |locale| hook_started.lock().unwrap().push(locale.to_string()))
+        .after_locale(move |locale, entry| {
+            hook_finished
+                .lock()
+                .unwrap()
+                .push((locale.to_string(), entry.skipped.clone()))
+        });
+    //5 characters per locale, `de` is over the budget
+    let over_budget = TranslationAPI::translate(builder.max_chars(8).build());
+    let budget_hooks = (
+        started.lock().unwrap().clone(),
+        finished.lock().unwrap().clone(),
+    );
+
+    //`fr` is in the cache, `de` can't reach the provider
+    started.lock().unwrap().clear();
+    finished.lock().unwrap().clear();
+    let failed = TranslationAPI::translate(
+        builder
+            .max_chars(100)
+            .deeplx_endpoint("http://127.0.0.1:1/v2/translate")
+            .force(true)
+            .build(),
+    );
+    let failed_hooks = (
+        started.lock().unwrap().clone(),
+        finished.lock().unwrap().clone(),
+    );
+
+    assert!(matches!(
+        over_budget,
+        Err(TranslationError::BudgetExceeded { .. })
+    ));
+    assert_eq!(budget_hooks.0, ["fr", "de"]);
+    assert_eq!(
+        budget_hooks.1,
+        [
+            ("fr".to_string(), None),
+            ("de".to_string(), Some(SkipReason::BudgetExceeded))
+        ]
+    );
+
+    assert!(failed.is_err());
+    assert_eq!(failed_hooks.0, ["fr", "de"]);
+    assert_eq!(failed_hooks.1[0], ("fr".to_string(), None));
+    assert!(matches!(
+        &failed_hooks.1[1],
+        (locale, Some(SkipReason::Failed(_))) if locale == "de"
+    ));
+}
+
+#[test]
+fn test_provider_error_recorded() {
+    use std::fs;
+
+    let (endpoint, _) = mock_deeplx_failing(&[("Hello", "Hallo")], &["FR"]);
+    let locales = &TempDir::new("locales_provider_error");
+    fs::write(locales.join("en.json"), r#"{"a": "Hello"}"#).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("de")
+        .add_target_lang("fr")
+        .add_target_lang("nl")
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .build();
+    let report = TranslationAPI::translate_with_report(config.clone());
+    let written: Vec<bool> = ["de", "fr", "nl"]
+        .iter()
+        .map(|locale| locales.join(format!("{locale}.json")).exists())
+        .collect();
+    let autogen = Autogen::load(&config);
+    let failed = TranslationAPI::translate(config);
+
+    let report = report.unwrap();
+    let skipped: Vec<_> = report
+        .locales
+        .iter()
+        .map(|entry| (entry.locale.as_str(), entry.skipped.is_some()))
+        .collect();
+    assert_eq!(skipped, [("de", false), ("fr", true), ("nl", false)]);
+    assert!(matches!(
+        report.locales[1].skipped,
+        Some(SkipReason::Failed(_))
+    ));
+    assert_eq!(written, [true, false, true]);
+    assert_eq!(autogen.get("en", "de", "Hello"), Some("Hallo"));
+    assert_eq!(autogen.get("en", "nl", "Hello"), Some("Hallo"));
+    //the locale that failed is translated again, the others come from the cache
+    assert!(autogen.checksum_of("en").is_none());
+    assert!(matches!(
+        failed,
+        Err(TranslationError::LocalesFailed(errors)) if errors.keys().eq(["fr"])
+    ));
+}
+
+#[test]
+fn test_journal_cleared() {
+    use crate::utils::journal::{self, Journal};
+
+    let locales = &TempDir::new("locales_journal_cleared");
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::PSEUDO)
+        .use_cache(false)
+        .resumable(true)
+        .build();
+    //left by a run that was stopped
+    let chunk = journal::chunk_id(&config, "en", None, &["Hello"]);
+    Journal::load(&config).record("fr", &chunk, &["Bonjour".to_string()]);
+
+    let source = BTreeMap::from([("a".to_string(), "Hello".to_string())]);
+    let resumed = TranslationAPI::translate_from_map(config.clone(), source.clone()).unwrap();
+    let removed = !Journal::path_for(&config).exists();
+    let translated = TranslationAPI::translate_from_map(config.clone(), source).unwrap();
+
+    assert_eq!(resumed["fr"]["a"], "Bonjour");
+    assert!(removed);
+    assert_ne!(translated["fr"]["a"], "Bonjour");
+}
+
+#[test]
+fn test_batch_resume() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_batch_resume");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"a": "Hello", "b": "Goodbye", "c": "Hi %{name}", "d": "Bye %{name}"}"#,
+    )
+    .unwrap();
+    //the second batch loses its placeholders
+    let dictionary = locales.join("dictionary.json");
+    fs::write(
+        &dictionary,
+        r#"{"fr": {"Hello": "Bonjour", "Goodbye": "Au revoir", "Hi __PH0__": "Salut", "Bye __PH0__": "Salut"}}"#,
+    )
+    .unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .dictionary_file(&dictionary)
+        .protect_placeholders(true)
+        .strict_placeholders(true)
+        .batch_size(2);
+    let stopped = TranslationAPI::translate_with_report(builder.build());
+    let checkpoint = Autogen::load(&builder.build());
+    let written = locales.join("fr.json").exists();
+    let resumed = TranslationAPI::translate_with_report(builder.strict_placeholders(false).build());
+    let translated = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+    let autogen = Autogen::load(&builder.build());
+
+    assert!(matches!(
+        stopped.unwrap().locales[0].skipped,
+        Some(SkipReason::Failed(_))
+    ));
+    assert_eq!(checkpoint.get("en", "fr", "Hello"), Some("Bonjour"));
+    assert_eq!(checkpoint.get("en", "fr", "Goodbye"), Some("Au revoir"));
+    assert!(checkpoint.checksum_of("en").is_none());
+    assert!(!written);
+
+    let resumed = resumed.unwrap();
+    assert_eq!(resumed.locales[0].cache_hits, 2);
+    assert_eq!(resumed.locales[0].translated_keys, vec!["c", "d"]);
+    assert!(translated.contains("Bonjour"));
+    assert!(autogen.checksum_of("en").is_some());
+}
+
+#[test]
+#[ignore = "slow, run with `cargo test -- --ignored`"]
+fn test_translate_large_source() {
+    use std::{
+        fmt::Write,
+        fs,
+        time::{Duration, Instant},
+    };
+
+    let locales = &TempDir::new("locales_large_source");
+    let mut source = String::from("{");
+    for idx in 0..20_000 {
+        let _ = write!(source, r#""key{idx}": "Hello {idx}","#);
+    }
+    source.push_str(r#""last": "Goodbye"}"#);
+    fs::write(locales.join("en.json"), source).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .build();
+    let cold = Instant::now();
+    let translated = TranslationAPI::translate_with_report(config.clone());
+    let cold = cold.elapsed();
+    let warm = Instant::now();
+    let cached = TranslationAPI::translate_with_report(config.clone().force(true).build());
+    let warm = warm.elapsed();
+
+    assert_eq!(translated.unwrap().locales[0].translated_keys.len(), 20_001);
+    assert_eq!(cached.unwrap().locales[0].cache_hits, 20_001);
+    //the keys are looked up once, a quadratic pass over them takes minutes
+    assert!(cold < Duration::from_secs(10), "translated in {cold:?}");
+    assert!(
+        warm < Duration::from_secs(10),
+        "read from the cache in {warm:?}"
+    );
+}
+
+#[test]
+fn test_filename_case() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_filename_case");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr-ca")
+        .translation_provider(TranslationProvider::DICTIONARY)
+        .filename_case(config::FilenameCase::Bcp47)
+        .prune_untracked(true);
+    let report = TranslationAPI::translate_with_report(builder.build());
+    let verified = verify_locales(&builder.build());
+    let written = fs::read_to_string(locales.join("fr-CA.json")).unwrap_or_default();
+
+    let report = report.unwrap();
+    assert_eq!(report.locales[0].file_name.as_deref(), Some("fr-CA.json"));
+    assert!(written.contains("Bonjour"));
+    assert_eq!(verified, Ok(()));
+}
+
+#[test]
+fn test_auto_route() {
+    use std::fs;
+
+    let locales = &TempDir::new("locales_auto_route");
+    fs::write(locales.join("en.json"), r#"{"hello": "Hello"}"#).unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::GOOGLE)
+        .auto_route(vec![TranslationProvider::DICTIONARY])
+        .build();
+    let estimate = TranslationAPI::estimate(config.clone());
+    let from_map = TranslationAPI::translate_from_map_targets(
+        &config,
+        &BTreeMap::from([("hello".to_string(), "Hello".to_string())]),
+        &["fr"],
+    );
+    let report = TranslationAPI::translate_with_report(config);
+    let written = fs::read_to_string(locales.join("fr.json")).unwrap_or_default();
+
+    let routes = Config::new()
+        .auto_route(vec![
+            TranslationProvider::DEEPL,
+            TranslationProvider::GOOGLE,
+        ])
+        .build();
+    let report = report.unwrap();
+    assert_eq!(
+        report.locales[0].provider,
+        Some(TranslationProvider::DICTIONARY)
+    );
+    assert!(written.contains("Bonjour"));
+    assert_eq!(from_map.unwrap()["fr"]["hello"], "Bonjour");
+    let estimate = estimate.unwrap();
+    assert_eq!(
+        (&estimate.locales[0].provider, estimate.characters),
+        (&TranslationProvider::DICTIONARY, 0)
+    );
+    assert_eq!(routes.routed("de").provider, TranslationProvider::DEEPL);
+    assert_eq!(routes.routed("haw").provider, TranslationProvider::GOOGLE);
+    assert_eq!(routes.routed("tlh").provider, routes.provider);
+}
+
+/// Commit every file of the directory to a new git repository
+#[cfg(test)]
+fn git_commit_all(dir: &Path) {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+    };
+    git(&["init", "-q"]);
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "init"]);
+}
+
+/// A deeplx answering from the dictionary on a local port, with the count of the requests it got
+#[cfg(test)]
+fn mock_deeplx(
+    dictionary: &'static [(&'static str, &'static str)],
+) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    mock_deeplx_failing(dictionary, &[])
+}
+
+/// Same as [`mock_deeplx`], the requests into the `failing` target languages get a 400
+#[cfg(test)]
+fn mock_deeplx_failing(
+    dictionary: &'static [(&'static str, &'static str)],
+    failing: &'static [&'static str],
+) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}/v2/translate", listener.local_addr().unwrap());
+    let served = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&served);
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let mut reader = BufReader::new(&stream);
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                if let Some((name, value)) = line.trim().split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap_or_default();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            let _ = reader.read_exact(&mut body);
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+            if failing.contains(&request["target_lang"].as_str().unwrap_or_default()) {
+                let body = r#"{"message": "Value for 'target_lang' not supported."}"#;
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 400 Bad Request\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                continue;
+            }
+            let translations: Vec<_> = request["text"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|text| {
+                    let text = text.as_str().unwrap_or_default();
+                    let text = dictionary
+                        .iter()
+                        .find(|(source, _)| *source == text)
+                        .map_or(text, |(_, translation)| translation);
+                    serde_json::json!({"detected_source_language": "EN", "text": text})
+                })
+                .collect();
+            let body = serde_json::json!({"translations": translations}).to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+
+    (endpoint, served)
+}
+
+#[test]
+fn test_translate_cache_round_trip() {
+    use std::{
+        fs,
+        sync::{Arc, Mutex, atomic::Ordering},
+    };
+
+    let (endpoint, served) = mock_deeplx(&[
+        ("Hello", "Bonjour"),
+        ("Save", "Enregistrer"),
+        ("Welcome", "Bienvenue"),
+    ]);
+
+    let locales = &TempDir::new("locales_cache_round_trip");
+    fs::write(
+        locales.join("en.yml"),
+        "greeting: Hello\nmenu:\n  save: Save\n  welcome: Welcome\n",
+    )
+    .unwrap();
+
+    //the report entries of `translate`
+    let entries = Arc::new(Mutex::new(Vec::new()));
+    let hook_entries = Arc::clone(&entries);
+    let mut builder = Config::new();
+    builder
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .translation_provider(TranslationProvider::DEEPL)
+        .deeplx_endpoint(endpoint)
+        .use_cache(true)
+        .overwrite(Overwrite::IfChanged)
+        .after_locale(move |_, entry| hook_entries.lock().unwrap().push(entry.clone()));
+    let config = builder.build();
+    let first = TranslationAPI::translate(config.clone());
+    let first_served = served.load(Ordering::SeqCst);
+    let written = fs::read_to_string(locales.join("fr.yml")).unwrap_or_default();
+    let modified = fs::metadata(locales.join("fr.yml")).and_then(|m| m.modified());
+    let autogen = Autogen::load(&config);
+    let cache_file = locales.join(".autogen.cache").exists();
+
+    //nothing changed, the run stops at the checksum
+    let unchanged = TranslationAPI::translate(config.clone());
+    let unchanged_entries = entries.lock().unwrap().len();
+    //a forced run goes through the cache instead
+    let cached = TranslationAPI::translate(builder.force(true).build());
+    let rewritten = fs::read_to_string(locales.join("fr.yml")).unwrap_or_default();
+    let remodified = fs::metadata(locales.join("fr.yml")).and_then(|m| m.modified());
+
+    first.unwrap();
+    unchanged.unwrap();
+    cached.unwrap();
+    let written: serde_json::Value = serde_yaml::from_str(&written).unwrap();
+    assert_eq!(
+        written,
+        serde_json::json!({
+            "greeting": "Bonjour",
+            "menu": {"save": "Enregistrer", "welcome": "Bienvenue"},
+        })
+    );
+    let entries = entries.lock().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(unchanged_entries, 1);
+    assert_eq!(entries[0].translated_keys.len(), 3);
+    assert!(entries[0].requests > 0);
+    assert_eq!(entries[0].requests, first_served);
+    assert!(cache_file);
+    assert_eq!(autogen.get("en", "fr", "Save"), Some("Enregistrer"));
+    assert!(autogen.checksum_of("en").is_some());
+
+    //no provider calls once everything is cached
+    assert_eq!(served.load(Ordering::SeqCst), first_served);
+    let entry = &entries[1];
+    assert_eq!((entry.cache_hits, entry.characters), (3, 0));
+    assert!(entry.translated_keys.is_empty());
+    assert_eq!(entry.requests, 0);
+    assert_eq!(entry.file, Some(FileWrite::Unchanged));
+    assert_eq!(
+        serde_yaml::from_str::<serde_json::Value>(&rewritten).ok(),
+        Some(written)
+    );
+    assert_eq!(remodified.ok(), modified.ok());
 }
@@ -0,0 +1,528 @@
+//!
+//! _Translation report_
+//!
+//! Summary of what a translation run did for each target locale
+//! The report derives `Serialize` so it can be written to a file and picked up by CI
+//!
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::{config::TranslationProvider, error::TranslationError};
+
+/// Why a target locale was not written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum SkipReason {
+    /// The provider does not support the language
+    UnsupportedLanguage,
+    /// The file already exists and `Config::overwrite` is `Never`
+    FileExists,
+    /// The provider returned fewer translations than values sent, see `failed_keys`
+    IncompleteTranslation,
+    /// The source locale has no keys to translate
+    EmptySource,
+    /// The translations could not be written to the file
+    WriteFailed(String),
+    /// The file does not match the schema, see `schema_errors`
+    SchemaViolation,
+    /// The file could not be converted from the other Chinese script, see `Config::zh_convert`
+    ConversionFailed(String),
+    /// The characters of the locale would go over `Config::max_chars`, the run stopped
+    BudgetExceeded,
+    /// The translation failed with the error, the next locales are still translated
+    Failed(String),
+}
+
+/// What happened to the generated file of a locale, see `Config::overwrite`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FileWrite {
+    /// The file was written
+    Written,
+    /// Same content as the existing file, not written (`IfChanged`)
+    Unchanged,
+    /// The file already exists, not written (`Never`)
+    Kept,
+}
+
+/// Keys the run changed in the file of a locale, see `Config::report_changes`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct KeyChanges {
+    /// Keys that were not in the previous file
+    pub added_keys: Vec<String>,
+    /// Keys whose value is different from the previous file
+    pub changed_keys: Vec<String>,
+    /// Keys of the previous file that were not written
+    pub removed_keys: Vec<String>,
+}
+
+impl KeyChanges {
+    /// Diff of the flattened keys of the previous and new file
+    pub fn between(
+        previous: &BTreeMap<String, String>,
+        current: &BTreeMap<String, String>,
+    ) -> Self {
+        let mut changes = KeyChanges::default();
+
+        for (key, value) in current {
+            match previous.get(key) {
+                None => changes.added_keys.push(key.clone()),
+                Some(previous) if previous != value => changes.changed_keys.push(key.clone()),
+                Some(_) => {}
+            }
+        }
+        changes.removed_keys = previous
+            .keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect();
+
+        changes
+    }
+
+    /// The file has the same keys and values as before
+    pub fn is_empty(&self) -> bool {
+        self.added_keys.is_empty() && self.changed_keys.is_empty() && self.removed_keys.is_empty()
+    }
+}
+
+/// Result of a single target locale
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct TranslationReportEntry {
+    /// Target locale
+    pub locale: String,
+    /// Set when the locale was skipped
+    pub skipped: Option<SkipReason>,
+    /// Provider the locale was translated with, see `Config::auto_route`
+    pub provider: Option<TranslationProvider>,
+    /// Keys in the source locale
+    pub keys: usize,
+    /// Keys whose translation was taken from the cache
+    pub cache_hits: usize,
+    /// Values sent more than once, only translated once
+    pub duplicates: usize,
+    /// Billable characters sent to the provider, retries included
+    pub characters: usize,
+    /// Characters billed as reported by the provider, see `Config::track_billing`
+    pub billed_characters: Option<usize>,
+    /// Values the provider had no translation for and were kept as is
+    pub untranslated: usize,
+    /// Http requests sent to the provider, retries excluded
+    pub requests: usize,
+    /// Http requests sent again after a failed attempt, eg: a blocked or rate limited one
+    pub retries: usize,
+    /// Keys that could not be translated, the locale is not written when set
+    pub failed_keys: Vec<String>,
+    /// Keys whose translation was taken from the cache
+    ///
+    /// A key expected here but listed in `translated_keys` usually has a value that differs
+    /// from the cached one by whitespace or normalization
+    pub cached_keys: Vec<String>,
+    /// Keys sent to the provider this run
+    pub translated_keys: Vec<String>,
+    /// Keys the provider could not translate, written as the source value
+    ///
+    /// Recorded in the cache with the reason and not sent again, see `Config::retry_failed`
+    pub untranslatable_keys: Vec<String>,
+    /// DeepL glossary used for the locale
+    pub glossary_id: Option<String>,
+    /// Set when the file was written or kept by the overwrite policy
+    pub file: Option<FileWrite>,
+    /// Name of the file relative to the locales directory, see `Config::filename_case`
+    pub file_name: Option<String>,
+    /// Keys changed in the file, only set with `Config::report_changes`
+    pub changes: Option<KeyChanges>,
+    /// Keys of the file that do not match `Config::validate_schema`
+    pub schema_errors: Vec<String>,
+    /// Locale the file was converted from instead of translated, see `Config::zh_convert`
+    pub converted_from: Option<String>,
+}
+
+/// Characters a run would send for a target locale, see `TranslationAPI::estimate`
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct LocaleEstimate {
+    /// Target locale
+    pub locale: String,
+    /// Provider the locale would be sent to, see `Config::auto_route`
+    pub provider: TranslationProvider,
+    /// Keys in the source locale
+    pub keys: usize,
+    /// Keys that would not be sent, their translation is cached
+    pub cache_hits: usize,
+    /// Billable characters, duplicates are counted once
+    pub characters: usize,
+    /// Approximate cost, only set with `Config::price_per_million`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+/// Characters and approximate cost of a run, see `TranslationAPI::estimate`
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct CostEstimate {
+    /// Provider of the run, a locale may be routed to another one
+    pub provider: Option<TranslationProvider>,
+    /// One entry per target locale
+    pub locales: Vec<LocaleEstimate>,
+    /// Billable characters of all the locales
+    pub characters: usize,
+    /// Approximate cost of all the locales, only set with `Config::price_per_million`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+impl CostEstimate {
+    /// Record the characters of a locale, priced per million characters
+    pub(crate) fn add(
+        &mut self,
+        locale: &str,
+        provider: &TranslationProvider,
+        keys: usize,
+        cache_hits: usize,
+        characters: usize,
+        price_per_million: Option<u64>,
+    ) {
+        //the price is in millionths
+        let cost = |characters: usize| {
+            price_per_million.map(|price| characters as f64 * price as f64 / 1e12)
+        };
+
+        self.locales.push(LocaleEstimate {
+            locale: locale.to_string(),
+            provider: provider.clone(),
+            keys,
+            cache_hits,
+            characters,
+            cost: cost(characters),
+        });
+        self.characters += characters;
+        self.cost = cost(self.characters);
+    }
+}
+
+/// Result of a translation run
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct TranslationReport {
+    /// Provider used for the run
+    pub provider: Option<TranslationProvider>,
+    /// One entry per target locale processed in the run
+    pub locales: Vec<TranslationReportEntry>,
+}
+
+impl TranslationReport {
+    /// Record a translated locale
+    pub(crate) fn translated(&mut self, locale: &str) -> &mut TranslationReportEntry {
+        self.locales.push(TranslationReportEntry {
+            locale: locale.to_string(),
+            ..Default::default()
+        });
+        self.locales.last_mut().expect("entry was just pushed")
+    }
+
+    /// Record a locale whose translation failed
+    pub(crate) fn fail(&mut self, locale: &str, keys: Vec<String>) {
+        self.locales.push(TranslationReportEntry {
+            locale: locale.to_string(),
+            skipped: Some(SkipReason::IncompleteTranslation),
+            failed_keys: keys,
+            ..Default::default()
+        });
+    }
+
+    /// Record a skipped locale
+    pub(crate) fn skip(&mut self, locale: &str, reason: SkipReason) {
+        self.locales.push(TranslationReportEntry {
+            locale: locale.to_string(),
+            skipped: Some(reason),
+            ..Default::default()
+        });
+    }
+
+    /// Total characters billed as reported by the provider
+    pub fn billed_characters(&self) -> Option<usize> {
+        self.locales
+            .iter()
+            .filter_map(|entry| entry.billed_characters)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Http requests sent to the provider during the run, retries excluded
+    pub fn requests(&self) -> usize {
+        self.locales.iter().map(|entry| entry.requests).sum()
+    }
+
+    /// Http requests sent again during the run
+    pub fn retries(&self) -> usize {
+        self.locales.iter().map(|entry| entry.retries).sum()
+    }
+
+    /// Locales that were skipped
+    pub fn skipped(&self) -> impl Iterator<Item = &TranslationReportEntry> {
+        self.locales.iter().filter(|entry| entry.skipped.is_some())
+    }
+
+    /// Locales whose translation failed
+    pub fn failed(&self) -> impl Iterator<Item = &TranslationReportEntry> {
+        self.locales
+            .iter()
+            .filter(|entry| !entry.failed_keys.is_empty())
+    }
+
+    /// Error with the locales that failed, see `SkipReason::Failed`
+    pub(crate) fn locale_errors(&self) -> Result<(), TranslationError> {
+        let errors: BTreeMap<String, String> = self
+            .locales
+            .iter()
+            .filter_map(|entry| match &entry.skipped {
+                Some(SkipReason::Failed(e)) => Some((entry.locale.clone(), e.clone())),
+                _ => None,
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(TranslationError::LocalesFailed(errors))
+        }
+    }
+
+    /// Summary of the run as Markdown, eg: for a pull request comment
+    ///
+    /// One row per locale with the keys added and changed, the characters sent and the status of
+    /// the file, followed by the keys that were not translated. The added and changed keys are
+    /// only known with `Config::report_changes`, `-` otherwise.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::from("### Translation report\n\n");
+        if let Some(provider) = &self.provider {
+            let _ = writeln!(markdown, "Provider: `{provider:?}`\n");
+        }
+        if self.locales.is_empty() {
+            markdown.push_str("No locale was translated.\n");
+            return markdown;
+        }
+
+        markdown
+            .push_str("| Locale | Status | Added | Changed | Cached | Translated | Characters |\n");
+        markdown.push_str("|---|---|---:|---:|---:|---:|---:|\n");
+        for entry in &self.locales {
+            let (added, changed) = match &entry.changes {
+                Some(changes) => (
+                    changes.added_keys.len().to_string(),
+                    changes.changed_keys.len().to_string(),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let _ = writeln!(
+                markdown,
+                "| {} | {} | {added} | {changed} | {} | {} | {} |",
+                entry.locale,
+                table_cell(&status(entry)),
+                entry.cache_hits,
+                entry.translated_keys.len(),
+                entry.characters
+            );
+        }
+
+        let characters: usize = self.locales.iter().map(|entry| entry.characters).sum();
+        let _ = writeln!(
+            markdown,
+            "\n**Total**: {characters} characters, {} requests, {} retries",
+            self.requests(),
+            self.retries()
+        );
+
+        let not_translated: Vec<_> = self
+            .locales
+            .iter()
+            .filter(|entry| {
+                !entry.failed_keys.is_empty()
+                    || !entry.untranslatable_keys.is_empty()
+                    || !entry.schema_errors.is_empty()
+            })
+            .collect();
+        if !not_translated.is_empty() {
+            markdown.push_str("\n#### Keys not translated\n\n");
+        }
+        for entry in not_translated {
+            let mut lists = Vec::new();
+            if !entry.failed_keys.is_empty() {
+                lists.push(format!("failed {}", code_list(&entry.failed_keys)));
+            }
+            if !entry.untranslatable_keys.is_empty() {
+                lists.push(format!(
+                    "untranslatable {}",
+                    code_list(&entry.untranslatable_keys)
+                ));
+            }
+            if !entry.schema_errors.is_empty() {
+                lists.push(format!("schema {}", code_list(&entry.schema_errors)));
+            }
+            let _ = writeln!(markdown, "- `{}`: {}", entry.locale, lists.join("; "));
+        }
+
+        markdown
+    }
+}
+
+/// Why the locale was skipped, or what happened to its file
+fn status(entry: &TranslationReportEntry) -> String {
+    match (&entry.skipped, entry.file) {
+        (Some(SkipReason::WriteFailed(e)), _) => format!("Write failed: {e}"),
+        (Some(SkipReason::ConversionFailed(e)), _) => format!("Conversion failed: {e}"),
+        (Some(SkipReason::Failed(e)), _) => format!("Failed: {e}"),
+        (Some(reason), _) => format!("Skipped: {reason:?}"),
+        (None, Some(file)) => match &entry.converted_from {
+            Some(base) => format!("{file:?}, converted from {base}"),
+            None => format!("{file:?}"),
+        },
+        (None, None) => "-".to_string(),
+    }
+}
+
+/// Text on a single line with the pipes escaped, a line break would end the table row
+fn table_cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+/// Items as inline code separated by commas
+fn code_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("`{item}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[test]
+fn test_report_serialize() {
+    let mut report = TranslationReport {
+        provider: Some(TranslationProvider::DEEPL),
+        ..Default::default()
+    };
+    let entry = report.translated("fr");
+    entry.keys = 3;
+    entry.cache_hits = 1;
+    entry.characters = 8;
+    entry.cached_keys = vec!["hello".to_string()];
+    entry.translated_keys = vec!["cat".to_string(), "dog".to_string()];
+    entry.file = Some(FileWrite::Written);
+    entry.file_name = Some("fr.json".to_string());
+    report.skip("tlh", SkipReason::UnsupportedLanguage);
+
+    assert_eq!(
+        serde_json::to_value(&report).unwrap(),
+        serde_json::json!({
+            "provider": "DEEPL",
+            "locales": [
+                {
+                    "locale": "fr",
+                    "skipped": null,
+                    "provider": null,
+                    "keys": 3,
+                    "cache_hits": 1,
+                    "duplicates": 0,
+                    "characters": 8,
+                    "billed_characters": null,
+                    "untranslated": 0,
+                    "requests": 0,
+                    "retries": 0,
+                    "failed_keys": [],
+                    "cached_keys": ["hello"],
+                    "translated_keys": ["cat", "dog"],
+                    "untranslatable_keys": [],
+                    "glossary_id": null,
+                    "file": "Written",
+                    "file_name": "fr.json",
+                    "changes": null,
+                    "schema_errors": [],
+                    "converted_from": null,
+                },
+                {
+                    "locale": "tlh",
+                    "skipped": "UnsupportedLanguage",
+                    "provider": null,
+                    "keys": 0,
+                    "cache_hits": 0,
+                    "duplicates": 0,
+                    "characters": 0,
+                    "billed_characters": null,
+                    "untranslated": 0,
+                    "requests": 0,
+                    "retries": 0,
+                    "failed_keys": [],
+                    "cached_keys": [],
+                    "translated_keys": [],
+                    "untranslatable_keys": [],
+                    "glossary_id": null,
+                    "file": null,
+                    "file_name": null,
+                    "changes": null,
+                    "schema_errors": [],
+                    "converted_from": null,
+                },
+            ],
+        })
+    );
+}
+
+#[test]
+fn test_to_markdown() {
+    let mut report = TranslationReport {
+        provider: Some(TranslationProvider::DEEPL),
+        ..Default::default()
+    };
+    let entry = report.translated("fr");
+    entry.cache_hits = 1;
+    entry.characters = 8;
+    entry.requests = 1;
+    entry.translated_keys = vec!["cat".to_string(), "dog".to_string()];
+    entry.untranslatable_keys = vec!["emoji".to_string()];
+    entry.file = Some(FileWrite::Written);
+    entry.changes = Some(KeyChanges {
+        added_keys: vec!["dog".to_string()],
+        changed_keys: vec!["cat".to_string()],
+        removed_keys: vec![],
+    });
+    report.fail("de", vec!["cat".to_string()]);
+    report.skip(
+        "tlh",
+        SkipReason::WriteFailed("a|b\nPermission denied".to_string()),
+    );
+
+    assert_eq!(
+        report.to_markdown(),
+        "### Translation report\n\n\
+         Provider: `DEEPL`\n\n\
+         | Locale | Status | Added | Changed | Cached | Translated | Characters |\n\
+         |---|---|---:|---:|---:|---:|---:|\n\
+         | fr | Written | 1 | 1 | 1 | 2 | 8 |\n\
+         | de | Skipped: IncompleteTranslation | - | - | 0 | 0 | 0 |\n\
+         | tlh | Write failed: a\\|b Permission denied | - | - | 0 | 0 | 0 |\n\
+         \n**Total**: 8 characters, 1 requests, 0 retries\n\
+         \n#### Keys not translated\n\n\
+         - `fr`: untranslatable `emoji`\n\
+         - `de`: failed `cat`\n"
+    );
+}
+
+#[test]
+fn test_key_changes() {
+    let previous = BTreeMap::from([
+        ("hello".to_string(), "Bonjour".to_string()),
+        ("cat".to_string(), "Chat".to_string()),
+        ("old".to_string(), "Vieux".to_string()),
+    ]);
+    let current = BTreeMap::from([
+        ("hello".to_string(), "Salut".to_string()),
+        ("cat".to_string(), "Chat".to_string()),
+        ("dog".to_string(), "Chien".to_string()),
+    ]);
+
+    let changes = KeyChanges::between(&previous, &current);
+    assert_eq!(changes.added_keys, vec!["dog".to_string()]);
+    assert_eq!(changes.changed_keys, vec!["hello".to_string()]);
+    assert_eq!(changes.removed_keys, vec!["old".to_string()]);
+    assert!(KeyChanges::between(&current, &current).is_empty());
+}
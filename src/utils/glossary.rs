@@ -0,0 +1,201 @@
+//Client side glossary
+//Glossary terms of the source are swapped for opaque tokens before sending to the provider, the
+//tokens are then replaced by the term of the target locale. Works the same for every provider,
+//providers with server side glossaries can use both.
+
+use std::{collections::BTreeMap, fs, sync::LazyLock};
+
+use regex::Regex;
+
+#[cfg(test)]
+use super::TempDir;
+use crate::{config::Config, utils::parse_locale_content};
+
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"__GL(\d+)__").unwrap());
+
+/// Source term -> target term of the target locale, `fr-CA` falls back to `fr`
+pub fn terms_for<'a>(
+    glossary: &'a BTreeMap<String, BTreeMap<String, String>>,
+    target_lang: &str,
+) -> Option<&'a BTreeMap<String, String>> {
+    let primary_lang = target_lang.split(['-', '_']).next().unwrap_or(target_lang);
+
+    glossary
+        .get(target_lang)
+        .or_else(|| glossary.get(primary_lang))
+        .filter(|terms| !terms.is_empty())
+}
+
+/// Terms of the target locale, its `Config::glossary_file` with the `Config::add_glossary_term`
+/// terms on top, `None` when it has no glossary
+pub fn load_terms(
+    config: &Config,
+    target_lang: &str,
+) -> Result<Option<BTreeMap<String, String>>, String> {
+    let primary_lang = target_lang.split(['-', '_']).next().unwrap_or(target_lang);
+    let file = config
+        .glossary_files
+        .get(target_lang)
+        .or_else(|| config.glossary_files.get(primary_lang));
+
+    let mut terms = match file {
+        Some(path) => {
+            let ext = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("json");
+            fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| parse_locale_content(&content, ext))
+                .map_err(|e| format!("Could not read the glossary {}: {e}", path.display()))?
+        }
+        None => BTreeMap::new(),
+    };
+    if let Some(inline) = terms_for(&config.glossary, target_lang) {
+        terms.extend(inline.clone());
+    }
+    //an empty term would match between every two chars
+    if terms.keys().any(|term| term.trim().is_empty()) {
+        return Err(format!("The glossary of `{target_lang}` has an empty term"));
+    }
+
+    Ok((!terms.is_empty()).then_some(terms))
+}
+
+/// Terms of the glossary matched as whole words, the longest first
+///
+/// A term is not matched next to a letter, digit or `_`, so terms that start or end with
+/// another char work too, eg: `C++` or `.NET`
+pub struct GlossaryMatcher<'a> {
+    targets: Vec<&'a str>,
+    sources: Vec<&'a str>,
+}
+
+impl<'a> GlossaryMatcher<'a> {
+    pub fn new(terms: &'a BTreeMap<String, String>) -> Self {
+        let mut sources: Vec<&str> = terms.keys().map(|k| k.as_str()).collect();
+        sources.sort_by_key(|term| std::cmp::Reverse(term.len()));
+
+        Self {
+            targets: sources.iter().map(|term| terms[*term].as_str()).collect(),
+            sources,
+        }
+    }
+
+    /// Swap the terms of the value for `__GL{index}__` tokens
+    pub fn protect(&self, value: &str) -> String {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let mut protected = String::with_capacity(value.len());
+        let mut previous: Option<char> = None;
+        let mut rest = value;
+
+        'chars: while let Some(c) = rest.chars().next() {
+            if !previous.is_some_and(is_word) {
+                for (idx, term) in self.sources.iter().enumerate() {
+                    if let Some(after) = rest.strip_prefix(term)
+                        && !after.starts_with(is_word)
+                    {
+                        protected.push_str(&format!("__GL{idx}__"));
+                        previous = term.chars().last();
+                        rest = after;
+                        continue 'chars;
+                    }
+                }
+            }
+            protected.push(c);
+            previous = Some(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        protected
+    }
+
+    /// Replace the tokens of the translation by the target terms
+    pub fn apply(&self, translated: &str) -> String {
+        TOKEN
+            .replace_all(translated, |caps: &regex::Captures| {
+                caps[1]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|idx| self.targets.get(idx))
+                    .map(|target| target.to_string())
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+}
+
+#[test]
+fn test_glossary_per_locale() {
+    let glossary = BTreeMap::from([
+        (
+            "de".to_string(),
+            BTreeMap::from([
+                ("Dashboard".to_string(), "Übersicht".to_string()),
+                ("Dashboard widget".to_string(), "Kachel".to_string()),
+            ]),
+        ),
+        (
+            "fr".to_string(),
+            BTreeMap::from([("Dashboard".to_string(), "Tableau de bord".to_string())]),
+        ),
+    ]);
+
+    let matcher = GlossaryMatcher::new(terms_for(&glossary, "de-AT").unwrap());
+    let protected = matcher.protect("Open the Dashboard widget or the Dashboard, not Dashboards");
+    assert_eq!(protected, "Open the __GL0__ or the __GL1__, not Dashboards");
+    assert_eq!(
+        matcher.apply("Öffne __GL0__ oder __GL1__"),
+        "Öffne Kachel oder Übersicht"
+    );
+
+    let matcher = GlossaryMatcher::new(terms_for(&glossary, "fr").unwrap());
+    assert_eq!(
+        matcher.apply(&matcher.protect("Dashboard")),
+        "Tableau de bord"
+    );
+    assert!(terms_for(&glossary, "es").is_none());
+}
+
+#[test]
+fn test_load_terms() {
+    let dir = TempDir::new("glossary_fr_test");
+    let path = &dir.join("glossary_fr_test.json");
+    fs::write(path, r#"{"OK": "D'accord", "Dashboard": "Tableau"}"#).unwrap();
+    let config = Config::new()
+        .glossary_file("fr", path)
+        .add_glossary_term("fr", "Dashboard", "Tableau de bord")
+        .glossary_file("de", "./glossary_missing_test.json")
+        .build();
+
+    let fr_ca = load_terms(&config, "fr-CA");
+    let es = load_terms(&config, "es");
+    let de = load_terms(&config, "de");
+
+    assert_eq!(
+        fr_ca,
+        Ok(Some(BTreeMap::from([
+            ("Dashboard".to_string(), "Tableau de bord".to_string()),
+            ("OK".to_string(), "D'accord".to_string()),
+        ])))
+    );
+    assert_eq!(es, Ok(None));
+    assert!(de.is_err());
+}
+
+#[test]
+fn test_glossary_non_word_terms() {
+    let terms = BTreeMap::from([
+        ("C++".to_string(), "C++".to_string()),
+        (".NET".to_string(), ".NET".to_string()),
+    ]);
+
+    let matcher = GlossaryMatcher::new(&terms);
+    assert_eq!(
+        matcher.protect("C++ and .NET, not ASP.NET or C++x"),
+        "__GL1__ and __GL0__, not ASP.NET or C++x"
+    );
+
+    let config = Config::new().add_glossary_term("fr", " ", "Espace").build();
+    assert!(load_terms(&config, "fr").is_err());
+}
@@ -0,0 +1,287 @@
+//ICU MessageFormat
+//Only the literal parts of a message are sent for translation, the `plural`, `select` and
+//`selectordinal` structure is kept as is.
+//
+//eg: `{count, plural, one {# item} other {# items}}`
+//sends `# item` and `# items` and keeps `{count, plural, one {`, `} other {` and `}}`
+
+/// Part of a tokenized message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcuToken {
+    /// Structure of the message, never translated
+    Raw(String),
+    /// Literal sub-message, simple arguments like `{name}` are kept inline
+    Text(String),
+}
+
+const COMPLEX_ARGS: [&str; 3] = ["plural", "select", "selectordinal"];
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    text: String,
+    tokens: Vec<IcuToken>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn push_raw(&mut self, raw: &str) {
+        if let Some(IcuToken::Raw(last)) = self.tokens.last_mut() {
+            last.push_str(raw);
+        } else {
+            self.tokens.push(IcuToken::Raw(raw.to_string()));
+        }
+    }
+
+    /// Move the pending text into the tokens, whitespace only text is kept raw
+    fn flush_text(&mut self) {
+        let text = std::mem::take(&mut self.text);
+        if text.trim().is_empty() {
+            if !text.is_empty() {
+                self.push_raw(&text);
+            }
+        } else {
+            self.tokens.push(IcuToken::Text(text));
+        }
+    }
+
+    /// Parse a message until the end or the `}` closing a nested message
+    fn message(&mut self, nested: bool) -> Result<(), String> {
+        while let Some(c) = self.peek() {
+            match c {
+                '{' => self.argument()?,
+                '}' if nested => return Ok(()),
+                '}' => return Err(format!("Unmatched `}}` at {}", self.pos)),
+                '\'' => self.quoted(),
+                _ => {
+                    self.text.push(c);
+                    self.bump();
+                }
+            }
+        }
+
+        if nested {
+            Err("Unclosed `{`".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `''` is an apostrophe, `'{...}'` is literal text
+    fn quoted(&mut self) {
+        let start = self.pos;
+        self.bump();
+
+        match self.peek() {
+            Some('\'') => {
+                self.bump();
+            }
+            Some('{' | '}' | '#' | '|') => {
+                while let Some(c) = self.bump() {
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.text.push_str(&self.src[start..self.pos]);
+    }
+
+    fn argument(&mut self) -> Result<(), String> {
+        let start = self.pos;
+        let rest = &self.src[start + 1..];
+
+        let name_end = rest
+            .find([',', '}', '{'])
+            .ok_or_else(|| "Unclosed `{`".to_string())?;
+        let name_end = start + 1 + name_end;
+
+        match &self.src[name_end..name_end + 1] {
+            //simple argument, stays inline with the text
+            "}" => {
+                self.pos = name_end + 1;
+                self.text.push_str(&self.src[start..self.pos]);
+                Ok(())
+            }
+            "{" => Err(format!("Invalid argument at {start}")),
+            _ => {
+                let type_end = self.src[name_end + 1..]
+                    .find([',', '}'])
+                    .map(|end| name_end + 1 + end)
+                    .ok_or_else(|| "Unclosed `{`".to_string())?;
+                let kind = self.src[name_end + 1..type_end].trim();
+
+                if COMPLEX_ARGS.contains(&kind) && &self.src[type_end..type_end + 1] == "," {
+                    self.flush_text();
+                    self.pos = type_end + 1;
+                    self.push_raw(&self.src[start..self.pos]);
+                    self.branches()
+                } else {
+                    //formatted argument like `{n, number}`, stays inline with the text
+                    self.pos = name_end;
+                    let mut depth = 1;
+                    while let Some(c) = self.bump() {
+                        match c {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        if depth == 0 {
+                            self.text.push_str(&self.src[start..self.pos]);
+                            return Ok(());
+                        }
+                    }
+                    Err("Unclosed `{`".to_string())
+                }
+            }
+        }
+    }
+
+    /// Selectors and their sub-messages until the `}` closing the argument
+    fn branches(&mut self) -> Result<(), String> {
+        loop {
+            let start = self.pos;
+            while let Some(c) = self.peek() {
+                if c == '{' || c == '}' {
+                    break;
+                }
+                self.bump();
+            }
+
+            match self.bump() {
+                Some('}') => {
+                    self.push_raw(&self.src[start..self.pos]);
+                    return Ok(());
+                }
+                Some('{') => {
+                    self.push_raw(&self.src[start..self.pos]);
+                    self.message(true)?;
+                    self.flush_text();
+                    self.bump();
+                    self.push_raw("}");
+                }
+                _ => return Err("Unclosed `{`".to_string()),
+            }
+        }
+    }
+}
+
+/// Split a message into its structure and the literal parts to translate
+pub fn tokenize(message: &str) -> Result<Vec<IcuToken>, String> {
+    let mut parser = Parser {
+        src: message,
+        pos: 0,
+        text: String::new(),
+        tokens: Vec::new(),
+    };
+
+    parser.message(false)?;
+    parser.flush_text();
+
+    Ok(parser.tokens)
+}
+
+/// Literal parts of the tokens, trimmed as providers do not keep the surrounding whitespace
+pub fn texts(tokens: &[IcuToken]) -> impl Iterator<Item = &str> {
+    tokens.iter().filter_map(|token| match token {
+        IcuToken::Text(text) => Some(text.trim()),
+        IcuToken::Raw(_) => None,
+    })
+}
+
+/// Rebuild the message with the translations of [`texts`] in order
+pub fn render(tokens: &[IcuToken], translations: &mut impl Iterator<Item = String>) -> String {
+    let mut message = String::new();
+
+    for token in tokens {
+        match token {
+            IcuToken::Raw(raw) => message.push_str(raw),
+            IcuToken::Text(text) => {
+                let trimmed = text.trim_start();
+                let leading = &text[..text.len() - trimmed.len()];
+                let trailing = &trimmed[trimmed.trim_end().len()..];
+
+                message.push_str(leading);
+                message.push_str(
+                    &translations
+                        .next()
+                        .unwrap_or_else(|| text.trim().to_string()),
+                );
+                message.push_str(trailing);
+            }
+        }
+    }
+
+    message
+}
+
+#[test]
+fn test_tokenize_plural() {
+    let tokens = tokenize("You have {count, plural, one {# item} other {# items}} left").unwrap();
+
+    assert_eq!(
+        tokens,
+        vec![
+            IcuToken::Text("You have ".to_string()),
+            IcuToken::Raw("{count, plural, one {".to_string()),
+            IcuToken::Text("# item".to_string()),
+            IcuToken::Raw("} other {".to_string()),
+            IcuToken::Text("# items".to_string()),
+            IcuToken::Raw("}}".to_string()),
+            IcuToken::Text(" left".to_string()),
+        ]
+    );
+    assert_eq!(
+        texts(&tokens).collect::<Vec<_>>(),
+        ["You have", "# item", "# items", "left"]
+    );
+}
+
+#[test]
+fn test_tokenize_nested_select() {
+    let message = "{gender, select, female {{n, plural, one {She has {n, number} cat} other {She has # cats}}} other {'{'Them'}'}}";
+    let tokens = tokenize(message).unwrap();
+
+    assert_eq!(
+        texts(&tokens).collect::<Vec<_>>(),
+        ["She has {n, number} cat", "She has # cats", "'{'Them'}'"]
+    );
+
+    let mut unchanged = texts(&tokens)
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .into_iter();
+    assert_eq!(render(&tokens, &mut unchanged), message);
+}
+
+#[test]
+fn test_render_translations() {
+    let tokens = tokenize("{count, plural, one {# item} other {# items}}").unwrap();
+    let mut translations = vec!["# article".to_string(), "# articles".to_string()].into_iter();
+
+    assert_eq!(
+        render(&tokens, &mut translations),
+        "{count, plural, one {# article} other {# articles}}"
+    );
+}
+
+#[test]
+fn test_tokenize_invalid() {
+    assert!(tokenize("{count, plural, one {# item}").is_err());
+    assert!(tokenize("closing } without opening").is_err());
+    assert_eq!(
+        tokenize("Hello {name}"),
+        Ok(vec![IcuToken::Text("Hello {name}".to_string())])
+    );
+}
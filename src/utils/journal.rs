@@ -0,0 +1,252 @@
+//Run journal, see `Config::resumable`
+//Every chunk translated is appended as a json line, a run that was stopped resumes from it
+//instead of sending the chunks again. The journal is loaded once when a run starts and removed
+//once it completes.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use super::TempDir;
+use crate::{
+    config::Config,
+    utils::logging::{debug, info, warn},
+};
+
+/// Name of the journal in the locales directory
+const JOURNAL_FILE_NAME: &str = ".autogen.journal";
+
+thread_local! {
+    static RUN: RefCell<Option<Arc<Journal>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    locale: String,
+    chunk: String,
+    translations: Vec<String>,
+}
+
+/// Chunks translated by the runs that did not complete
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    done: HashMap<(String, String), Vec<String>>,
+    file: Mutex<Option<File>>,
+}
+
+impl Journal {
+    /// Journal of the locales directory
+    pub fn path_for(config: &Config) -> PathBuf {
+        config.locales_dir.join(JOURNAL_FILE_NAME)
+    }
+
+    /// Read the journal, a line cut short by a crash is ignored
+    pub fn load(config: &Config) -> Self {
+        let path = Self::path_for(config);
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        let done: HashMap<_, _> = content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+            .map(|entry| ((entry.locale, entry.chunk), entry.translations))
+            .collect();
+
+        if !done.is_empty() {
+            info!(chunks = done.len(); "Resuming from the journal {}", path.display());
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .inspect_err(|e| warn!("Could not open the journal {}: {e}", path.display()))
+            .ok();
+        //start after the line cut short
+        let file = match file {
+            Some(mut file) if !content.is_empty() && !content.ends_with('\n') => {
+                file.write_all(b"\n").ok().map(|_| file)
+            }
+            file => file,
+        };
+
+        Journal {
+            path,
+            done,
+            file: Mutex::new(file),
+        }
+    }
+
+    /// Journal loaded by the current run, read now when not called from a run
+    pub fn of_run(config: &Config) -> Arc<Self> {
+        let path = Self::path_for(config);
+        RUN.with(|run| run.borrow().clone())
+            .filter(|journal| journal.path == path)
+            .unwrap_or_else(|| Arc::new(Self::load(config)))
+    }
+
+    /// Translations of a chunk sent by a previous run
+    pub fn get(&self, locale: &str, chunk: &str) -> Option<&Vec<String>> {
+        self.done.get(&(locale.to_string(), chunk.to_string()))
+    }
+
+    /// Append the translations of a chunk
+    pub fn record(&self, locale: &str, chunk: &str, translations: &[String]) {
+        let entry = Entry {
+            locale: locale.to_string(),
+            chunk: chunk.to_string(),
+            translations: translations.to_vec(),
+        };
+        let Ok(mut line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        match file.as_mut().map(|file| file.write_all(line.as_bytes())) {
+            Some(Ok(())) => debug!(locale, chunk; "Chunk recorded in the journal"),
+            Some(Err(e)) => warn!("Could not write the journal: {e}"),
+            None => {}
+        }
+    }
+}
+
+/// Remove the journal once a run completed
+fn remove_file(path: &PathBuf) {
+    if path.exists()
+        && let Err(e) = fs::remove_file(path)
+    {
+        warn!("Could not remove the journal {}: {e}", path.display());
+    }
+}
+
+/// Loads the journal once for a run, see [`Journal::of_run`]
+///
+/// A run started from another one keeps the journal of the outer run.
+#[derive(Debug)]
+pub struct JournalScope {
+    /// Journal of the outer run, set when this scope loaded one
+    previous: Option<Option<Arc<Journal>>>,
+}
+
+impl JournalScope {
+    /// Load the journal of the config when `Config::resumable` is set
+    pub fn enter(config: &Config) -> Self {
+        let path = Journal::path_for(config);
+        let loaded = RUN.with(|run| {
+            run.borrow()
+                .as_ref()
+                .is_some_and(|journal| journal.path == path)
+        });
+        if !config.resumable || loaded {
+            return JournalScope { previous: None };
+        }
+
+        let journal = Arc::new(Journal::load(config));
+        JournalScope {
+            previous: Some(RUN.with(|run| run.replace(Some(journal)))),
+        }
+    }
+
+    /// The run completed, remove the journal this scope loaded
+    pub fn complete(mut self) {
+        if let Some(previous) = self.previous.take()
+            && let Some(journal) = RUN.with(|run| run.replace(previous))
+        {
+            let path = journal.path.clone();
+            //closes the file before removing it
+            drop(journal);
+            remove_file(&path);
+        }
+    }
+}
+
+impl Drop for JournalScope {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            RUN.with(|run| *run.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Identifies a chunk sent for a locale, the hash of the request
+pub fn chunk_id(
+    config: &Config,
+    source_lang: &str,
+    context: Option<&str>,
+    chunk: &[&str],
+) -> String {
+    let request = serde_json::json!([
+        format!("{:?}", config.provider),
+        source_lang,
+        context,
+        chunk
+    ]);
+    sha256::digest(request.to_string())
+}
+
+#[test]
+fn test_journal() {
+    let locales = &TempDir::new("locales_journal");
+    let config = Config::new().locales_directory(locales).build();
+    let chunk = chunk_id(&config, "en", None, &["Hello", "World"]);
+
+    let journal = Journal::load(&config);
+    journal.record("fr", &chunk, &["Bonjour".to_string(), "Monde".to_string()]);
+    drop(journal);
+    //a crash while writing the next line
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(Journal::path_for(&config))
+        .unwrap();
+    file.write_all(b"{\"locale\":\"de\",\"chu").unwrap();
+
+    let resumed = Journal::load(&config);
+    resumed.record("de", &chunk, &["Hallo".to_string(), "Welt".to_string()]);
+    let reloaded = Journal::load(&config);
+    remove_file(&Journal::path_for(&config));
+    let removed = !Journal::path_for(&config).exists();
+
+    assert_eq!(
+        resumed.get("fr", &chunk).cloned(),
+        Some(vec!["Bonjour".to_string(), "Monde".to_string()])
+    );
+    assert_eq!(resumed.get("de", &chunk), None);
+    assert!(reloaded.get("de", &chunk).is_some());
+    assert_ne!(
+        chunk,
+        chunk_id(&config, "en", Some("menu"), &["Hello", "World"])
+    );
+    assert!(removed);
+}
+
+#[test]
+fn test_journal_scope() {
+    let locales = &TempDir::new("locales_journal_scope");
+    let config = Config::new()
+        .locales_directory(locales)
+        .resumable(true)
+        .build();
+
+    let scope = JournalScope::enter(&config);
+    //loaded once for the run and the runs it starts
+    let nested = JournalScope::enter(&config);
+    let shared = Arc::ptr_eq(&Journal::of_run(&config), &Journal::of_run(&config));
+    nested.complete();
+    let kept = Journal::path_for(&config).exists();
+    scope.complete();
+    let removed = !Journal::path_for(&config).exists();
+    let outside = !Arc::ptr_eq(&Journal::of_run(&config), &Journal::of_run(&config));
+
+    assert!(shared);
+    assert!(kept);
+    assert!(removed);
+    assert!(outside);
+}
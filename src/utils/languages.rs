@@ -36,9 +36,18 @@ const LIBRE_TRANSLATE_LANG_CODES: [&str; 49] = [
     "tr", "uk", "ur", "vi",
 ];
 
+/// Languages written right to left
+const RTL_LANG_CODES: [&str; 17] = [
+    "ar", "arc", "ckb", "dv", "fa", "he", "iw", "ks", "prs", "ps", "sd", "syr", "ug", "ur", "yi",
+    "ms-Arab", "pa-Arab",
+];
+
+/// Scripts written right to left
+const RTL_SCRIPTS: [&str; 5] = ["Arab", "Hebr", "Thaa", "Syrc", "Nkoo"];
+
 use thiserror::Error;
 
-use crate::config::TranslationProvider;
+use crate::config::{Config, TranslationProvider, ZhScript};
 
 #[derive(Error, Debug)]
 pub enum LanguageNormalizeError<T: Into<String>> {
@@ -50,16 +59,86 @@ pub fn normalize_lang(
     provider: &TranslationProvider,
     lang_code: &str,
 ) -> Result<String, LanguageNormalizeError<String>> {
+    let canonical = canonical_locale(lang_code);
     match provider {
-        TranslationProvider::GOOGLE => normalize(&lang_code, &GOOGLE_TRANSLATE_LANG_CODES),
+        TranslationProvider::GOOGLE => match google_zh_script(&canonical) {
+            Some(code) => Ok(code.to_string()),
+            None => normalize(&canonical, &GOOGLE_TRANSLATE_LANG_CODES),
+        },
         TranslationProvider::DEEPL => {
-            let lang_code_uppercase = lang_code.to_uppercase();
+            let lang_code_uppercase = canonical.to_uppercase();
             normalize(&lang_code_uppercase, &DEEPL_LANG_CODES)
         }
-        TranslationProvider::LIBRETRANSLATE => normalize(&lang_code, &LIBRE_TRANSLATE_LANG_CODES),
+        TranslationProvider::LIBRETRANSLATE => normalize(&canonical, &LIBRE_TRANSLATE_LANG_CODES),
+        //any language can be in the dictionary, pseudolocales are made up
+        TranslationProvider::DICTIONARY | TranslationProvider::PSEUDO => Ok(lang_code.to_string()),
+    }
+}
+
+/// Google code of a Chinese locale with a script subtag, eg: `zh-Hant-HK` -> `zh-TW`
+///
+/// Google has the regional codes of Chinese only
+fn google_zh_script(canonical: &str) -> Option<&'static str> {
+    let mut subtags = canonical.split('-');
+    match (subtags.next(), subtags.next()) {
+        (Some("zh"), Some("Hant")) => Some("zh-TW"),
+        (Some("zh"), Some("Hans")) => Some("zh-CN"),
+        _ => None,
     }
 }
 
+/// Provider code of the source language
+///
+/// DeepL only takes the base language as source, eg: `EN` for `en-GB`
+pub fn normalize_source_lang(
+    config: &Config,
+    lang_code: &str,
+) -> Result<String, LanguageNormalizeError<String>> {
+    let code = normalize_lang(&config.provider, lang_code)?;
+
+    Ok(match config.provider {
+        TranslationProvider::DEEPL => code.split('-').next().unwrap_or(&code).to_string(),
+        _ => code,
+    })
+}
+
+/// Provider code of the target language
+///
+/// DeepL wants a regional variant for English and Portuguese targets, a bare `EN` or `PT`
+/// is sent as `Config::deepl_default_english` or `Config::deepl_default_portuguese`
+pub fn normalize_target_lang(
+    config: &Config,
+    lang_code: &str,
+) -> Result<String, LanguageNormalizeError<String>> {
+    let code = normalize_lang(&config.provider, lang_code)?;
+
+    Ok(match (&config.provider, code.as_str()) {
+        (TranslationProvider::DEEPL, "EN") => config.deepl_default_english.code().to_string(),
+        (TranslationProvider::DEEPL, "PT") => config.deepl_default_portuguese.code().to_string(),
+        _ => code,
+    })
+}
+
+/// Locale spelled like the provider tables, eg: `zh_cn` -> `zh-CN`, `sr_latn` -> `sr-Latn`
+///
+/// Lowercase language, title case script and uppercase region, separated by `-`
+pub fn canonical_locale(lang_code: &str) -> String {
+    lang_code
+        .split(['-', '_'])
+        .enumerate()
+        .map(|(index, subtag)| match subtag.len() {
+            _ if index == 0 => subtag.to_lowercase(),
+            4 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => {
+                let (first, rest) = subtag.split_at(1);
+                first.to_uppercase() + &rest.to_lowercase()
+            }
+            2 | 3 => subtag.to_uppercase(),
+            _ => subtag.to_lowercase(),
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
 fn normalize(locale: &str, codes: &[&str]) -> Result<String, LanguageNormalizeError<String>> {
     let contains = codes.contains(&locale);
     if contains {
@@ -71,8 +150,21 @@ fn normalize(locale: &str, codes: &[&str]) -> Result<String, LanguageNormalizeEr
 
         let first_source = split_source_lang.first();
 
+        //a base code is written in its default script, eg: `sr` is Cyrillic,
+        //so `sr-Latn` can't fall back to it. Chinese scripts keep the lookup below
+        let other_script = split_source_lang.iter().skip(1).any(|subtag| {
+            subtag.len() == 4
+                && subtag.chars().all(|c| c.is_ascii_alphabetic())
+                && !["hans", "hant"].contains(&subtag.to_lowercase().as_str())
+        });
+        if other_script {
+            return Err(LanguageNormalizeError::Redaction(locale.to_string()));
+        }
+
         if let Some(first) = first_source {
-            let find_code = codes.iter().position(|x| x.contains(first));
+            let find_code = codes
+                .iter()
+                .position(|x| x.split('-').next() == Some(*first));
 
             if let Some(found_code) = find_code {
                 let item = codes[found_code];
@@ -85,3 +177,122 @@ fn normalize(locale: &str, codes: &[&str]) -> Result<String, LanguageNormalizeEr
         }
     }
 }
+
+/// Whether the language is written right to left
+///
+/// Checks the full code, then the script subtag and finally the base language
+/// eg: `pa-Arab` -> rtl, `pa` -> ltr, `he-IL` -> rtl
+pub fn is_rtl(lang_code: &str) -> bool {
+    if RTL_LANG_CODES.contains(&lang_code) {
+        return true;
+    }
+
+    let mut subtags = lang_code.split(['-', '_']);
+    let base = subtags.next().unwrap_or_default().to_lowercase();
+
+    if subtags.any(|subtag| RTL_SCRIPTS.iter().any(|s| s.eq_ignore_ascii_case(subtag))) {
+        return true;
+    }
+
+    RTL_LANG_CODES.contains(&base.as_str())
+}
+
+/// Script of a Chinese locale, `None` for other languages
+///
+/// eg: `zh-Hant` and `zh-TW` -> Traditional, `zh`, `zh-CN` and `zh-Hans-SG` -> Simplified
+pub fn zh_script(lang_code: &str) -> Option<ZhScript> {
+    let canonical = canonical_locale(lang_code);
+    let mut subtags = canonical.split('-');
+    if subtags.next() != Some("zh") {
+        return None;
+    }
+
+    let traditional = subtags.any(|subtag| matches!(subtag, "Hant" | "TW" | "HK" | "MO"))
+        && !canonical.contains("-Hans");
+    Some(if traditional {
+        ZhScript::Traditional
+    } else {
+        ZhScript::Simplified
+    })
+}
+
+#[test]
+fn test_zh_script() {
+    assert_eq!(zh_script("zh"), Some(ZhScript::Simplified));
+    assert_eq!(zh_script("zh_CN"), Some(ZhScript::Simplified));
+    assert_eq!(zh_script("zh-Hans-HK"), Some(ZhScript::Simplified));
+    assert_eq!(zh_script("zh-tw"), Some(ZhScript::Traditional));
+    assert_eq!(zh_script("zh-Hant"), Some(ZhScript::Traditional));
+    assert_eq!(zh_script("ja"), None);
+}
+
+#[test]
+fn test_is_rtl() {
+    assert!(is_rtl("ar"));
+    assert!(is_rtl("he-IL"));
+    assert!(is_rtl("pa-Arab"));
+    assert!(is_rtl("FA"));
+    assert!(!is_rtl("pa"));
+    assert!(!is_rtl("en"));
+    assert!(!is_rtl("zh-TW"));
+}
+
+#[test]
+fn test_normalize_deepl_regional() {
+    use crate::config::{DeepLEnglish, DeepLPortuguese};
+
+    let config = Config::new()
+        .translation_provider(TranslationProvider::DEEPL)
+        .build();
+    let british = Config::new()
+        .translation_provider(TranslationProvider::DEEPL)
+        .deepl_default_english(DeepLEnglish::EnGb)
+        .deepl_default_portuguese(DeepLPortuguese::PtPt)
+        .build();
+
+    let target = |config: &Config, lang: &str| normalize_target_lang(config, lang).unwrap();
+    assert_eq!(target(&config, "en"), "EN-US");
+    assert_eq!(target(&british, "en"), "EN-GB");
+    assert_eq!(target(&british, "en-US"), "EN-US");
+    assert_eq!(target(&config, "pt"), "PT-BR");
+    assert_eq!(target(&british, "pt"), "PT-PT");
+    assert_eq!(target(&config, "fr"), "FR");
+
+    let source = |lang: &str| normalize_source_lang(&config, lang).unwrap();
+    assert_eq!(source("en"), "EN");
+    assert_eq!(source("en-GB"), "EN");
+    assert_eq!(source("pt-BR"), "PT");
+
+    let google = Config::new().build();
+    assert_eq!(normalize_target_lang(&google, "en").unwrap(), "en");
+}
+
+#[test]
+fn test_normalize_canonical_spelling() {
+    let google = |lang: &str| normalize_lang(&TranslationProvider::GOOGLE, lang).unwrap();
+    assert_eq!(google("zh_CN"), "zh-CN");
+    assert_eq!(google("zh-cn"), "zh-CN");
+    assert_eq!(google("PT_br"), "pt-BR");
+    assert!(normalize_lang(&TranslationProvider::GOOGLE, "sr_latn").is_err());
+    assert!(normalize_lang(&TranslationProvider::GOOGLE, "sr-Latn-RS").is_err());
+    assert_eq!(google("pa_arab"), "pa-Arab");
+    assert_eq!(google("zh-Hant"), "zh-TW");
+    assert_eq!(google("zh_hant"), "zh-TW");
+    assert_eq!(google("zh-Hans"), "zh-CN");
+    assert_eq!(google("zh_hans_sg"), "zh-CN");
+    assert_eq!(google("mni"), "mni-Mtei");
+    //the base subtag is compared, `ce` is not `ceb`
+    assert!(normalize_lang(&TranslationProvider::GOOGLE, "ce").is_err());
+
+    let deepl = |lang: &str| normalize_lang(&TranslationProvider::DEEPL, lang).unwrap();
+    assert_eq!(deepl("PT_br"), "PT-BR");
+    assert_eq!(deepl("zh_hans"), "ZH-HANS");
+    assert!(normalize_lang(&TranslationProvider::DEEPL, "sr_latn").is_err());
+
+    let libre = |lang: &str| normalize_lang(&TranslationProvider::LIBRETRANSLATE, lang).unwrap();
+    assert_eq!(libre("zh_hant"), "zh-Hant");
+    assert_eq!(libre("pt_br"), "pt-BR");
+
+    assert_eq!(canonical_locale("SR_latn_rs"), "sr-Latn-RS");
+    assert_eq!(canonical_locale("es_419"), "es-419");
+}
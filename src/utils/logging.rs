@@ -0,0 +1,103 @@
+//Log level cap of the crate, see `Config::max_log_level`
+//The `log` macros of the crate go through these ones, a record above the cap of the run is
+//dropped before reaching the logger. The cap only quiets the crate, the logger still decides
+//what it shows. It is set per thread for the duration of a run.
+
+use std::cell::Cell;
+
+use log::{Level, LevelFilter};
+
+use crate::config::Config;
+
+thread_local! {
+    static LEVEL: Cell<Option<LevelFilter>> = const { Cell::new(None) };
+}
+
+/// Whether the crate logs records of this level in the current run
+pub fn enabled(level: Level) -> bool {
+    LEVEL.with(|max| max.get().is_none_or(|max| level <= max))
+}
+
+/// Cap of the current run, to carry it over to worker threads
+pub fn current() -> Option<LevelFilter> {
+    LEVEL.with(Cell::get)
+}
+
+/// Sets the cap of the run until dropped, the previous level is restored
+#[derive(Debug)]
+pub struct LogScope {
+    previous: Option<LevelFilter>,
+}
+
+impl LogScope {
+    /// Use the cap of the config, keeps the current one when not set
+    pub fn enter(config: &Config) -> Self {
+        Self::set(config.max_log_level.or_else(current))
+    }
+
+    /// Use the level until dropped
+    pub fn set(level: Option<LevelFilter>) -> Self {
+        LogScope {
+            previous: LEVEL.with(|max| max.replace(level)),
+        }
+    }
+}
+
+impl Drop for LogScope {
+    fn drop(&mut self) {
+        LEVEL.with(|max| max.set(self.previous));
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => {
+        if $crate::utils::logging::enabled(log::Level::Error) {
+            log::error!($($arg)+)
+        }
+    };
+}
+
+//`warn` alone is ambiguous with the builtin attribute
+macro_rules! log_warn {
+    ($($arg:tt)+) => {
+        if $crate::utils::logging::enabled(log::Level::Warn) {
+            log::warn!($($arg)+)
+        }
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => {
+        if $crate::utils::logging::enabled(log::Level::Info) {
+            log::info!($($arg)+)
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if $crate::utils::logging::enabled(log::Level::Debug) {
+            log::debug!($($arg)+)
+        }
+    };
+}
+
+pub(crate) use {debug, error, info, log_warn as warn};
+
+#[test]
+fn test_log_scope() {
+    let quiet = Config::new().max_log_level(LevelFilter::Warn).build();
+    let unset = Config::new().build();
+
+    assert!(enabled(Level::Debug));
+    {
+        let _scope = LogScope::enter(&quiet);
+        assert!(!enabled(Level::Info));
+        assert!(enabled(Level::Warn));
+
+        //a nested run without a level keeps the level of the outer one
+        let _nested = LogScope::enter(&unset);
+        assert!(!enabled(Level::Info));
+    }
+    assert!(enabled(Level::Debug));
+}
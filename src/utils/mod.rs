@@ -1,23 +1,92 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     ffi::OsStr,
-    fs::{self, OpenOptions},
-    io::{BufWriter, Write},
+    fs,
+    io::Write,
     path::{Path, PathBuf},
+    process::{Command, Stdio},
 };
 
 use serde_json::{Value, json};
 
-use crate::i18n::autogen_cache::Autogen;
+use crate::{
+    config::{Config, LockPolicy, OutputFormat, Overwrite, PropertiesEncoding, StringsEncoding},
+    i18n::autogen_cache::Autogen,
+    report::FileWrite,
+    utils::{
+        languages::is_rtl,
+        logging::{info, warn},
+    },
+};
 
+pub mod glossary;
+pub mod icu;
+pub mod journal;
 pub mod languages;
+pub mod logging;
+pub mod placeholders;
+pub mod retry;
+pub mod schema;
+pub mod tmx;
 pub mod translation_limiter;
 
+/// Locale code of a file in the locales directory
+///
+/// `None` for directories and files with other extensions, eg: `README.md`, `.gitkeep`
+pub fn locale_file_stem<S: AsRef<str>>(path: &Path, extensions: &[S]) -> Option<String> {
+    let ext = path.extension()?.to_str()?;
+    let stem = path.file_stem()?.to_str()?;
+
+    (path.is_file() && extensions.iter().any(|e| e.as_ref() == ext)).then(|| stem.to_string())
+}
+
+/// Locales with a file in the locales directory, the source excluded, sorted
+pub fn existing_locales(config: &Config) -> Vec<String> {
+    let Ok(read_dir) = fs::read_dir(&config.locales_dir) else {
+        return vec![];
+    };
+
+    let mut extensions: Vec<&str> = config
+        .locale_extensions
+        .iter()
+        .map(|e| e.as_str())
+        .collect();
+    if let Some(format) = &config.output_format {
+        extensions.push(format);
+    }
+
+    let mut locales: Vec<String> = read_dir
+        .flatten()
+        .filter_map(|dir| locale_file_stem(&dir.path(), &extensions))
+        .filter(|stem| is_locale_code(stem) && *stem != config.source_locale)
+        .collect();
+    locales.sort();
+    locales.dedup();
+
+    locales
+}
+
+/// Looks like a language code, eg: `en`, `zh-CN`, `pt_BR`, `sr-Latn-RS`
+fn is_locale_code(code: &str) -> bool {
+    let mut parts = code.split(['-', '_']);
+    let lang = parts.next().unwrap_or_default();
+
+    (2..=3).contains(&lang.len())
+        && lang.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| {
+            (2..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
 /// If it does not match then return the new sha256
-pub fn match_sha256(locale_path: &Path, source_lang: &str, autogen_sha: &str) -> Option<String> {
-    let res = get_source_file_path(locale_path, source_lang);
+pub fn match_sha256(config: &Config, autogen_sha: &str) -> Option<String> {
+    let res = source_path(config);
     if let Some(item_path) = res {
-        let sha256_res = sha256::try_digest(item_path);
+        let sha256_res = if item_path.is_dir() {
+            namespaces_sha256(config)
+        } else {
+            sha256::try_digest(item_path)
+        };
         if let Ok(sha) = sha256_res {
             if autogen_sha != sha { Some(sha) } else { None }
         } else {
@@ -28,55 +97,364 @@ pub fn match_sha256(locale_path: &Path, source_lang: &str, autogen_sha: &str) ->
     }
 }
 
-pub fn write_locale_file(
-    locale_dir: &PathBuf,
+/// Checksum of the names and content of the namespace files
+fn namespaces_sha256(config: &Config) -> std::io::Result<String> {
+    let mut digests = String::new();
+    for path in namespace_files(config) {
+        digests.push_str(&path.file_name().unwrap_or_default().to_string_lossy());
+        digests.push_str(&sha256::try_digest(&path)?);
+    }
+    Ok(sha256::digest(digests))
+}
+
+/// Extension of the generated locale files
+/// Uses the source file extension unless an output format is set
+pub fn output_extension<'a>(config: &'a Config, source_path: &'a Path) -> &'a str {
+    if let Some(format) = &config.output_format {
+        format.as_str()
+    } else {
+        source_path
+            .extension()
+            .unwrap_or(OsStr::new("json"))
+            .to_str()
+            .unwrap_or("json")
+    }
+}
+
+/// Name of the generated file of a target locale relative to the locales directory,
+/// see `Config::output_name_template` and `Config::filename_case`
+pub fn output_file_name(config: &Config, source_path: &Path, target_locale: &str) -> String {
+    config
+        .output_name_template
+        .replace("{locale}", &config.filename_case.apply(target_locale))
+        .replace("{ext}", output_extension(config, source_path))
+        .replace("{source}", &config.source_locale)
+}
+
+/// Path of the generated file of a target locale
+pub fn output_file_path(config: &Config, source_path: &Path, target_locale: &str) -> PathBuf {
+    config
+        .locales_dir
+        .join(output_file_name(config, source_path, target_locale))
+}
+
+/// Nested value written to the file of a locale
+pub fn locale_value(
+    config: &Config,
     data: &BTreeMap<String, String>,
-    source_locale: &str,
     target_locale: &str,
-) -> Result<(), String> {
-    let locale_path = locale_dir.as_path();
+) -> Value {
+    let mut value = dot_to_json(data, &source_arrays(config));
+    if config.emit_direction {
+        value["_dir"] = json!(if is_rtl(target_locale) { "rtl" } else { "ltr" });
+    }
+    value
+}
 
-    let item_path_res = get_source_file_path(locale_path, source_locale);
+pub fn write_locale_file(
+    config: &Config,
+    data: &BTreeMap<String, String>,
+    target_locale: &str,
+) -> Result<FileWrite, String> {
+    let item_path_res = source_path(config);
 
     if let Some(item_path) = item_path_res {
-        let ext = item_path
-            .extension()
-            .unwrap_or(OsStr::new("json"))
-            .to_str()
-            .unwrap_or("json");
-
-        let new_map = dot_to_json(data);
-        let file_name = format!("{target_locale}.{ext}");
-        let file_path = locale_path.join(file_name);
-
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(file_path)
-            .unwrap();
-        let mut writer = BufWriter::new(file);
-
-        match ext {
-            "yml" | "yaml" => serde_yaml::to_writer(writer, &new_map).map_err(|e| e.to_string())?,
-            "toml" => writer
+        let ext = output_extension(config, &item_path);
+
+        let new_map = locale_value(config, data, target_locale);
+        let file_path = output_file_path(config, &item_path, target_locale);
+        if config.overwrite == Overwrite::Never && file_path.exists() {
+            return Ok(FileWrite::Kept);
+        }
+
+        let mut writer = Vec::new();
+
+        let format = OutputFormat::from_extension(ext)
+            .ok_or_else(|| format!("Unsupported output format `{ext}`"))?;
+
+        match format {
+            OutputFormat::Yaml => {
+                serde_yaml::to_writer(&mut writer, &new_map).map_err(|e| e.to_string())?
+            }
+            OutputFormat::Toml => writer
                 .write_all(
-                    toml::to_string_pretty(&new_map)
+                    toml::to_string_pretty(&json_to_toml(&new_map))
                         .map_err(|e| e.to_string())?
                         .as_bytes(),
                 )
                 .map_err(|e| e.to_string())?,
+            OutputFormat::Json5 => {
+                let mut json5 = String::new();
+                if config.generated_header {
+                    json5.push_str("// autogenerated by rust-i18n-autotranslate\n");
+                    json5.push_str(&format!("// source: {}\n", config.source_locale));
+                }
+                to_json5(&new_map, 0, &mut json5);
+                json5.push('\n');
+
+                writer
+                    .write_all(json5.as_bytes())
+                    .map_err(|e| e.to_string())?
+            }
+            OutputFormat::Strings => {
+                let mut strings = String::new();
+                if config.generated_header {
+                    strings.push_str("/* autogenerated by rust-i18n-autotranslate */\n");
+                    strings.push_str(&format!("/* source: {} */\n\n", config.source_locale));
+                }
+                for (key, value) in json_to_dot(&new_map) {
+                    strings.push_str(&format!(
+                        "\"{}\" = \"{}\";\n",
+                        escape_strings(&key),
+                        escape_strings(&value)
+                    ));
+                }
+
+                match config.strings_encoding {
+                    StringsEncoding::Utf8 => writer.write_all(strings.as_bytes()),
+                    StringsEncoding::Utf16Le => {
+                        //BOM followed by the utf-16 little endian code units
+                        let mut bytes = vec![0xFF, 0xFE];
+                        bytes.extend(strings.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+                        writer.write_all(&bytes)
+                    }
+                }
+                .map_err(|e| e.to_string())?
+            }
+            OutputFormat::Properties => {
+                let latin1 = config.properties_encoding == PropertiesEncoding::Latin1;
+                let mut properties = String::new();
+                if config.generated_header {
+                    properties.push_str("# autogenerated by rust-i18n-autotranslate\n");
+                    properties.push_str(&format!("# source: {}\n\n", config.source_locale));
+                }
+                for (key, value) in json_to_dot(&new_map) {
+                    properties.push_str(&format!(
+                        "{}={}\n",
+                        escape_properties(&key, true, latin1),
+                        escape_properties(&value, false, latin1)
+                    ));
+                }
 
-            _ => serde_json::to_writer_pretty(writer, &new_map).map_err(|e| e.to_string())?,
+                if latin1 {
+                    //every char above U+00FF was escaped, one byte per char
+                    let bytes: Vec<u8> = properties.chars().map(|c| c as u8).collect();
+                    writer.write_all(&bytes)
+                } else {
+                    writer.write_all(properties.as_bytes())
+                }
+                .map_err(|e| e.to_string())?
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, &new_map).map_err(|e| e.to_string())?
+            }
         }
 
-        Ok(())
+        if config.overwrite == Overwrite::IfChanged
+            && fs::read(&file_path).is_ok_and(|existing| existing == writer)
+        {
+            return Ok(FileWrite::Unchanged);
+        }
+
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        if config.read_only_output || config.target_file_permissions.is_some() {
+            make_writable(&file_path)?;
+        }
+        fs::write(&file_path, writer).map_err(|e| e.to_string())?;
+
+        Ok(FileWrite::Written)
     } else {
         Err("Source file not found".to_string())
     }
 }
 
-fn dot_to_json(map: &BTreeMap<String, String>) -> Value {
+/// Run `Config::post_format_command` on the written file of a locale
+pub fn format_locale_file(config: &Config, target_locale: &str) -> Result<(), String> {
+    let Some((program, args)) = config.post_format_command.split_first() else {
+        return Ok(());
+    };
+    let source_path = source_path(config).ok_or("Source file not found")?;
+    let file_path = output_file_path(config, &source_path, target_locale);
+    let file = file_path.to_string_lossy();
+
+    let mut command = Command::new(program);
+    command.args(args.iter().map(|arg| arg.replace("{file}", &file)));
+    if !args.iter().any(|arg| arg.contains("{file}")) {
+        command.arg(&file_path);
+    }
+
+    let output = command
+        .output()
+        .map_err(|e| format!("Could not run `{program}`: {e}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{program}` failed on {file} ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Convert the script of Chinese values with `Config::zh_convert_command`
+pub fn convert_zh(config: &Config, values: &[&str]) -> Result<Vec<String>, String> {
+    let (program, args) = config
+        .zh_convert_command
+        .split_first()
+        .ok_or("No command set to convert the Chinese script")?;
+    let input = serde_json::to_string(values).map_err(|e| e.to_string())?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run `{program}`: {e}"))?;
+    //written from another thread, the command may write its output before reading everything
+    let stdin = child.stdin.take();
+    let writer = std::thread::spawn(move || {
+        stdin.map_or(Ok(()), |mut stdin| stdin.write_all(input.as_bytes()))
+    });
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Could not run `{program}`: {e}"))?;
+    if let Ok(Err(e)) = writer.join() {
+        return Err(format!("Could not write to `{program}`: {e}"));
+    }
+    if !output.status.success() {
+        return Err(format!(
+            "`{program}` failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let converted: Vec<String> = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("`{program}` did not write a json array of strings: {e}"))?;
+    if converted.len() != values.len() {
+        return Err(format!(
+            "`{program}` wrote {} values instead of {}",
+            converted.len(),
+            values.len()
+        ));
+    }
+    Ok(converted)
+}
+
+/// Set `Config::target_file_permissions` and `Config::read_only_output` on the written file of
+/// a locale
+pub fn set_file_permissions(config: &Config, target_locale: &str) -> Result<(), String> {
+    if !config.read_only_output && config.target_file_permissions.is_none() {
+        return Ok(());
+    }
+    let source_path = source_path(config).ok_or("Source file not found")?;
+    let file_path = output_file_path(config, &source_path, target_locale);
+    let mut permissions = fs::metadata(&file_path)
+        .map_err(|e| format!("Could not read {}: {e}", file_path.display()))?
+        .permissions();
+
+    #[cfg(unix)]
+    if let Some(mode) = config.target_file_permissions {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(mode);
+    }
+    if config.read_only_output {
+        permissions.set_readonly(true);
+    }
+
+    fs::set_permissions(&file_path, permissions).map_err(|e| {
+        format!(
+            "Could not set the permissions of {}: {e}",
+            file_path.display()
+        )
+    })
+}
+
+/// Let the owner write a file made read-only by a previous run
+fn make_writable(path: &Path) -> Result<(), String> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Ok(());
+    };
+    let mut permissions = metadata.permissions();
+    if !permissions.readonly() {
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+
+    fs::set_permissions(path, permissions)
+        .map_err(|e| format!("Could not make {} writable: {e}", path.display()))
+}
+
+/// Dotted paths of the arrays of the source, a map with the keys `0..n` is not one of them
+fn source_arrays(config: &Config) -> BTreeSet<String> {
+    let files = if config.combine_output {
+        namespace_files(config)
+    } else {
+        source_path(config).into_iter().collect()
+    };
+
+    let mut arrays = BTreeSet::new();
+    for path in files {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let Ok(value) =
+            read_locale_file(&path).and_then(|(content, _)| parse_locale_value(&content, ext))
+        else {
+            continue;
+        };
+        let prefix = if config.combine_output {
+            locale_file_stem(&path, &config.locale_extensions).unwrap_or_default()
+        } else {
+            String::new()
+        };
+        collect_arrays(&value, &prefix, &mut arrays);
+    }
+
+    arrays
+}
+
+fn collect_arrays(value: &Value, prefix: &str, arrays: &mut BTreeSet<String>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{prefix}.{key}")
+        }
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map {
+                collect_arrays(item, &join(key), arrays);
+            }
+        }
+        Value::Array(items) => {
+            arrays.insert(prefix.to_string());
+            for (idx, item) in items.iter().enumerate() {
+                collect_arrays(item, &join(&idx.to_string()), arrays);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Nest the dotted keys, `features.0.title` is the `title` of the first item of the `features`
+/// array when `features` is one of the `arrays` paths
+fn dot_to_json(map: &BTreeMap<String, String>, arrays: &BTreeSet<String>) -> Value {
     let mut root = json!({});
 
     for (key, value) in map {
@@ -86,6 +464,15 @@ fn dot_to_json(map: &BTreeMap<String, String>) -> Value {
         for (i, part) in parts.iter().enumerate() {
             if i == parts.len() - 1 {
                 current[part] = json!(value);
+            } else if current.get(part).is_some_and(|item| !item.is_object()) {
+                //`menu` and `menu.file` can not both be nested, the rest of the key stays dotted
+                let rest = parts[i..].join(".");
+                warn!(
+                    "`{key}` is under the value of `{}`, written as the key `{rest}`",
+                    parts[..=i].join(".")
+                );
+                current[rest.as_str()] = json!(value);
+                break;
             } else {
                 if current.get(part).is_none() {
                     current[part] = json!({});
@@ -95,106 +482,562 @@ fn dot_to_json(map: &BTreeMap<String, String>) -> Value {
         }
     }
 
+    //the root stays a map
+    if let Value::Object(map) = &mut root {
+        for (key, item) in map.iter_mut() {
+            restore_arrays(item, key, arrays);
+        }
+    }
+
     root
 }
 
-pub fn get_source_file_path(locale_path: &Path, source_locale: &str) -> Option<PathBuf> {
-    let directory = fs::read_dir(locale_path).ok()?;
+/// Objects indexed `0..n` at the path of an array of the source were flattened to dotted keys
+fn restore_arrays(value: &mut Value, path: &str, arrays: &BTreeSet<String>) {
+    if let Value::Object(map) = value {
+        for (key, item) in map.iter_mut() {
+            restore_arrays(item, &format!("{path}.{key}"), arrays);
+        }
 
-    let mut path_buf = None;
+        let is_array = arrays.contains(path)
+            && !map.is_empty()
+            && (0..map.len()).all(|idx| map.contains_key(&idx.to_string()));
+        if is_array {
+            let items = (0..map.len())
+                .filter_map(|idx| map.remove(&idx.to_string()))
+                .collect();
+            *value = Value::Array(items);
+        }
+    }
+}
 
-    for item_res in directory {
-        if let Ok(item) = item_res {
-            let item_path = item.path();
-            if item_path.is_file()
-                && item_path
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_str()
-                    .unwrap_or_default()
-                    == source_locale
-            {
-                path_buf = Some(item_path);
-                break;
-            } else {
-                path_buf = None;
+/// Convert to a TOML table
+fn json_to_toml(value: &Value) -> toml::Value {
+    match value {
+        Value::Object(map) => toml::Value::Table(
+            map.iter()
+                .map(|(key, item)| (key.clone(), json_to_toml(item)))
+                .collect(),
+        ),
+        Value::Array(items) => toml::Value::Array(items.iter().map(json_to_toml).collect()),
+        Value::String(s) => toml::Value::String(s.clone()),
+        other => toml::Value::String(other.to_string()),
+    }
+}
+
+/// Flatten a nested value back into dotted keys
+fn json_to_dot(value: &Value) -> BTreeMap<String, String> {
+    fn flatten(value: &Value, prefix: &str, out: &mut BTreeMap<String, String>) {
+        match value {
+            Value::Object(map) => {
+                for (key, item) in map {
+                    let path = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{prefix}.{key}")
+                    };
+                    flatten(item, &path, out);
+                }
             }
-        } else {
-            path_buf = None;
+            Value::Array(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    flatten(item, &format!("{prefix}.{idx}"), out);
+                }
+            }
+            Value::String(s) => {
+                out.insert(prefix.to_string(), s.clone());
+            }
+            other => {
+                out.insert(prefix.to_string(), other.to_string());
+            }
+        }
+    }
+
+    let mut out = BTreeMap::new();
+    flatten(value, "", &mut out);
+    out
+}
+
+/// Escape a value for an Apple `.strings` file
+fn escape_strings(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape a key or value for a Java `.properties` file
+///
+/// Chars outside ISO-8859-1 are written as `\uXXXX` when `latin1` is set
+fn escape_properties(value: &str, is_key: bool, latin1: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (idx, c) in value.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\x0C' => escaped.push_str("\\f"),
+            '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            //whitespace separates the key, leading whitespace of a value is dropped
+            ' ' if is_key || idx == 0 => escaped.push_str("\\ "),
+            c if latin1 && (c as u32) > 0xFF => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{unit:04X}"));
+                }
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Serialize the value as JSON5, unquoted identifier keys and trailing commas
+fn to_json5(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push_str("{\n");
+            for (key, item) in map {
+                out.push_str(&"  ".repeat(indent + 1));
+                if is_json5_identifier(key) {
+                    out.push_str(key);
+                } else {
+                    out.push_str(&Value::String(key.clone()).to_string());
+                }
+                out.push_str(": ");
+                to_json5(item, indent + 1, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&"  ".repeat(indent));
+            out.push('}');
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push_str("[\n");
+            for item in items {
+                out.push_str(&"  ".repeat(indent + 1));
+                to_json5(item, indent + 1, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&"  ".repeat(indent));
+            out.push(']');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+fn is_json5_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' || first == '$' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        }
+        _ => false,
+    }
+}
+
+/// Content of a locale file, a byte order mark is removed and UTF-16 is transcoded
+///
+/// Returns the encoding that was converted, `None` for plain UTF-8
+pub fn read_locale_file(path: &Path) -> Result<(String, Option<&'static str>), String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    decode_locale_content(&bytes)
+}
+
+/// Files saved by Windows editors start with a byte order mark, UTF-16 ones may not
+fn decode_locale_content(bytes: &[u8]) -> Result<(String, Option<&'static str>), String> {
+    let utf16 = |bytes: &[u8], unit: fn([u8; 2]) -> u16, encoding: &'static str| {
+        if !bytes.len().is_multiple_of(2) {
+            return Err(format!("Invalid {encoding} content, odd number of bytes"));
         }
+        let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map(|content| (content, Some(encoding)))
+            .map_err(|e| format!("Invalid {encoding} content, {e}"))
+    };
+
+    match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => std::str::from_utf8(rest)
+            .map(|content| (content.to_string(), Some("UTF-8 with BOM")))
+            .map_err(|e| format!("Invalid UTF-8 content, {e}")),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes, "UTF-16LE"),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes, "UTF-16BE"),
+        //ascii text without a byte order mark
+        [first, 0, ..] if *first != 0 => utf16(bytes, u16::from_le_bytes, "UTF-16LE"),
+        [0, second, ..] if *second != 0 => utf16(bytes, u16::from_be_bytes, "UTF-16BE"),
+        _ => String::from_utf8(bytes.to_vec())
+            .map(|content| (content, None))
+            .map_err(|e| format!("The file is not UTF-8 or UTF-16, save it as UTF-8 ({e})")),
     }
+}
 
-    path_buf
+/// Parse the content of a locale file into dotted keys
+pub fn parse_locale_content(content: &str, ext: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut data = json_to_dot(&parse_locale_value(content, ext)?);
+    data.remove("_version");
+
+    Ok(data)
+}
+
+/// Nested value of the content of a locale file
+fn parse_locale_value(content: &str, ext: &str) -> Result<Value, String> {
+    Ok(match ext {
+        "yml" | "yaml" => serde_yaml::from_str::<Value>(content)
+            .map_err(|e| format!("Invalid YAML format, {e}"))?,
+        "json" => serde_json::from_str::<Value>(content)
+            .map_err(|e| format!("Invalid JSON format, {e}"))?,
+        "toml" => {
+            toml::from_str::<Value>(content).map_err(|e| format!("Invalid TOML format, {e}"))?
+        }
+        _ => return Err(format!("Unsupported locale file extension `{ext}`")),
+    })
+}
+
+/// The dotted key is the path or under it, `menu.file` matches `menu.file.open` but not `menu.files`
+pub fn in_path(key: &str, path: &str) -> bool {
+    key.strip_prefix(path)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Add the keys outside `Config::only_path` from the existing target file to the translations
+pub fn merge_outside_path(
+    config: &Config,
+    mut translated: BTreeMap<String, String>,
+    target_locale: &str,
+) -> BTreeMap<String, String> {
+    let Some(path) = &config.only_path else {
+        return translated;
+    };
+
+    match existing_translations(config, target_locale) {
+        Some(Ok(existing)) => {
+            for (key, value) in existing {
+                if !in_path(&key, path) {
+                    translated.entry(key).or_insert(value);
+                }
+            }
+        }
+        Some(Err(e)) => warn!("{e}, the keys outside `{path}` are not kept"),
+        //nothing to keep yet
+        None => {}
+    }
+
+    translated
+}
+
+/// Keep the existing translation of the locked keys, see `Config::lock_key`
+///
+/// The source value of a key is recorded in the cache the first time it is locked, with
+/// `LockPolicy::ReleaseOnSourceChange` the key is translated again once the source changes.
+pub fn apply_locks(
+    config: &Config,
+    autogen: &mut Autogen,
+    source_data: &BTreeMap<String, String>,
+    mut translated: BTreeMap<String, String>,
+    target_locale: &str,
+) -> BTreeMap<String, String> {
+    if config.locked_keys.is_empty() {
+        return translated;
+    }
+
+    let existing = match existing_translations(config, target_locale) {
+        Some(Ok(existing)) => existing,
+        Some(Err(e)) => {
+            warn!("{e}, the locked keys are not kept");
+            return translated;
+        }
+        None => BTreeMap::new(),
+    };
+
+    for key in &config.locked_keys {
+        let Some(source) = source_data.get(key) else {
+            continue;
+        };
+
+        let released = released(config, autogen, target_locale, key, source);
+
+        match existing.get(key) {
+            Some(value) if !released => {
+                translated.insert(key.to_string(), value.to_string());
+                //locked from now on, the translation made this run is kept next time
+                if autogen
+                    .locked_source(&config.source_locale, target_locale, key)
+                    .is_none()
+                {
+                    autogen.lock(&config.source_locale, target_locale, key, source);
+                }
+            }
+            _ => {
+                if released {
+                    info!(locale = target_locale, key = key.as_str(); "Source of locked `{key}` changed, translated again");
+                }
+                autogen.lock(&config.source_locale, target_locale, key, source);
+            }
+        }
+    }
+
+    translated
+}
+
+/// Locked keys whose existing translation is kept by [`apply_locks`], they are not sent to
+/// the provider
+pub fn kept_locked_keys<'a>(
+    config: &'a Config,
+    autogen: &Autogen,
+    source_data: &BTreeMap<String, String>,
+    target_locale: &str,
+) -> BTreeSet<&'a str> {
+    if config.locked_keys.is_empty() {
+        return BTreeSet::new();
+    }
+    let Some(Ok(existing)) = existing_translations(config, target_locale) else {
+        return BTreeSet::new();
+    };
+
+    config
+        .locked_keys
+        .iter()
+        .filter(|key| {
+            existing.contains_key(*key)
+                && source_data
+                    .get(*key)
+                    .is_some_and(|source| !released(config, autogen, target_locale, key, source))
+        })
+        .map(String::as_str)
+        .collect()
+}
+
+/// Translations kept for the locked keys of an in-memory source map, see `Config::lock_key`
+///
+/// A map has no target file, a locked key keeps the cached translation of its value when it
+/// was locked. The keys not locked yet are locked to their current value.
+pub fn map_locked_translations(
+    config: &Config,
+    autogen: &mut Autogen,
+    source_map: &BTreeMap<String, String>,
+    contexts: &BTreeMap<String, String>,
+    target_locale: &str,
+) -> BTreeMap<String, String> {
+    let cache_key = config.cache_key_normalization;
+    let mut kept = BTreeMap::new();
+    for key in &config.locked_keys {
+        let Some(source) = source_map.get(key) else {
+            continue;
+        };
+
+        let locked = autogen
+            .locked_source(&config.source_locale, target_locale, key)
+            .map(str::to_string);
+        match locked {
+            Some(locked) if !released(config, autogen, target_locale, key, source) => {
+                let context = contexts.get(key).map(String::as_str);
+                if let Some(translation) = autogen
+                    .translations(&config.source_locale, target_locale)
+                    .and_then(|cached| cached.get(&*cache_key.context_key(&locked, context)))
+                {
+                    kept.insert(key.to_string(), translation.to_string());
+                }
+            }
+            //the translation made this run is cached and kept next time
+            _ => autogen.lock(&config.source_locale, target_locale, key, source),
+        }
+    }
+
+    kept
+}
+
+/// The source of a locked key changed and `LockPolicy::ReleaseOnSourceChange` translates it again
+fn released(
+    config: &Config,
+    autogen: &Autogen,
+    target_locale: &str,
+    key: &str,
+    source: &str,
+) -> bool {
+    config.lock_policy == LockPolicy::ReleaseOnSourceChange
+        && autogen
+            .locked_source(&config.source_locale, target_locale, key)
+            .is_some_and(|locked| locked != source)
+}
+
+/// Translations of the existing target file, `None` when there is no file yet
+pub fn existing_translations(
+    config: &Config,
+    target_locale: &str,
+) -> Option<Result<BTreeMap<String, String>, String>> {
+    let source_path = source_path(config)?;
+
+    let target_path = output_file_path(config, &source_path, target_locale);
+    let ext = output_extension(config, &source_path);
+    let (content, _) = read_locale_file(&target_path).ok()?;
+    let existing = parse_locale_content(&content, ext)
+        .map_err(|e| format!("Could not read {}: {e}", target_path.display()));
+
+    Some(existing)
+}
+
+/// Key -> description of the `context_file`, empty when not set or invalid
+pub fn load_contexts(config: &Config) -> BTreeMap<String, String> {
+    let Some(path) = &config.context_file else {
+        return BTreeMap::new();
+    };
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("json");
+
+    match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|c| parse_locale_content(&c, ext))
+    {
+        Ok(contexts) => contexts,
+        Err(e) => {
+            warn!("Could not load the context file {}: {e}", path.display());
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Content of the file at the given git ref
+///
+/// Runs `git show <ref>:./<file>` from the directory of the file
+pub fn git_show(file_path: &Path, git_ref: &str) -> Result<String, String> {
+    let file_name = file_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid file path")?;
+    let dir = file_path.parent().ok_or("Invalid file path")?;
+
+    let output = Command::new("git")
+        .arg("show")
+        .arg(format!("{git_ref}:./{file_name}"))
+        .current_dir(dir)
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        decode_locale_content(&output.stdout).map(|(content, _)| content)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+pub fn get_source_file_path(
+    locale_path: &Path,
+    source_locale: &str,
+    extensions: &[String],
+) -> Option<PathBuf> {
+    fs::read_dir(locale_path)
+        .ok()?
+        .flatten()
+        .map(|item| item.path())
+        .find(|item_path| locale_file_stem(item_path, extensions).as_deref() == Some(source_locale))
+}
+
+/// Source of the config, the directory of the namespace files with `Config::combine_output`
+pub fn source_path(config: &Config) -> Option<PathBuf> {
+    if config.combine_output {
+        let namespaces = config.locales_dir.join(&config.source_locale);
+        return namespaces.is_dir().then_some(namespaces);
+    }
+
+    get_source_file_path(
+        &config.locales_dir,
+        &config.source_locale,
+        &config.locale_extensions,
+    )
+}
+
+/// Namespace files of the source, sorted, see `Config::combine_output`
+pub fn namespace_files(config: &Config) -> Vec<PathBuf> {
+    let Ok(read_dir) = fs::read_dir(config.locales_dir.join(&config.source_locale)) else {
+        return vec![];
+    };
+
+    let mut files: Vec<PathBuf> = read_dir
+        .flatten()
+        .map(|item| item.path())
+        .filter(|path| locale_file_stem(path, &config.locale_extensions).is_some())
+        .collect();
+    files.sort();
+
+    files
 }
 
 ///check things list to re-translate data if
 /// - whether all the target languages specified exist
-/// eg: if all the languages specified already exist in the locale dir then no retranslate
+///   eg: if all the languages specified already exist in the locale dir then no retranslate
 /// - whether there is addition or subtraction of languages
-/// eg: if new language is added then re-translate
+///   eg: if new language is added then re-translate
 ///
+/// The files and cached translations of the locales no longer targeted are removed with
+/// `Config::prune_untracked`, left alone otherwise
 ///
 /// If verification fails retranslate
-pub fn verify_locales(
-    locale_path: &Path,
-    source_locale: &str,
-    target_locales: &Vec<String>,
-) -> Result<(), &'static str> {
-    let source_locale_path_res = get_source_file_path(locale_path, source_locale);
+pub fn verify_locales(config: &Config) -> Result<(), &'static str> {
+    let locale_path = config.locales_dir.as_path();
+    let source_locale_path_res = source_path(config);
 
     if let Some(source_locale_path) = source_locale_path_res {
-        let ext = source_locale_path
-            .extension()
-            .unwrap_or(OsStr::new("json"))
-            .to_str()
-            .unwrap_or("json");
+        let read_dir = fs::read_dir(locale_path).map_err(|_| "Read Dir Error")?;
 
-        let target_locales_with_ext: Vec<String> = target_locales
+        //generated files may use another format than the source
+        let mut extensions: Vec<&str> = config
+            .locale_extensions
             .iter()
-            .map(|t| format!("{t}.{ext}"))
+            .map(|e| e.as_str())
             .collect();
+        extensions.push(output_extension(config, &source_locale_path));
 
-        let read_dir = fs::read_dir(locale_path).map_err(|_| "Read Dir Error")?;
+        //locales are matched by file stem so a change of output format does not delete them
+        let mut locales_dir = Vec::new();
 
-        let mut file_names_dir = Vec::new();
-
-        let source_filename = source_locale_path
-            .file_name()
-            .unwrap_or_default()
-            .display()
-            .to_string();
-
-        for entry in read_dir {
-            if let Ok(dir) = entry {
-                //Check if the files in directory are in target locales
-                //if not in target locales delete them
-                let dir_file_name = dir.file_name().display().to_string();
-                if !target_locales_with_ext.contains(&dir_file_name)
-                    && dir_file_name != source_filename
-                {
-                    let _ = fs::remove_file(dir.path());
-
-                    //remove locale data from autogen also
-                    let mut autogen = Autogen::load();
-                    let file_stem = dir
-                        .path()
-                        .file_stem()
-                        .unwrap_or_default()
-                        .display()
-                        .to_string();
-                    autogen.data.remove(&file_stem);
-                    let _ = autogen.update_cache();
-                } else {
-                    file_names_dir.push(dir_file_name);
+        for dir in read_dir.flatten() {
+            //Check if the files in directory are in target locales
+            //if not in target locales delete them
+            //leave anything that is not a locale file alone
+            let Some(file_stem) = locale_file_stem(&dir.path(), &extensions) else {
+                continue;
+            };
+            if !is_locale_code(&file_stem) {
+                continue;
+            }
+
+            if !config.is_known_locale(&file_stem) {
+                if !config.prune_untracked {
+                    continue;
                 }
+                info!(locale = file_stem.as_str(); "Removing `{file_stem}`, it is no longer a target");
+                let _ = fs::remove_file(dir.path());
+
+                //remove locale data from autogen also, an older cache is migrated by the run
+                let mut autogen = Autogen::load_migrating(config, None);
+                autogen.remove_locale(&file_stem);
+                let _ = autogen.update_cache();
+            } else {
+                locales_dir.push(file_stem);
             }
         }
 
         let mut should_retranslate = false;
 
         //Now check if new locales are specified that do not exist in the directory
-        for target_locale_ext in target_locales_with_ext {
-            if !file_names_dir.contains(&target_locale_ext) {
+        for target_locale in &config.target_locales {
+            if !locales_dir.contains(target_locale)
+                && !output_file_path(config, &source_locale_path, target_locale).exists()
+            {
                 should_retranslate = true;
             }
         }
@@ -205,25 +1048,231 @@ pub fn verify_locales(
             Ok(())
         }
     } else {
-        return Err("No source file path");
+        Err("No source file path")
+    }
+}
+
+/// Directory of a test under the temp dir of the system, removed when dropped
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// Empty directory for the test `name`, unique to the test process
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "rust_i18n_autotranslate_{name}_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
     }
 }
 
 #[test]
 fn test_locale_file() {
-    use normpath::PathExt;
     let mut data = BTreeMap::new();
     data.insert("hello.me".to_string(), "Bonjour Me".to_string());
     data.insert("hello.world".to_string(), "Monde".to_string());
 
     let locales = Path::new("./locales");
-    fs::create_dir_all(&locales).unwrap();
+    fs::create_dir_all(locales).unwrap();
     fs::File::create(locales.join("en.json")).unwrap();
-    let locale_dir = locales.normalize().unwrap().as_path().to_path_buf();
+    let config = Config::new().locales_directory(locales).build();
 
-    assert_eq!(write_locale_file(&locale_dir, &data, "en", "fr"), Ok(()));
+    assert_eq!(
+        write_locale_file(&config, &data, "fr"),
+        Ok(FileWrite::Written)
+    );
 
-    fs::remove_dir_all(&locales).unwrap();
+    fs::remove_dir_all(locales).unwrap();
+}
+
+#[test]
+fn test_locale_file_overwrite() {
+    let mut data = BTreeMap::new();
+    data.insert("hello".to_string(), "Bonjour".to_string());
+
+    let locales = &TempDir::new("locales_overwrite");
+    fs::File::create(locales.join("en.json")).unwrap();
+    let mut config = Config::new().locales_directory(locales).build();
+
+    let always = write_locale_file(&config, &data, "fr");
+
+    config.overwrite = Overwrite::IfChanged;
+    let unchanged = write_locale_file(&config, &data, "fr");
+    data.insert("hello".to_string(), "Salut".to_string());
+    let changed = write_locale_file(&config, &data, "fr");
+
+    config.overwrite = Overwrite::Never;
+    data.insert("hello".to_string(), "Coucou".to_string());
+    let kept = write_locale_file(&config, &data, "fr");
+    let missing = write_locale_file(&config, &data, "de");
+
+    let fr = fs::read_to_string(locales.join("fr.json")).unwrap();
+
+    assert_eq!(always, Ok(FileWrite::Written));
+    assert_eq!(unchanged, Ok(FileWrite::Unchanged));
+    assert_eq!(changed, Ok(FileWrite::Written));
+    assert_eq!(kept, Ok(FileWrite::Kept));
+    assert_eq!(missing, Ok(FileWrite::Written));
+    assert!(fr.contains("Salut"));
+}
+
+#[test]
+fn test_locale_file_json5() {
+    let mut data = BTreeMap::new();
+    data.insert("hello.me".to_string(), "Bonjour \"Me\"".to_string());
+    data.insert("hello.world-wide".to_string(), "Monde".to_string());
+
+    let locales = &TempDir::new("locales_json5");
+    fs::File::create(locales.join("en.yml")).unwrap();
+    let config = Config::new()
+        .locales_directory(locales)
+        .output_format("json5")
+        .build();
+
+    assert_eq!(
+        write_locale_file(&config, &data, "fr"),
+        Ok(FileWrite::Written)
+    );
+
+    let written = fs::read_to_string(locales.join("fr.json5")).unwrap();
+
+    assert_eq!(
+        written,
+        "// autogenerated by rust-i18n-autotranslate\n// source: en\n{\n  hello: {\n    me: \"Bonjour \\\"Me\\\"\",\n    \"world-wide\": \"Monde\",\n  },\n}\n"
+    );
+}
+
+#[test]
+fn test_verify_locales_mixed_extensions() {
+    let locales = &TempDir::new("locales_verify_mixed");
+    for file in ["en.yml", "fr.json", "de.yml"] {
+        fs::File::create(locales.join(file)).unwrap();
+    }
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_target_lang("de")
+        .build();
+    let verified = verify_locales(&config);
+
+    let fr_kept = locales.join("fr.json").exists();
+
+    assert_eq!(verified, Ok(()));
+    assert!(fr_kept);
+}
+
+#[test]
+fn test_verify_locales_junk_files() {
+    let locales = &TempDir::new("locales_verify_junk");
+    fs::create_dir_all(locales.join("nested")).unwrap();
+    for file in [
+        "README.md",
+        ".gitkeep",
+        "notes.txt",
+        "en.yml",
+        "fr.yml",
+        "schema.json",
+    ] {
+        fs::File::create(locales.join(file)).unwrap();
+    }
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .build();
+    let source = get_source_file_path(locales, "en", &config.locale_extensions);
+    let verified = verify_locales(&config);
+
+    let kept = [
+        "README.md",
+        ".gitkeep",
+        "notes.txt",
+        "schema.json",
+        "nested",
+    ]
+    .iter()
+    .all(|file| locales.join(file).exists());
+
+    assert_eq!(source, Some(locales.join("en.yml")));
+    assert_eq!(verified, Ok(()));
+    assert!(kept);
+}
+
+#[test]
+fn test_source_file_path_before_other_files() {
+    let locales = &TempDir::new("locales_source_first");
+    for file in ["en.json", "fr.json", "de.yml", "zz.txt"] {
+        fs::File::create(locales.join(file)).unwrap();
+    }
+    fs::create_dir_all(locales.join("es")).unwrap();
+
+    let extensions = Config::new().locale_extensions;
+    let source = get_source_file_path(locales, "en", &extensions);
+    let missing = get_source_file_path(locales, "it", &extensions);
+
+    assert_eq!(source, Some(locales.join("en.json")));
+    assert_eq!(missing, None);
+}
+
+#[test]
+fn test_source_file_path_extensions() {
+    let locales = &TempDir::new("locales_extensions");
+    fs::File::create(locales.join("en.json")).unwrap();
+
+    let config = Config::new().locale_extensions(vec!["yml"]).build();
+    let source = get_source_file_path(locales, "en", &config.locale_extensions);
+
+    assert_eq!(source, None);
+}
+
+#[test]
+fn test_locale_file_name_template() {
+    let mut data = BTreeMap::new();
+    data.insert("hello".to_string(), "Bonjour".to_string());
+
+    let locales = &TempDir::new("locales_name_template");
+    fs::File::create(locales.join("en.json")).unwrap();
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .output_name_template("values-{locale}/strings.{ext}")
+        .build();
+
+    let written = write_locale_file(&config, &data, "fr");
+    let exists = locales.join("values-fr/strings.json").exists();
+    let verified = verify_locales(&config);
+
+    assert_eq!(written, Ok(FileWrite::Written));
+    assert!(exists);
+    assert_eq!(verified, Ok(()));
 }
 
 // use std::hash::{DefaultHasher, Hash, Hasher};
@@ -233,3 +1282,434 @@ fn test_locale_file() {
 //     s.hash(&mut hasher);
 //     hasher.finish()
 // }
+
+#[test]
+fn test_locale_file_direction() {
+    let mut data = BTreeMap::new();
+    data.insert("hello".to_string(), "مرحبا".to_string());
+
+    let locales = &TempDir::new("locales_direction");
+    fs::File::create(locales.join("en.json")).unwrap();
+    let config = Config::new()
+        .locales_directory(locales)
+        .emit_direction(true)
+        .build();
+
+    assert_eq!(
+        write_locale_file(&config, &data, "ar"),
+        Ok(FileWrite::Written)
+    );
+    assert_eq!(
+        write_locale_file(&config, &data, "fr"),
+        Ok(FileWrite::Written)
+    );
+
+    let ar: Value =
+        serde_json::from_str(&fs::read_to_string(locales.join("ar.json")).unwrap()).unwrap();
+    let fr: Value =
+        serde_json::from_str(&fs::read_to_string(locales.join("fr.json")).unwrap()).unwrap();
+
+    assert_eq!(ar["_dir"], "rtl");
+    assert_eq!(fr["_dir"], "ltr");
+}
+
+#[test]
+fn test_verify_locales_keeps_other_groups() {
+    let locales = &TempDir::new("locales_groups");
+    for file in ["en.json", "fr.json", "ja.json", "ko.json"] {
+        fs::File::create(locales.join(file)).unwrap();
+    }
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("fr")
+        .add_group("ja", vec!["ko"])
+        .build();
+
+    let verified = verify_locales(&config);
+    let kept = ["ja.json", "ko.json"].map(|file| locales.join(file).exists());
+
+    assert_eq!(verified, Ok(()));
+    assert_eq!(kept, [true, true]);
+}
+
+#[test]
+fn test_merge_outside_path() {
+    let locales = &TempDir::new("locales_only_path");
+    fs::File::create(locales.join("en.json")).unwrap();
+    fs::write(
+        locales.join("fr.json"),
+        r#"{"menu": {"file": {"open": "Ancien"}, "files": "Fichiers"}, "title": "Titre"}"#,
+    )
+    .unwrap();
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .only_path("menu.file")
+        .build();
+    let translated = BTreeMap::from([("menu.file.open".to_string(), "Ouvrir".to_string())]);
+
+    let merged = merge_outside_path(&config, translated, "fr");
+
+    assert!(in_path("menu.file", "menu.file"));
+    assert!(!in_path("menu.files", "menu.file"));
+    assert_eq!(
+        merged,
+        BTreeMap::from([
+            ("menu.file.open".to_string(), "Ouvrir".to_string()),
+            ("menu.files".to_string(), "Fichiers".to_string()),
+            ("title".to_string(), "Titre".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn test_targets_from_existing() {
+    let locales = &TempDir::new("locales_existing");
+    for file in ["en.json", "fr.yml", "de.json", "notes.json", "README.md"] {
+        fs::File::create(locales.join(file)).unwrap();
+    }
+
+    let config = Config::new()
+        .locales_directory(locales)
+        .add_target_lang("es")
+        .add_target_lang("fr")
+        .targets_from_existing(true)
+        .build();
+
+    assert_eq!(config.target_locales, ["es", "fr", "de"]);
+}
+
+#[test]
+fn test_load_contexts() {
+    let dir = TempDir::new("descriptions_test");
+    let path = &dir.join("descriptions_test.yml");
+    fs::write(path, "menu:\n  open: Button opening a file\n").unwrap();
+
+    let config = Config::new().context_file(path).build();
+    let contexts = load_contexts(&config);
+
+    assert_eq!(
+        contexts.get("menu.open").map(String::as_str),
+        Some("Button opening a file")
+    );
+    assert!(load_contexts(&Config::new().build()).is_empty());
+}
+
+#[test]
+fn test_locale_file_properties() {
+    let mut data = BTreeMap::new();
+    data.insert("menu.open".to_string(), "Öffnen: 文件 = ok".to_string());
+
+    let locales = &TempDir::new("locales_properties");
+    fs::File::create(locales.join("en.json")).unwrap();
+    let mut config = Config::new()
+        .locales_directory(locales)
+        .output_format("properties")
+        .output_name_template("messages_{locale}.{ext}")
+        .generated_header(false)
+        .build();
+
+    let latin1_written = write_locale_file(&config, &data, "de");
+    let latin1 = fs::read(locales.join("messages_de.properties")).unwrap();
+
+    config.properties_encoding = PropertiesEncoding::Utf8;
+    let utf8_written = write_locale_file(&config, &data, "de");
+    let utf8 = fs::read_to_string(locales.join("messages_de.properties")).unwrap();
+
+    assert_eq!(latin1_written, Ok(FileWrite::Written));
+    assert_eq!(utf8_written, Ok(FileWrite::Written));
+    assert_eq!(
+        latin1,
+        b"menu.open=\xD6ffnen\\: \\u6587\\u4EF6 \\= ok\n".to_vec()
+    );
+    assert_eq!(utf8, "menu.open=Öffnen\\: 文件 \\= ok\n");
+}
+
+#[test]
+fn test_locale_file_strings_utf16() {
+    let mut data = BTreeMap::new();
+    data.insert("menu.open".to_string(), "Ouvrir \"fichier\"".to_string());
+
+    let locales = &TempDir::new("locales_strings");
+    fs::File::create(locales.join("en.json")).unwrap();
+    let config = Config::new()
+        .locales_directory(locales)
+        .output_format("strings")
+        .generated_header(false)
+        .strings_encoding(StringsEncoding::Utf16Le)
+        .build();
+
+    assert_eq!(
+        write_locale_file(&config, &data, "fr"),
+        Ok(FileWrite::Written)
+    );
+
+    let written = fs::read(locales.join("fr.strings")).unwrap();
+
+    assert_eq!(&written[..2], &[0xFF, 0xFE]);
+    let units: Vec<u16> = written[2..]
+        .chunks(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    assert_eq!(
+        String::from_utf16(&units).unwrap(),
+        "\"menu.open\" = \"Ouvrir \\\"fichier\\\"\";\n"
+    );
+}
+
+#[test]
+fn test_git_show() {
+    let locales = &TempDir::new("locales_git");
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+            .args(args)
+            .current_dir(locales)
+            .output()
+            .unwrap()
+    };
+    git(&["init", "-q"]);
+    fs::write(
+        locales.join("en.yml"),
+        "hello: Hello\nmenu:\n  open: Open\n",
+    )
+    .unwrap();
+    git(&["add", "en.yml"]);
+    git(&["commit", "-q", "-m", "init"]);
+    fs::write(locales.join("en.yml"), "hello: Hi\n").unwrap();
+
+    let previous = git_show(&locales.join("en.yml"), "HEAD");
+    let missing = git_show(&locales.join("en.yml"), "no-such-ref");
+
+    let previous = parse_locale_content(&previous.unwrap(), "yml").unwrap();
+    assert_eq!(previous.get("hello").map(|v| v.as_str()), Some("Hello"));
+    assert_eq!(previous.get("menu.open").map(|v| v.as_str()), Some("Open"));
+    assert!(missing.is_err());
+}
+
+#[test]
+fn test_apply_locks() {
+    let locales = &TempDir::new("locales_locks");
+    fs::write(
+        locales.join("en.json"),
+        r#"{"legal": "Terms", "hello": "Hello"}"#,
+    )
+    .unwrap();
+    fs::write(
+        locales.join("fr.json"),
+        r#"{"legal": "Conditions", "hello": "Bonjour"}"#,
+    )
+    .unwrap();
+
+    let mut config = Config::new()
+        .locales_directory(locales)
+        .lock_key("legal")
+        .build();
+    let mut autogen = Autogen::default();
+    let translated = |legal: &str| {
+        BTreeMap::from([
+            ("legal".to_string(), legal.to_string()),
+            ("hello".to_string(), "Salut".to_string()),
+        ])
+    };
+    let source = |legal: &str| {
+        BTreeMap::from([
+            ("legal".to_string(), legal.to_string()),
+            ("hello".to_string(), "Hello".to_string()),
+        ])
+    };
+
+    let not_sent =
+        kept_locked_keys(&config, &autogen, &source("Terms"), "fr") == BTreeSet::from(["legal"]);
+    let kept = apply_locks(
+        &config,
+        &mut autogen,
+        &source("Terms"),
+        translated("Termes"),
+        "fr",
+    );
+    let kept_changed = apply_locks(
+        &config,
+        &mut autogen,
+        &source("New terms"),
+        translated("Nouveaux termes"),
+        "fr",
+    );
+    config.lock_policy = LockPolicy::ReleaseOnSourceChange;
+    let sent = kept_locked_keys(&config, &autogen, &source("New terms"), "fr").is_empty();
+    let released = apply_locks(
+        &config,
+        &mut autogen,
+        &source("New terms"),
+        translated("Nouveaux termes"),
+        "fr",
+    );
+
+    assert!(not_sent && sent);
+    assert_eq!(kept["legal"], "Conditions");
+    assert_eq!(kept["hello"], "Salut");
+    assert_eq!(kept_changed["legal"], "Conditions");
+    assert_eq!(released["legal"], "Nouveaux termes");
+    assert_eq!(
+        autogen.locked_source("en", "fr", "legal"),
+        Some("New terms")
+    );
+}
+
+#[test]
+fn test_locale_file_toml_nested() {
+    let source = r#"title = "Hello"
+
+[menu]
+open = "Open"
+items = ["One", "Two"]
+
+[menu.file]
+save = "Save"
+
+[[menu.file.recent]]
+name = "First"
+
+[[menu.file.recent]]
+name = "Second"
+"#;
+    let data = parse_locale_content(source, "toml").unwrap();
+    assert_eq!(
+        data.get("menu.file.recent.1.name").map(String::as_str),
+        Some("Second")
+    );
+
+    let locales = &TempDir::new("locales_toml_nested");
+    fs::write(locales.join("en.toml"), source).unwrap();
+    let config = Config::new().locales_directory(locales).build();
+
+    let written = write_locale_file(&config, &data, "fr");
+    let output = fs::read_to_string(locales.join("fr.toml")).unwrap();
+
+    assert_eq!(written, Ok(FileWrite::Written));
+    assert_eq!(
+        toml::from_str::<toml::Value>(&output).unwrap(),
+        toml::from_str::<toml::Value>(source).unwrap()
+    );
+}
+
+#[test]
+fn test_locale_file_numeric_keys() {
+    let source = r#"{"errors": {"0": "Unknown", "1": "Timeout"}, "steps": ["Open", "Save"]}"#;
+    let data = parse_locale_content(source, "json").unwrap();
+
+    let locales = &TempDir::new("locales_numeric_keys");
+    fs::write(locales.join("en.json"), source).unwrap();
+    let json_config = Config::new().locales_directory(locales).build();
+    let toml_config = Config::new()
+        .locales_directory(locales)
+        .output_format("toml")
+        .build();
+
+    write_locale_file(&json_config, &data, "fr").unwrap();
+    write_locale_file(&toml_config, &data, "de").unwrap();
+    let json = fs::read_to_string(locales.join("fr.json")).unwrap();
+    let toml = fs::read_to_string(locales.join("de.toml")).unwrap();
+
+    let expected = json!({
+        "errors": {"0": "Unknown", "1": "Timeout"},
+        "steps": ["Open", "Save"]
+    });
+    assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected);
+    assert_eq!(toml::from_str::<Value>(&toml).unwrap(), expected);
+}
+
+#[test]
+fn test_locale_file_list_of_objects() {
+    let source = r#"features:
+- title: Fast
+  desc: Translations at build time
+- title: Cached
+  desc: Only new keys are sent
+"#;
+    let data = parse_locale_content(source, "yaml").unwrap();
+    assert_eq!(
+        data.get("features.1.title").map(String::as_str),
+        Some("Cached")
+    );
+
+    let locales = &TempDir::new("locales_list_of_objects");
+    fs::write(locales.join("en.yml"), source).unwrap();
+    let yaml_config = Config::new().locales_directory(locales).build();
+    let json_config = Config::new()
+        .locales_directory(locales)
+        .output_format("json")
+        .build();
+
+    write_locale_file(&yaml_config, &data, "fr").unwrap();
+    write_locale_file(&json_config, &data, "de").unwrap();
+    let yaml = fs::read_to_string(locales.join("fr.yml")).unwrap();
+    let json = fs::read_to_string(locales.join("de.json")).unwrap();
+
+    let expected = json!({
+        "features": [
+            {"title": "Fast", "desc": "Translations at build time"},
+            {"title": "Cached", "desc": "Only new keys are sent"}
+        ]
+    });
+    assert_eq!(serde_yaml::from_str::<Value>(&yaml).unwrap(), expected);
+    assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected);
+}
+
+#[test]
+fn test_decode_locale_content() {
+    let utf16le: Vec<u8> = "hello: Hello"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let utf16be: Vec<u8> = [0xFE, 0xFF]
+        .into_iter()
+        .chain("hello: Hello".encode_utf16().flat_map(u16::to_be_bytes))
+        .collect();
+
+    assert_eq!(
+        decode_locale_content(b"\xEF\xBB\xBFhello: Hello"),
+        Ok(("hello: Hello".to_string(), Some("UTF-8 with BOM")))
+    );
+    assert_eq!(
+        decode_locale_content(&utf16le),
+        Ok(("hello: Hello".to_string(), Some("UTF-16LE")))
+    );
+    assert_eq!(
+        decode_locale_content(&utf16be),
+        Ok(("hello: Hello".to_string(), Some("UTF-16BE")))
+    );
+    assert_eq!(
+        decode_locale_content("hello: Héllo".as_bytes()),
+        Ok(("hello: Héllo".to_string(), None))
+    );
+    assert!(
+        decode_locale_content(b"hello: \xE9")
+            .unwrap_err()
+            .contains("save it as UTF-8")
+    );
+}
+
+#[test]
+fn test_locale_file_value_and_parent() {
+    let data: BTreeMap<String, String> = [
+        ("menu", "Menu"),
+        ("menu.file", "File"),
+        ("menu.file.open", "Open"),
+        ("title", "Hello"),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+
+    let locales = &TempDir::new("locales_value_and_parent");
+    fs::write(locales.join("en.yml"), "title: Hello\n").unwrap();
+    let config = Config::new().locales_directory(locales).build();
+
+    let written = write_locale_file(&config, &data, "fr");
+    let output = fs::read_to_string(locales.join("fr.yml")).unwrap_or_default();
+
+    assert_eq!(written, Ok(FileWrite::Written));
+    assert_eq!(parse_locale_content(&output, "yml"), Ok(data));
+}
@@ -0,0 +1,183 @@
+//Placeholders protection
+//Placeholders are swapped for opaque tokens before sending to the provider so they are not
+//translated, then swapped back. Providers may move the tokens around but every token has to
+//come back exactly once.
+//
+//Recognized placeholders:
+// - `%{name}` rust-i18n, with `Config::protect_placeholders`
+// - `{0}`, `{1}` positional, with `Config::protect_placeholders`
+// - whitespace between two tags, with `Config::preserve_html_whitespace`
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"%\{[^{}]+\}|\{\d+\}").unwrap());
+
+static HTML_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"%\{[^{}]+\}|\{\d+\}|>(\s+)<").unwrap());
+
+static HTML_WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r">(\s+)<").unwrap());
+
+static TOKEN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"__PH(\d+)__").unwrap());
+
+/// Value with its placeholders swapped for tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Protected {
+    /// Text sent to the provider
+    pub text: String,
+    /// Placeholders in order of appearance, the index is the token id
+    pub placeholders: Vec<String>,
+}
+
+/// Swap the placeholders of the value for `__PH{index}__` tokens
+pub fn protect(value: &str) -> Protected {
+    let mut placeholders = Vec::new();
+
+    let text = PLACEHOLDER
+        .replace_all(value, |caps: &regex::Captures| {
+            placeholders.push(caps[0].to_string());
+            format!("__PH{}__", placeholders.len() - 1)
+        })
+        .into_owned();
+
+    Protected { text, placeholders }
+}
+
+/// Same as [`protect`], also swaps the whitespace-only text between two tags for a token
+///
+/// eg: the space of `<b>Hello</b> <i>World</i>`, providers drop it as insignificant in html
+pub fn protect_html(value: &str) -> Protected {
+    protect_tags(value, &HTML_PLACEHOLDER)
+}
+
+/// Only swap the whitespace-only text between two tags for a token, see [`protect_html`]
+pub fn protect_html_whitespace(value: &str) -> Protected {
+    protect_tags(value, &HTML_WHITESPACE)
+}
+
+fn protect_tags(value: &str, regex: &Regex) -> Protected {
+    let mut placeholders = Vec::new();
+
+    let text = regex
+        .replace_all(value, |caps: &regex::Captures| match caps.get(1) {
+            Some(whitespace) => {
+                placeholders.push(whitespace.as_str().to_string());
+                format!(">__PH{}__<", placeholders.len() - 1)
+            }
+            None => {
+                placeholders.push(caps[0].to_string());
+                format!("__PH{}__", placeholders.len() - 1)
+            }
+        })
+        .into_owned();
+
+    Protected { text, placeholders }
+}
+
+/// Swap the tokens of the translation back for the placeholders
+///
+/// Errors if a token was dropped, duplicated or made up by the provider,
+/// the restored text is returned either way.
+pub fn restore(translated: &str, placeholders: &[String]) -> (String, Result<(), String>) {
+    if placeholders.is_empty() && !TOKEN.is_match(translated) {
+        return (translated.to_string(), Ok(()));
+    }
+
+    let mut seen = vec![0; placeholders.len()];
+    let mut unknown = Vec::new();
+
+    let restored = TOKEN
+        .replace_all(translated, |caps: &regex::Captures| {
+            let placeholder = caps[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| Some((idx, placeholders.get(idx)?)));
+
+            match placeholder {
+                Some((idx, placeholder)) => {
+                    seen[idx] += 1;
+                    placeholder.to_string()
+                }
+                None => {
+                    unknown.push(caps[0].to_string());
+                    caps[0].to_string()
+                }
+            }
+        })
+        .into_owned();
+
+    let changed: Vec<&str> = placeholders
+        .iter()
+        .zip(seen)
+        .filter(|(_, count)| *count != 1)
+        .map(|(placeholder, _)| placeholder.as_str())
+        .collect();
+
+    let check = if changed.is_empty() && unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Placeholders not kept exactly once: {}",
+            changed
+                .into_iter()
+                .chain(unknown.iter().map(|u| u.as_str()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    };
+
+    (restored, check)
+}
+
+#[test]
+fn test_protect_restore_reordered() {
+    let protected = protect("Hello %{name}, you have {0} new {1}");
+
+    assert_eq!(
+        protected.text,
+        "Hello __PH0__, you have __PH1__ new __PH2__"
+    );
+    assert_eq!(protected.placeholders, ["%{name}", "{0}", "{1}"]);
+
+    let (restored, check) = restore(
+        "__PH0__, vous avez __PH2__ __PH1__ nouveaux",
+        &protected.placeholders,
+    );
+    assert_eq!(restored, "%{name}, vous avez {1} {0} nouveaux");
+    assert_eq!(check, Ok(()));
+}
+
+#[test]
+fn test_restore_dropped_or_duplicated() {
+    let protected = protect("{0} of {1}");
+
+    let (restored, check) = restore("__PH0__ de", &protected.placeholders);
+    assert_eq!(restored, "{0} de");
+    assert!(check.unwrap_err().contains("{1}"));
+
+    let (_, check) = restore("__PH0__ __PH0__ de __PH1__", &protected.placeholders);
+    assert!(check.unwrap_err().contains("{0}"));
+
+    let (_, check) = restore("__PH0__ de __PH1__ __PH7__", &protected.placeholders);
+    assert!(check.unwrap_err().contains("__PH7__"));
+}
+
+#[test]
+fn test_protect_html_whitespace() {
+    let protected = protect_html("<b>Hello</b> <i>%{name}</i>\n<br/>");
+
+    assert_eq!(
+        protected.text,
+        "<b>Hello</b>__PH0__<i>__PH1__</i>__PH2__<br/>"
+    );
+    assert_eq!(protected.placeholders, [" ", "%{name}", "\n"]);
+
+    let (restored, check) = restore(
+        "<b>Bonjour</b>__PH0__<i>__PH1__</i>__PH2__<br/>",
+        &protected.placeholders,
+    );
+    assert_eq!(restored, "<b>Bonjour</b> <i>%{name}</i>\n<br/>");
+    assert_eq!(check, Ok(()));
+    assert_eq!(protect("<b>Hello</b> <i>World</i>").placeholders.len(), 0);
+}
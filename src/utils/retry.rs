@@ -0,0 +1,127 @@
+//Retries of the provider requests, see `Config::retry_policy`
+//A failed request is sent again after the delay given by the policy, until the policy gives up.
+//The jitter spreads the retries of concurrent requests instead of sending them all at once.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+use crate::{config::RetryPolicy, error::TranslationError, utils::logging::warn};
+
+/// Run `send` until it succeeds or the policy gives up, the last error is returned
+pub fn with_retry<T>(
+    policy: &RetryPolicy,
+    mut send: impl FnMut() -> Result<T, TranslationError>,
+) -> Result<T, TranslationError> {
+    let mut attempt = 0;
+    loop {
+        let error = match send() {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        attempt += 1;
+        let Some(delay) = delay(policy, attempt, &error) else {
+            return Err(error);
+        };
+
+        warn!(attempt; "Request failed, retrying in {delay:?}: {error}");
+        thread::sleep(delay);
+    }
+}
+
+/// Delay before the retry `attempt`, from 1, `None` to give up
+fn delay(policy: &RetryPolicy, attempt: u32, error: &TranslationError) -> Option<Duration> {
+    match policy {
+        RetryPolicy::Never => None,
+        RetryPolicy::ExponentialJitter {
+            base,
+            max,
+            attempts,
+        } => {
+            if attempt > *attempts || !is_transient(error) {
+                return None;
+            }
+            let ceiling = base
+                .saturating_mul(2u32.saturating_pow(attempt - 1))
+                .min(*max);
+            Some(ceiling.mul_f64(jitter()))
+        }
+        RetryPolicy::Custom(policy) => (policy.0)(attempt, error),
+    }
+}
+
+/// Whether the request may succeed when sent again, a bad request or a wrong key will not
+fn is_transient(error: &TranslationError) -> bool {
+    match error {
+        TranslationError::Provider(e) => {
+            !(400..500).contains(&e.code) || [408, 429].contains(&e.code)
+        }
+        _ => true,
+    }
+}
+
+/// Random factor between 0 and 1
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[test]
+fn test_with_retry() {
+    use crate::error::ProviderError;
+
+    let unauthorized = || TranslationError::Provider(ProviderError::raw(403, "Wrong key"));
+    let unavailable = || TranslationError::Provider(ProviderError::raw(503, "Unavailable"));
+    let policy = RetryPolicy::custom(|attempt, error| match error {
+        TranslationError::Provider(e) if e.code == 403 => None,
+        _ => (attempt <= 3).then_some(Duration::ZERO),
+    });
+
+    let mut sent = 0;
+    let recovered = with_retry(&policy, || {
+        sent += 1;
+        if sent < 3 {
+            Err(unavailable())
+        } else {
+            Ok(sent)
+        }
+    });
+    let mut auth_sent = 0;
+    let auth = with_retry(&policy, || {
+        auth_sent += 1;
+        Err::<(), _>(unauthorized())
+    });
+    let mut never_sent = 0;
+    let never = with_retry(&RetryPolicy::Never, || {
+        never_sent += 1;
+        Err::<(), _>(unavailable())
+    });
+
+    assert_eq!(recovered, Ok(3));
+    assert_eq!((auth, auth_sent), (Err(unauthorized()), 1));
+    assert_eq!((never, never_sent), (Err(unavailable()), 1));
+}
+
+#[test]
+fn test_exponential_jitter() {
+    use crate::error::ProviderError;
+
+    let policy = RetryPolicy::ExponentialJitter {
+        base: Duration::from_millis(100),
+        max: Duration::from_millis(300),
+        attempts: 3,
+    };
+    let error = TranslationError::Provider(ProviderError::raw(429, "Too many requests"));
+
+    for attempt in 1..=3 {
+        let ceiling = Duration::from_millis([100, 200, 300][attempt as usize - 1]);
+        assert!(delay(&policy, attempt, &error).is_some_and(|d| d <= ceiling));
+    }
+    assert_eq!(delay(&policy, 4, &error), None);
+    let bad_request = TranslationError::Provider(ProviderError::raw(400, "Bad request"));
+    assert_eq!(delay(&policy, 1, &bad_request), None);
+    assert!(delay(&policy, 1, &"timed out".to_string().into()).is_some());
+}
@@ -0,0 +1,67 @@
+//JSON Schema validation of the generated files, see `Config::validate_schema`
+//The nested value written to the file is validated, whatever the output format.
+
+use std::{fs, path::Path};
+
+use jsonschema::Validator;
+use serde_json::Value;
+
+/// Read and compile the schema
+pub fn load(path: &Path) -> Result<Validator, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read the schema {}: {e}", path.display()))?;
+    let schema = serde_json::from_str::<Value>(&content)
+        .map_err(|e| format!("Invalid schema {}: {e}", path.display()))?;
+
+    jsonschema::validator_for(&schema)
+        .map_err(|e| format!("Invalid schema {}: {e}", path.display()))
+}
+
+/// Violations of the value, prefixed with the dotted key they are about
+pub fn violations(validator: &Validator, value: &Value) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .map(|error| {
+            let key = dotted_key(error.instance_path().as_str());
+            if key.is_empty() {
+                error.to_string()
+            } else {
+                format!("`{key}`: {error}")
+            }
+        })
+        .collect()
+}
+
+/// `/menu/items/0` -> `menu.items.0`
+fn dotted_key(pointer: &str) -> String {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[test]
+fn test_violations() {
+    let validator = jsonschema::validator_for(&serde_json::json!({
+        "type": "object",
+        "required": ["title"],
+        "properties": {
+            "cart": {
+                "type": "object",
+                "properties": {"items": {"type": "string", "pattern": "%\\{count\\}"}}
+            }
+        }
+    }))
+    .unwrap();
+
+    let valid = serde_json::json!({"title": "Panier", "cart": {"items": "%{count} articles"}});
+    assert!(violations(&validator, &valid).is_empty());
+
+    let invalid = serde_json::json!({"cart": {"items": "articles"}});
+    let errors = violations(&validator, &invalid);
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().any(|e| e.contains("title")));
+    assert!(errors.iter().any(|e| e.starts_with("`cart.items`: ")));
+}
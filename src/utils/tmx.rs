@@ -0,0 +1,123 @@
+//TMX 1.4 (Translation Memory eXchange)
+//The format CAT tools (OmegaT, Trados, memoQ...) read and write. One `<tu>` per source value
+//with a `<tuv>` for the source and one for every locale that has a translation.
+//Only plain text segments are supported, inline markup is kept as text.
+
+use std::{collections::BTreeMap, sync::LazyLock};
+
+use html_escape::{decode_html_entities, encode_double_quoted_attribute, encode_text};
+use regex::Regex;
+
+static TU: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<tu\b[^>]*>(.*?)</tu>").unwrap());
+static TUV: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)<tuv\b[^>]*\b(?:xml:)?lang="([^"]*)"[^>]*>.*?<seg>(.*?)</seg>"#).unwrap()
+});
+
+/// target locale -> source value -> translation
+pub type Memory = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Write the translations as a TMX document
+pub fn to_tmx(source_locale: &str, memory: &Memory) -> String {
+    //source value -> target locale -> translation
+    let mut units: BTreeMap<&str, BTreeMap<&str, &str>> = BTreeMap::new();
+    for (locale, translations) in memory {
+        for (source, translation) in translations {
+            units.entry(source).or_default().insert(locale, translation);
+        }
+    }
+
+    let source_lang = encode_double_quoted_attribute(source_locale);
+    let mut tmx =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tmx version=\"1.4\">\n");
+    tmx.push_str(&format!(
+        "  <header creationtool=\"rust-i18n-autotranslate\" creationtoolversion=\"{}\" segtype=\"sentence\" o-tmf=\"autogen\" adminlang=\"en\" srclang=\"{source_lang}\" datatype=\"plaintext\"/>\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    tmx.push_str("  <body>\n");
+
+    for (source, translations) in units {
+        tmx.push_str("    <tu>\n");
+        push_tuv(&mut tmx, source_locale, source);
+        for (locale, translation) in translations {
+            push_tuv(&mut tmx, locale, translation);
+        }
+        tmx.push_str("    </tu>\n");
+    }
+
+    tmx.push_str("  </body>\n</tmx>\n");
+    tmx
+}
+
+fn push_tuv(tmx: &mut String, lang: &str, text: &str) {
+    tmx.push_str(&format!(
+        "      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n",
+        encode_double_quoted_attribute(lang),
+        encode_text(text)
+    ));
+}
+
+/// Read the translations of a TMX document
+///
+/// `lang_of` maps the `xml:lang` of the document to a locale, eg: `fr-FR` to `fr`.
+/// Units without a segment in the source locale are ignored.
+pub fn from_tmx(
+    content: &str,
+    source_locale: &str,
+    lang_of: impl Fn(&str) -> String,
+) -> Result<Memory, String> {
+    if !content.contains("<tmx") {
+        return Err("Not a TMX document".to_string());
+    }
+
+    let mut memory = Memory::new();
+
+    for unit in TU.captures_iter(content) {
+        let variants: Vec<(String, String)> = TUV
+            .captures_iter(&unit[1])
+            .map(|tuv| (lang_of(&tuv[1]), decode_html_entities(&tuv[2]).to_string()))
+            .collect();
+
+        let Some((_, source)) = variants
+            .iter()
+            .find(|(lang, _)| lang.eq_ignore_ascii_case(source_locale))
+        else {
+            continue;
+        };
+
+        for (lang, translation) in &variants {
+            if !lang.eq_ignore_ascii_case(source_locale) && !translation.is_empty() {
+                memory
+                    .entry(lang.to_string())
+                    .or_default()
+                    .insert(source.to_string(), translation.to_string());
+            }
+        }
+    }
+
+    Ok(memory)
+}
+
+#[test]
+fn test_tmx_round_trip() {
+    let mut memory = Memory::new();
+    memory.entry("fr".to_string()).or_default().insert(
+        "Save & close".to_string(),
+        "Enregistrer & fermer".to_string(),
+    );
+    memory.entry("de".to_string()).or_default().insert(
+        "Save & close".to_string(),
+        "Speichern <b>und</b> schließen".to_string(),
+    );
+
+    let tmx = to_tmx("en", &memory);
+    assert!(tmx.contains("<tuv xml:lang=\"en\"><seg>Save &amp; close</seg></tuv>"));
+    assert_eq!(tmx.matches("<tu>").count(), 1);
+
+    assert_eq!(from_tmx(&tmx, "en", |lang| lang.to_string()), Ok(memory));
+
+    let regional = from_tmx(&tmx.replace("\"fr\"", "\"fr-FR\""), "en", |lang| {
+        lang.split('-').next().unwrap_or(lang).to_string()
+    });
+    assert_eq!(regional.map(|memory| memory.contains_key("fr")), Ok(true));
+    assert!(from_tmx("{}", "en", |lang| lang.to_string()).is_err());
+}
@@ -53,6 +53,9 @@ impl SyncRateLimiter {
             guard = self.0.lock().unwrap();
         }
 
+        //let the other threads take their permit while this one runs
+        drop(guard);
+
         // Execute the passed function after rate limiting
         f()
     }